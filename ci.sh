@@ -8,18 +8,30 @@ export DEFMT_LOG=trace
 
 find . -name '*.rs' -not -path '*target*' | xargs rustfmt --check  --skip-children --unstable-features --edition 2021
 
+RNFC_ALL='iso14443a,iso14443b,iso15693,felica,iso-dep,emulation'
+
+cargo build --release --manifest-path rnfc-traits/Cargo.toml --no-default-features --features ''
+cargo build --release --manifest-path rnfc-traits/Cargo.toml --features 'iso14443b,iso15693,felica,iso-dep,emulation,defmt'
+
+cargo build --release --manifest-path rnfc/Cargo.toml --no-default-features --features ''
+for tech in iso14443a iso14443b iso15693 felica iso-dep emulation; do
+    cargo build --release --manifest-path rnfc/Cargo.toml --no-default-features --features $tech
+done
 cargo build --release --manifest-path rnfc/Cargo.toml --features ''
-cargo build --release --manifest-path rnfc/Cargo.toml --features 'defmt'
-cargo build --release --manifest-path rnfc/Cargo.toml --features 'log'
-RUST_LOG=trace cargo test --release --manifest-path rnfc/Cargo.toml --features 'log'
+cargo build --release --manifest-path rnfc/Cargo.toml --features "defmt,$RNFC_ALL"
+cargo build --release --manifest-path rnfc/Cargo.toml --features "log,$RNFC_ALL"
+RUST_LOG=trace cargo test --release --manifest-path rnfc/Cargo.toml --features "log,$RNFC_ALL"
 
 cargo build --release --manifest-path rnfc-fm175xx/Cargo.toml --features ''
 cargo build --release --manifest-path rnfc-fm175xx/Cargo.toml --features 'defmt'
 cargo build --release --manifest-path rnfc-fm175xx/Cargo.toml --features 'log'
 
+cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --no-default-features --features ''
+cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --no-default-features --features 'iso14443b'
 cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --features ''
-cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --features 'defmt'
-cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --features 'log'
+cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --no-default-features --features 'emulation'
+cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --features 'defmt,iso14443b,emulation'
+cargo build --release --manifest-path rnfc-st25r39/Cargo.toml --features 'log,iso14443b,emulation'
 
 cargo build --release --manifest-path rnfc-acr122u/Cargo.toml --features ''
 
@@ -107,9 +107,31 @@ where
             ll::Frame::Anticoll { bits } => (&tx[..(bits + 7) / 8], false, 65536, (bits % 8) as u8, (bits % 8) as u8),
             ll::Frame::ReqA => (&[0x26][..], false, 16384, 7, 0),
             ll::Frame::WupA => (&[0x52][..], false, 16384, 7, 0),
-            ll::Frame::Standard { timeout_1fc } => (tx, true, timeout_1fc, 0, 0),
+            ll::Frame::Bits { bits, timeout_1fc } => (&tx[..(bits + 7) / 8], false, timeout_1fc, (bits % 8) as u8, 0),
+            ll::Frame::Standard { timeout_1fc, crc } | ll::Frame::Custom { timeout_1fc, crc, .. } => match crc {
+                ll::Crc::A => (tx, true, timeout_1fc, 0, 0),
+                ll::Crc::None => (tx, false, timeout_1fc, 0, 0),
+                ll::Crc::B => {
+                    warn!("CRC_B is not supported in ISO14443A mode");
+                    return Err(Error::Other);
+                }
+            },
+            ll::Frame::Type1 { .. } => {
+                warn!("Type 1 framing is not supported");
+                return Err(Error::Other);
+            }
         };
 
+        // Parity
+        let no_parity = matches!(
+            opts,
+            ll::Frame::Custom {
+                parity: ll::Parity::None,
+                ..
+            }
+        );
+        r.regs().manualrcv().modify(|w| w.set_paritydisable(no_parity));
+
         // Set CRC
         r.regs().txmode().modify(|w| w.set_crcen(crc));
         r.regs().rxmode().modify(|w| w.set_crcen(crc));
@@ -258,19 +280,21 @@ where
 
         if let ll::Frame::Anticoll { bits } = opts {
             let shift = bits / 8;
+            // The byte shared with the prefix is merged into even if nothing was received.
+            let partial_byte = bits % 8 != 0;
+            if rx.len() < shift + rx_pos.max(partial_byte as usize) {
+                warn!(
+                    "rx overflow! anticollision frame is {} bytes but buffer is only {}",
+                    shift + rx_pos,
+                    rx.len()
+                );
+                return Err(Error::Other);
+            }
             rx[rx_pos + shift..].fill(0);
             for i in (0..rx_pos).rev() {
                 rx[i + shift] = rx[i];
             }
-            rx[..shift].copy_from_slice(&tx[..shift]);
-            if bits % 8 != 0 {
-                let byte_part = tx[bits / 8];
-                let mask = 1u8 << (bits % 8) - 1;
-                rx[bits / 8] |= byte_part & mask;
-            }
-            for i in 0..(bits + 7) / 8 {
-                rx[i] |= tx[i];
-            }
+            ll::merge_anticoll_prefix(rx, tx, bits);
 
             // Collision at bit `i` means that bit is not valid, only `0..i-1` are.
             // substract 1 because collpos is 1-based, not 0-based (why??)
@@ -299,7 +323,26 @@ where
                 return Err(Error::Collision);
             }
             debug!("RX: {:02x}", Bytes(&rx[..rx_pos]));
-            Ok(rx_pos * 8)
+
+            // Number of valid bits in the last byte, 0 if it's complete.
+            let last_bits = match opts {
+                ll::Frame::Bits { .. } | ll::Frame::Custom { .. } => r.regs().control().read().rxbits() as usize,
+                _ => 0,
+            };
+            if last_bits != 0 && rx_pos != 0 {
+                Ok((rx_pos - 1) * 8 + last_bits)
+            } else {
+                Ok(rx_pos * 8)
+            }
         }
     }
+
+    async fn delay_1fc(&mut self, time_1fc: u32) {
+        Timer::after(Duration::from_micros(time_1fc as u64 * 1_000 / 13_560)).await;
+    }
+
+    /// Powers the chip down, like dropping the session. Start a new session to use it again.
+    fn field_off(&mut self) {
+        self.inner.off();
+    }
 }
@@ -1,5 +1,5 @@
-use embassy_time::{Duration, Timer};
 use embedded_hal::digital::InputPin;
+use embedded_hal_async::delay::DelayNs;
 use embedded_hal_async::digital::Wait;
 
 use crate::{Error, Interface, St25r39};
@@ -19,7 +19,7 @@ pub struct AatConfig {
     pub amp_weight: u8,
 }
 
-impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> St25r39<I, IrqPin, D> {
     pub async fn aat(&mut self, conf: AatConfig) -> Result<(), Error<I::Error>> {
         let mut a = conf.a_start;
         let mut b = conf.b_start;
@@ -65,7 +65,7 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         self.regs().ant_tune_a().write_value(b)?;
 
         // Wait for caps to settle.
-        Timer::after(Duration::from_millis(1)).await;
+        self.delay.delay_ms(1).await;
 
         info!("aa");
         let amp = self.measure_phase().await?;
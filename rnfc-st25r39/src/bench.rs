@@ -0,0 +1,80 @@
+//! Throughput measurement, for choosing bitrates and frame sizes or checking the effect of
+//! latency settings such as [`Timings`](crate::iso14443a::Timings) and [`AgcResetStrategy`](crate::AgcResetStrategy).
+
+use core::time::Duration;
+
+use embassy_time::Instant;
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use rnfc_traits::iso14443a_ll::{self as ll, Reader as _};
+
+use crate::iso14443a::{Error, Iso14443a};
+use crate::Interface;
+
+/// Result of [`Iso14443a::bench_transceive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Throughput {
+    pub frames: u32,
+    /// Bytes sent, without CRC.
+    pub tx_bytes: usize,
+    /// Bytes received, without CRC.
+    pub rx_bytes: usize,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    pub fn frames_per_sec(&self) -> u32 {
+        per_sec(self.frames as u64, self.elapsed)
+    }
+
+    /// Bytes per second in both directions.
+    pub fn bytes_per_sec(&self) -> u32 {
+        per_sec((self.tx_bytes + self.rx_bytes) as u64, self.elapsed)
+    }
+}
+
+fn per_sec(n: u64, elapsed: Duration) -> u32 {
+    match elapsed.as_micros() {
+        0 => 0,
+        us => (n as u128 * 1_000_000 / us) as u32,
+    }
+}
+
+impl<'d, I: Interface + 'd, IrqPin: InputPin + Wait + 'd, D: DelayNs + 'd> Iso14443a<'d, I, IrqPin, D> {
+    /// Send `tx` to the selected card `frames` times back to back, and measure the throughput.
+    ///
+    /// The card must answer `tx` the same way each time without changing state, such as a
+    /// READ for Type 2 Tags. Only transceives are timed, with the session settings as they are,
+    /// so settings can be compared by changing them between runs on the same session. Stops
+    /// at the first error.
+    pub async fn bench_transceive(
+        &mut self,
+        tx: &[u8],
+        rx: &mut [u8],
+        opts: ll::Frame,
+        frames: u32,
+    ) -> Result<Throughput, Error<I::Error>> {
+        let mut rx_bytes = 0;
+        let start = Instant::now();
+        for _ in 0..frames {
+            let bits = self.transceive(tx, rx, opts).await?;
+            rx_bytes += bits.div_ceil(8);
+        }
+        let res = Throughput {
+            frames,
+            tx_bytes: tx.len() * frames as usize,
+            rx_bytes,
+            elapsed: start.elapsed().into(),
+        };
+        debug!(
+            "bench: {} frames in {} us, {} frames/s, {} bytes/s",
+            res.frames,
+            res.elapsed.as_micros(),
+            res.frames_per_sec(),
+            res.bytes_per_sec()
+        );
+        Ok(res)
+    }
+}
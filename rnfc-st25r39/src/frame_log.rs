@@ -0,0 +1,97 @@
+use core::cell::RefCell;
+
+#[cfg(feature = "embassy-time")]
+use embassy_time::Instant;
+use heapless::{Deque, Vec};
+use rnfc_traits::iso14443a_ll::Frame;
+
+/// Max amount of frame data stored per logged frame. Longer frames are truncated.
+pub const FRAME_LOG_DATA_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// Frame sent from us to the card.
+    Tx,
+    /// Frame received from the card.
+    Rx,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LoggedFrame {
+    pub direction: Direction,
+    /// Frame type the transceive was done with.
+    pub frame: Frame,
+    /// When it was recorded. Needs the `embassy-time` feature, for the clock.
+    #[cfg(feature = "embassy-time")]
+    pub timestamp: Instant,
+    /// Frame data, truncated to [`FRAME_LOG_DATA_LEN`] bytes.
+    pub data: Vec<u8, FRAME_LOG_DATA_LEN>,
+    /// Length of the frame before truncation.
+    pub len: usize,
+}
+
+/// Something frames can be recorded into, see [`St25r39::set_frame_log`](crate::St25r39::set_frame_log).
+pub trait FrameSink {
+    fn record(&self, direction: Direction, frame: Frame, data: &[u8]);
+}
+
+/// Fixed-capacity ring buffer holding the last `N` frames.
+///
+/// When full, the oldest frame is discarded. Memory usage is `N * size_of::<LoggedFrame>()`
+/// regardless of traffic.
+pub struct FrameLog<const N: usize> {
+    frames: RefCell<Deque<LoggedFrame, N>>,
+}
+
+impl<const N: usize> FrameLog<N> {
+    pub const fn new() -> Self {
+        Self {
+            frames: RefCell::new(Deque::new()),
+        }
+    }
+
+    /// Remove and return all logged frames, oldest first.
+    pub fn drain(&self) -> Drain<'_, N> {
+        Drain { log: self }
+    }
+}
+
+impl<const N: usize> Default for FrameLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FrameSink for FrameLog<N> {
+    fn record(&self, direction: Direction, frame: Frame, data: &[u8]) {
+        let n = data.len().min(FRAME_LOG_DATA_LEN);
+        let logged = LoggedFrame {
+            direction,
+            frame,
+            #[cfg(feature = "embassy-time")]
+            timestamp: Instant::now(),
+            data: Vec::from_slice(&data[..n]).unwrap(),
+            len: data.len(),
+        };
+
+        let mut frames = self.frames.borrow_mut();
+        if frames.is_full() {
+            frames.pop_front();
+        }
+        let _ = frames.push_back(logged);
+    }
+}
+
+pub struct Drain<'a, const N: usize> {
+    log: &'a FrameLog<N>,
+}
+
+impl<const N: usize> Iterator for Drain<'_, N> {
+    type Item = LoggedFrame;
+
+    fn next(&mut self) -> Option<LoggedFrame> {
+        self.log.frames.borrow_mut().pop_front()
+    }
+}
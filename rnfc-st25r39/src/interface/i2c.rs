@@ -1,6 +1,7 @@
 use embedded_hal::i2c::I2c;
 
 use super::Interface;
+use crate::{ConfigError, Error, PT_MEMORY_SIZE};
 
 pub struct I2cInterface<T>
 where
@@ -73,4 +74,14 @@ where
         buf[1..][..data.len()].copy_from_slice(data);
         self.i2c.write(self.address, &buf[..1 + data.len()])
     }
+
+    fn write_pt_memory(&mut self, data: &[u8]) -> Result<(), Error<Self::Error>> {
+        if data.len() > PT_MEMORY_SIZE {
+            return Err(Error::Config(ConfigError::PtMemoryTooLong));
+        }
+        let mut buf = [0u8; PT_MEMORY_SIZE + 1];
+        buf[0] = 0xA0;
+        buf[1..][..data.len()].copy_from_slice(data);
+        self.i2c.write(self.address, &buf[..1 + data.len()]).map_err(Error::Interface)
+    }
 }
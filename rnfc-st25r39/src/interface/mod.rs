@@ -6,6 +6,8 @@ use core::fmt::Debug;
 pub use i2c::I2cInterface;
 pub use spi::SpiInterface;
 
+use crate::{ConfigError, Error};
+
 pub trait Interface {
     type Error: Debug;
 
@@ -14,4 +16,20 @@ pub trait Interface {
     fn write_reg(&mut self, reg: u8, val: u8) -> Result<(), Self::Error>;
     fn read_fifo(&mut self, data: &mut [u8]) -> Result<(), Self::Error>;
     fn write_fifo(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+    /// Load the passive target memory, which holds the automatic anticollision responses.
+    ///
+    /// Fails with [`ConfigError::PtMemoryTooLong`] if `data` is longer than
+    /// [`PT_MEMORY_SIZE`](crate::PT_MEMORY_SIZE). The default fails with
+    /// [`ConfigError::Unsupported`], for interfaces only used as a reader.
+    fn write_pt_memory(&mut self, data: &[u8]) -> Result<(), Error<Self::Error>> {
+        let _ = data;
+        Err(Error::Config(ConfigError::Unsupported))
+    }
+
+    /// Max bytes to transfer to/from the FIFO in a single bus transaction.
+    ///
+    /// Longer transfers are split, yielding to the executor between chunks. `None` means no limit.
+    fn max_burst(&self) -> Option<usize> {
+        None
+    }
 }
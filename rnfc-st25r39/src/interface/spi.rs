@@ -1,14 +1,24 @@
 use embedded_hal::spi::{Operation, SpiDevice};
 
 use super::Interface;
+use crate::{ConfigError, Error, PT_MEMORY_SIZE};
 
 pub struct SpiInterface<T: SpiDevice> {
     spi: T,
+    max_burst: Option<usize>,
 }
 
 impl<T: SpiDevice> SpiInterface<T> {
     pub fn new(spi: T) -> Self {
-        Self { spi }
+        Self::new_with_max_burst(spi, None)
+    }
+
+    /// Create an interface that splits FIFO transfers in chunks of at most `max_burst` bytes.
+    ///
+    /// Useful on slow or shared buses, so that loading a full FIFO doesn't hold the bus
+    /// or starve other tasks for too long.
+    pub fn new_with_max_burst(spi: T, max_burst: Option<usize>) -> Self {
+        Self { spi, max_burst }
     }
 }
 
@@ -79,4 +89,17 @@ impl<T: SpiDevice> Interface for SpiInterface<T> {
     fn write_fifo(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         self.spi.transaction(&mut [Operation::Write(&[0x80]), Operation::Write(data)])
     }
+
+    fn write_pt_memory(&mut self, data: &[u8]) -> Result<(), Error<Self::Error>> {
+        if data.len() > PT_MEMORY_SIZE {
+            return Err(Error::Config(ConfigError::PtMemoryTooLong));
+        }
+        self.spi
+            .transaction(&mut [Operation::Write(&[0xA0]), Operation::Write(data)])
+            .map_err(Error::Interface)
+    }
+
+    fn max_burst(&self) -> Option<usize> {
+        self.max_burst
+    }
 }
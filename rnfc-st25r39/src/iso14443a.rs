@@ -1,7 +1,6 @@
 use core::fmt::Debug;
 
-use embassy_time::{with_timeout, Timer};
-use rnfc_traits::iso14443a_ll as ll;
+use embedded_hal_async::delay::DelayNs;
 
 use crate::fmt::Bytes;
 use crate::*;
@@ -17,28 +16,87 @@ pub enum Error<T> {
 
     Crc,
     Collision,
+    /// A collision in a frame that isn't part of anticollision, with [`CollisionPolicy::Transient`].
+    TransientCollision,
     Parity,
     ResponseTooShort,
     ResponseTooLong,
+    /// The response to REQA or WUPA wasn't exactly 16 bits, usually due to RF noise or a
+    /// non-compliant card.
+    UnexpectedAtqaLength,
 
     FifoOverflow,
     FifoUnderflow,
+
+    /// The field was cycled by the field watchdog, and another device's field prevented turning it back on.
+    FieldCollision,
+    /// The frame is longer than the chip can send: [`TX_MAX_LEN`], or [`FIFO_SIZE`] in listen
+    /// mode which doesn't stream. Contains the limit.
+    TxTooLong {
+        max_len: usize,
+    },
+    /// Our field isn't on, so the frame can't be sent. Usually the chip was turned off,
+    /// or the field wasn't turned on when using `St25r39::mode_on` and friends directly.
+    FieldNotOn,
+    Irq,
+    Config(ConfigError),
 }
 
 impl<T: Debug> ll::Error for Error<T> {
     fn kind(&self) -> ll::ErrorKind {
         match self {
             Self::Timeout => ll::ErrorKind::Timeout,
+            Self::TransientCollision => ll::ErrorKind::Corruption,
             _ => ll::ErrorKind::Other,
         }
     }
 }
 
+impl Health {
+    fn record<T>(&mut self, err: Option<&Error<T>>) {
+        fn inc(counter: &mut u32) {
+            *counter = counter.saturating_add(1);
+        }
+
+        inc(&mut self.frames);
+        let Some(err) = err else { return };
+        match err {
+            Error::Interface(_) | Error::Irq => inc(&mut self.interface),
+            Error::Timeout => inc(&mut self.timeouts),
+            Error::Framing | Error::FramingLastByteMissingParity => inc(&mut self.framing),
+            Error::Crc => inc(&mut self.crc),
+            Error::Collision | Error::TransientCollision => inc(&mut self.collisions),
+            Error::Parity => inc(&mut self.parity),
+            Error::FifoOverflow | Error::FifoUnderflow => inc(&mut self.fifo),
+            Error::FieldCollision | Error::FieldNotOn => inc(&mut self.field),
+            Error::ResponseTooShort
+            | Error::ResponseTooLong
+            | Error::UnexpectedAtqaLength
+            | Error::TxTooLong { .. }
+            | Error::Config(_) => inc(&mut self.other),
+        }
+    }
+}
+
 impl<T> From<crate::Error<T>> for Error<T> {
     fn from(val: crate::Error<T>) -> Self {
         match val {
             crate::Error::Interface(e) => Error::Interface(e),
             crate::Error::Timeout => Error::Timeout,
+            crate::Error::Irq => Error::Irq,
+            crate::Error::Config(e) => Error::Config(e),
+        }
+    }
+}
+
+impl<T> From<FieldOnError<T>> for Error<T> {
+    fn from(val: FieldOnError<T>) -> Self {
+        match val {
+            FieldOnError::Interface(e) => Error::Interface(e),
+            FieldOnError::Timeout => Error::Timeout,
+            FieldOnError::FieldCollision => Error::FieldCollision,
+            FieldOnError::Irq => Error::Irq,
+            FieldOnError::Config(e) => Error::Config(e),
         }
     }
 }
@@ -49,6 +107,8 @@ pub enum StartError<T> {
     Interface(T),
     FieldCollision,
     Timeout,
+    Irq,
+    Config(ConfigError),
 }
 
 impl<T> From<crate::Error<T>> for StartError<T> {
@@ -56,17 +116,511 @@ impl<T> From<crate::Error<T>> for StartError<T> {
         match val {
             crate::Error::Interface(e) => StartError::Interface(e),
             crate::Error::Timeout => StartError::Timeout,
+            crate::Error::Irq => StartError::Irq,
+            crate::Error::Config(e) => StartError::Config(e),
+        }
+    }
+}
+
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> St25r39<I, IrqPin, D> {
+    /// Read an anticollision response from the FIFO, and merge it with the `bits`-long prefix we sent.
+    ///
+    /// `fifo_bytes` is the amount of bytes in the FIFO. Returns the frame length in `rx` as
+    /// `(bytes, valid_bits)`. On collision, only bits before the collision are valid.
+    async fn read_anticoll_response(
+        &mut self,
+        tx: &[u8],
+        bits: usize,
+        rx: &mut [u8],
+        fifo_bytes: usize,
+    ) -> Result<(usize, usize), Error<I::Error>> {
+        let full_bytes = bits / 8;
+        // The byte shared with the prefix is merged into even if nothing was received.
+        let partial_byte = bits % 8 != 0;
+        if rx.len() < full_bytes + fifo_bytes.max(partial_byte as usize) {
+            return Err(Error::ResponseTooLong);
+        }
+        self.read_fifo(&mut rx[full_bytes..][..fifo_bytes]).await?;
+        ll::merge_anticoll_prefix(rx, tx, bits);
+
+        let valid_bits = if self.irq(Interrupt::Col) {
+            let coll = self.regs().collision_status().read()?;
+            coll.c_byte() as usize * 8 + coll.c_bit() as usize
+        } else {
+            (full_bytes + fifo_bytes) * 8
+        };
+        Ok((valid_bits.div_ceil(8), valid_bits))
+    }
+}
+
+/// Receiver demodulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Demodulator {
+    /// AM channel, with the peak detector.
+    AmPeak,
+    /// AM channel, with the mixer.
+    AmMixer,
+    /// PM channel.
+    Pm,
+}
+
+impl Demodulator {
+    /// A demodulator on the other channel, for [`Config::demod_retry`].
+    fn other_channel(self) -> Self {
+        match self {
+            Self::AmPeak | Self::AmMixer => Self::Pm,
+            Self::Pm => Self::AmPeak,
+        }
+    }
+}
+
+/// Receiver demodulation settings, see [`Iso14443a::set_demod_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DemodConfig {
+    pub demodulator: Demodulator,
+    /// Correlator configuration (`corr_conf1` register), for all frames except anticollision.
+    pub corr_conf1: u8,
+    /// Correlator configuration (`corr_conf1` register) for anticollision frames, which sets how
+    /// the correlator detects collisions. See the datasheet for the collision threshold fields.
+    ///
+    /// With antennas with a lot of phase noise, the default can report collisions with a single
+    /// card (spurious [`Error::Collision`] or collided UID bits): raise the tolerance for them.
+    /// The tradeoff is that collisions between cards with close responses, such as the same
+    /// model, can go undetected, so anticollision can select a UID mixing both cards' bits.
+    /// `corr_s6` is cleared regardless, it's needed to detect collisions at all.
+    pub anticoll_corr_conf1: u8,
+    /// Correlator configuration (`corr_conf2` register).
+    pub corr_conf2: u8,
+}
+
+impl Default for DemodConfig {
+    fn default() -> Self {
+        Self {
+            demodulator: Demodulator::AmPeak,
+            corr_conf1: 0x53,
+            anticoll_corr_conf1: 0x13,
+            corr_conf2: 0x00,
+        }
+    }
+}
+
+/// Receiver squelch after our frame, see [`Timings::squelch`].
+///
+/// The squelch keeps the receiver from triggering on the noise right after TX. Tags that answer
+/// very fast can start before it's released, losing the first bits (usually reported as
+/// [`Error::Framing`]): shorten or disable it for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Squelch {
+    /// Automatic squelch after TX, released once the noise settles.
+    Dynamic,
+    /// Squelch for a fixed time after TX, in 1/fc. Rounded down to the chip's 64/fc resolution,
+    /// max 255 * 64/fc.
+    Fixed { time_1fc: u32 },
+    /// No squelch.
+    Off,
+}
+
+/// What a collision in a frame other than anticollision means, see [`Iso14443a::set_collision_policy`].
+///
+/// With a single card in the field, these collisions come from noise (EMI, a detuned antenna).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CollisionPolicy {
+    /// Fail with [`Error::Collision`]. This is the default.
+    Strict,
+    /// Fail with [`Error::TransientCollision`], which higher layers such as ISO-DEP retry like
+    /// other corrupted frames.
+    Transient,
+    /// Receive the frame anyway, and only fail if its CRC is wrong. Frames without CRC
+    /// are handled as with [`Transient`](Self::Transient).
+    IgnoreIfCrcOk,
+}
+
+/// Frame timings, see [`Iso14443a::set_timings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timings {
+    /// Time after the end of our frame during which the receiver ignores the field, in 1/fc.
+    ///
+    /// Responses starting before this are missed, so it must stay below the card's frame
+    /// delay time. Rounded down to the chip's 64/fc resolution, max 255 * 64/fc.
+    pub mask_rx_1fc: u32,
+    /// Delay before sending each frame, so the card is ready to receive it after its previous response.
+    pub guard_time: Duration,
+    /// Receiver squelch after our frame.
+    pub squelch: Squelch,
+    /// Delay before REQA, WUPA, anticollision and SELECT frames, instead of `guard_time`.
+    ///
+    /// Some cloned or compatible cards need more time between anticollision iterations than
+    /// compliant ones, and fail enumeration without it.
+    pub anticoll_guard_time: Duration,
+    /// Min time to wait for the response to REQA, WUPA, anticollision and SELECT frames, in 1/fc.
+    ///
+    /// The ISO14443-3 response time is far below this, but slow cards answer later,
+    /// especially with their SAK.
+    pub anticoll_timeout_1fc: u32,
+}
+
+impl Timings {
+    /// Generic timings, with a relaxed guard time. This is the default.
+    pub const DEFAULT: Self = Self {
+        mask_rx_1fc: 0,
+        guard_time: Duration::from_millis(1),
+        squelch: Squelch::Dynamic,
+        anticoll_guard_time: Duration::from_millis(1),
+        anticoll_timeout_1fc: 65536,
+    };
+    /// Timings for MIFARE Classic.
+    ///
+    /// The cards answer at the minimum ISO14443-3 frame delay time (1172/fc), including
+    /// the 4-bit ACK/NAK which is easily lost in the noise right after our frame, so reception
+    /// is masked until just before it. The guard time is the ISO14443-3 minimum, to keep the
+    /// authentication handshake tight.
+    pub const MIFARE_CLASSIC: Self = Self {
+        mask_rx_1fc: 1024,
+        guard_time: Duration::from_micros(100),
+        squelch: Squelch::Dynamic,
+        anticoll_guard_time: Duration::from_micros(100),
+        anticoll_timeout_1fc: 65536,
+    };
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// TX modulation settings at 106 kbps, see [`Iso14443a::set_modulation`].
+///
+/// Some tags only answer with specific modulation settings. ST's recommended settings for them
+/// map to these register fields. EGT and SOF/EOF timings only exist in ISO14443B, see
+/// `St25r39::set_iso14443b_timings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Modulation {
+    /// Modulation pause length, `p_len` in the ISO14443A and NFC 106kb/s settings register.
+    /// 4 bits, 0 is the chip's default pause.
+    pub pause_len: u8,
+    /// `None` for OOK, `tr_am` cleared in the mode register. Otherwise AM with this index,
+    /// `am_mod` in the TX driver register: 4 bits, from 5% at 0 to 40% at 15. AM leaves a
+    /// residual carrier during the pauses.
+    pub am_index: Option<u8>,
+    /// Resistive AM, with this `md_res` (7 bits) in the resistive AM modulation register,
+    /// instead of the regulator-based AM. It shapes the modulation edges differently.
+    pub resistive_am: Option<u8>,
+}
+
+impl Modulation {
+    /// OOK with the chip's default pause. This is the default.
+    pub const DEFAULT: Self = Self {
+        pause_len: 0,
+        am_index: None,
+        resistive_am: None,
+    };
+}
+
+impl Default for Modulation {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Type B frames are sent with 10% ASK, whatever the session's Type A modulation.
+const MODULATION_B: Modulation = Modulation {
+    pause_len: 0,
+    am_index: Some(regs::TxDriverAmMod::_12PERCENT.0),
+    resistive_am: None,
+};
+
+/// Whether a frame is part of card activation, for [`Config::demod_retry`] and the anticollision timings.
+fn is_activation_frame(frame: ll::Frame, tx: &[u8]) -> bool {
+    match frame {
+        ll::Frame::ReqA | ll::Frame::WupA | ll::Frame::Anticoll { .. } => true,
+        // SELECT: SEL, NVB 0x70 and the UID part with its BCC.
+        ll::Frame::Standard { .. } => tx.len() == 7 && matches!(tx[0], 0x93 | 0x95 | 0x97) && tx[1] == 0x70,
+        ll::Frame::Bits { .. } | ll::Frame::Custom { .. } | ll::Frame::Type1 { .. } => false,
+    }
+}
+
+/// How the transceive core handles a frame, derived from its [`ll::Frame`].
+///
+/// Technology specific frame handling goes here, so the transceive core stays the same for all.
+/// What `transceive_inner` does after sending the frame.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RxMode {
+    Strict,
+    IgnoreParity,
+    /// Return once the frame is sent.
+    Skip,
+}
+
+struct FrameSpec {
+    cmd: Command,
+    /// Bits written to the FIFO, including the software CRC. `None` for frames the chip
+    /// generates by itself, such as REQA.
+    fifo_bits: Option<usize>,
+    /// The CRC of the operation mode, CRC_A or CRC_B, is added on TX and checked on RX by the chip.
+    hw_crc: bool,
+    /// Collisions are expected, and the response is read up to the first collided bit instead
+    /// of failing, as in bit-oriented anticollision.
+    bit_collisions: bool,
+    /// The response may end with an incomplete byte, without parity.
+    partial_last_byte: bool,
+    /// Parity bits are added on TX and checked on RX by the chip.
+    parity: bool,
+    /// Exact length the response must have, in bits.
+    expected_rx_bits: Option<usize>,
+    /// Frame waiting time.
+    fwt_ms: u32,
+    /// Operation mode the frame is sent in, which sets its framing: Topaz for T1T frames,
+    /// ISO14443B for CRC_B frames.
+    om: regs::ModeOm,
+}
+
+impl FrameSpec {
+    fn new(frame: ll::Frame, tx: &[u8]) -> Self {
+        let fwt_ms = |timeout_1fc: u32| timeout_1fc / 13560 + 1;
+        let raw = |cmd, fifo_bits| Self {
+            cmd,
+            fifo_bits,
+            hw_crc: false,
+            bit_collisions: false,
+            partial_last_byte: false,
+            parity: true,
+            expected_rx_bits: None,
+            fwt_ms: 5,
+            om: regs::ModeOm::INI_ISO14443A,
+        };
+        // REQA and WUPA are sent as 7-bit short frames by the chip, the ATQA is 16 bits.
+        let short_frame = |cmd| Self {
+            expected_rx_bits: Some(16),
+            ..raw(cmd, None)
+        };
+        match frame {
+            ll::Frame::ReqA => short_frame(Command::TransmitReqa),
+            ll::Frame::WupA => short_frame(Command::TransmitWupa),
+            ll::Frame::Anticoll { bits } => Self {
+                bit_collisions: true,
+                ..raw(Command::TransmitWithoutCrc, Some(bits))
+            },
+            ll::Frame::Bits { bits, timeout_1fc } => Self {
+                partial_last_byte: true,
+                fwt_ms: fwt_ms(timeout_1fc),
+                ..raw(Command::TransmitWithoutCrc, Some(bits))
+            },
+            ll::Frame::Standard { timeout_1fc, crc } => Self::with_crc(tx, crc, timeout_1fc),
+            ll::Frame::Custom {
+                timeout_1fc,
+                crc,
+                parity,
+            } => {
+                // Type B frames have no parity to disable.
+                let spec = Self::with_crc(tx, crc, timeout_1fc);
+                let parity = spec.parity && parity == ll::Parity::Hardware;
+                Self {
+                    parity,
+                    partial_last_byte: spec.parity && !parity,
+                    ..spec
+                }
+            }
+            // The CRC_B is part of `tx`, and is left in the response for the caller.
+            ll::Frame::Type1 { timeout_1fc } => Self {
+                om: regs::ModeOm::INI_TOPAZ,
+                ..Self::with_crc(tx, ll::Crc::None, timeout_1fc)
+            },
+        }
+    }
+
+    /// A byte-oriented frame with `crc`.
+    ///
+    /// CRC_B frames are sent in ISO14443B mode: the chip adds and checks the CRC_B, and frames
+    /// them with SOF, EOF and start and stop bits instead of parity.
+    fn with_crc(tx: &[u8], crc: ll::Crc, timeout_1fc: u32) -> Self {
+        let type_b = crc == ll::Crc::B;
+        let cmd = match crc {
+            ll::Crc::None => Command::TransmitWithoutCrc,
+            _ => Command::TransmitWithCrc,
+        };
+        Self {
+            cmd,
+            fifo_bits: Some(tx.len() * 8),
+            hw_crc: crc != ll::Crc::None,
+            bit_collisions: false,
+            partial_last_byte: false,
+            parity: !type_b,
+            expected_rx_bits: None,
+            fwt_ms: timeout_1fc / 13560 + 1,
+            om: match type_b {
+                true => regs::ModeOm::INI_ISO14443B,
+                false => regs::ModeOm::INI_ISO14443A,
+            },
+        }
+    }
+
+    /// Time on air at 106 kbps in 1/fc, for the TX timeout. Each bit takes 128/fc, plus a parity
+    /// bit per byte, or for Type B start and stop bits and up to 22 etu of SOF and EOF.
+    fn tx_1fc(&self) -> usize {
+        let bits = match self.fifo_bits {
+            None => 7,
+            Some(bits) => bits + self.hw_crc as usize * 16,
+        };
+        let framing = match self.om {
+            regs::ModeOm::INI_ISO14443B => bits / 8 * 2 + 22,
+            _ => bits / 8,
+        };
+        (bits + framing) * 128
+    }
+}
+
+/// Card presence detection from the field amplitude, see [`Iso14443a::set_card_detect`].
+///
+/// A card in the field loads the antenna, lowering the amplitude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CardDetect {
+    /// Amplitude with no card in the field, as returned by [`St25r39::measure_amplitude`].
+    pub empty_amplitude: u8,
+    /// A card is in the field if the amplitude is at least this much below `empty_amplitude`.
+    pub delta: u8,
+}
+
+/// Amplitude change reported by [`AmplitudeMonitor::next`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AmplitudeEvent {
+    /// Amplitude the change is relative to: the initial one, or the one from the previous event.
+    pub previous: u8,
+    pub amplitude: u8,
+}
+
+impl AmplitudeEvent {
+    /// Whether the amplitude went up, such as when a card leaves the field.
+    pub fn increased(&self) -> bool {
+        self.amplitude > self.previous
+    }
+}
+
+/// Periodic amplitude measurement between transactions, see [`Iso14443a::monitor_amplitude`].
+pub struct AmplitudeMonitor<'a, 'd, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> {
+    iso: &'a mut Iso14443a<'d, I, IrqPin, D>,
+    interval: Duration,
+    threshold_delta: u8,
+    reference: Option<u8>,
+}
+
+impl<'d, I: Interface + 'd, IrqPin: InputPin + Wait + 'd, D: DelayNs + 'd> AmplitudeMonitor<'_, 'd, I, IrqPin, D> {
+    /// Wait until the amplitude moves by at least the threshold from the reference.
+    ///
+    /// The reference is the first measurement, then the amplitude of the last event, so
+    /// each change is reported once. Cancelling this (dropping the future) is safe.
+    pub async fn next(&mut self) -> Result<AmplitudeEvent, crate::Error<I::Error>> {
+        let this = &mut *self.iso.inner;
+        let reference = match self.reference {
+            Some(reference) => reference,
+            None => {
+                let amplitude = this.measure_amplitude().await?;
+                self.reference = Some(amplitude);
+                amplitude
+            }
+        };
+        loop {
+            this.delay.delay_us(duration_us(self.interval)).await;
+            let amplitude = this.measure_amplitude().await?;
+            if amplitude.abs_diff(reference) >= self.threshold_delta {
+                debug!("amplitude monitor: {} -> {}", reference, amplitude);
+                self.reference = Some(amplitude);
+                return Ok(AmplitudeEvent {
+                    previous: reference,
+                    amplitude,
+                });
+            }
         }
     }
+
+    /// Reference amplitude for the next event, `None` before the first [`next`](Self::next).
+    pub fn reference(&self) -> Option<u8> {
+        self.reference
+    }
 }
 
+/// Margin added to the expected TX duration when waiting for TXE.
+const TXE_TIMEOUT_MARGIN: Duration = Duration::from_millis(2);
+/// How long [`Iso14443a::warm_up`] keeps the receiver open.
+const WARM_UP_RX_TIME: Duration = Duration::from_millis(1);
+/// Max time from the start of a response to its end. It should never hit, it's just for safety.
+const RX_TIMEOUT: Duration = Duration::from_millis(500);
+
 /// An ST25 chip enabled in Iso14443a mode.
-pub struct Iso14443a<'d, I: Interface, IrqPin: InputPin + Wait> {
-    inner: &'d mut St25r39<I, IrqPin>,
+///
+/// Turns the field off on drop, unless it was handed out by a [`Session`].
+pub struct Iso14443a<'d, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> {
+    inner: &'d mut St25r39<I, IrqPin, D>,
+    /// Whether the field goes off on drop. False for handles from a [`Session`].
+    owns_field: bool,
+    /// Whether the RX gain has been reset at least once in this session.
+    rx_gain_reset: bool,
+    agc_reset: AgcResetStrategy,
+    demod: DemodConfig,
+    /// See `last_response_delay`.
+    #[cfg(feature = "embassy-time")]
+    response_delay: Option<Duration>,
+    /// Whether the last transceive received a frame, see `last_rx_bitrate`.
+    rx_ok: bool,
+    guard_time: Duration,
+    squelch: Squelch,
+    anticoll_guard_time: Duration,
+    anticoll_timeout_1fc: u32,
+    card_detect: Option<CardDetect>,
+    modulation: Modulation,
+    partial_rx: bool,
+    collision_policy: CollisionPolicy,
+    /// The receiver was cleared with `reset_rx`, so the next transceive doesn't have to stop the chip.
+    rx_reset: bool,
 }
 
-impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
-    pub async fn start_iso14443a(&mut self) -> Result<Iso14443a<'_, I, IrqPin>, FieldOnError<I::Error>> {
+/// An ST25 chip with the field on, handing out [`Iso14443a`] handles.
+///
+/// The field stays on across handles, so cards keep their state between them, and is only
+/// turned off when the session is dropped. This allows flows such as select, transact, drop
+/// the handle to use the chip directly with [`inner_mut`](Self::inner_mut), then get a new
+/// handle to talk to the same card again.
+pub struct Session<'d, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> {
+    inner: &'d mut St25r39<I, IrqPin, D>,
+}
+
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> St25r39<I, IrqPin, D> {
+    pub async fn start_iso14443a(&mut self) -> Result<Iso14443a<'_, I, IrqPin, D>, FieldOnError<I::Error>> {
+        self.start_field().await?;
+        Ok(Iso14443a::new(self, true)?)
+    }
+
+    /// Turn the field on, for a [`Session`].
+    pub async fn start_session(&mut self) -> Result<Session<'_, I, IrqPin, D>, FieldOnError<I::Error>> {
+        self.start_field().await?;
+        Ok(Session { inner: self })
+    }
+
+    /// Write the [`Modulation`] registers other than `p_len`, which is written with each frame.
+    fn write_modulation(&mut self, modulation: &Modulation) -> Result<(), crate::Error<I::Error>> {
+        self.regs().mode().modify(|w| w.set_tr_am(modulation.am_index.is_some()))?;
+        self.regs().tx_driver().modify(|w| {
+            let am_index = modulation.am_index.unwrap_or(regs::TxDriverAmMod::_12PERCENT.0);
+            w.set_am_mod(regs::TxDriverAmMod(am_index & 0x0F));
+        })?;
+        self.regs()
+            .aux_mod()
+            .modify(|w| w.set_res_am(modulation.resistive_am.is_some()))?;
+        if let Some(md_res) = modulation.resistive_am {
+            self.regs().res_am_mod().modify(|w| w.set_md_res(md_res))?;
+        }
+        Ok(())
+    }
+
+    async fn start_field(&mut self) -> Result<(), FieldOnError<I::Error>> {
         self.mode_on().await?;
         match self.field_on().await {
             Ok(()) => {}
@@ -76,156 +630,523 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
             }
         }
 
-        // Field on guard time
-        Timer::after(Duration::from_millis(5)).await;
+        self.delay.delay_us(duration_us(FIELD_ON_GUARD_TIME)).await;
+        Ok(())
+    }
+}
 
-        Ok(Iso14443a { inner: self })
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> Session<'_, I, IrqPin, D> {
+    /// Get a handle to talk to cards, with the default settings. The field stays on when it's dropped.
+    pub fn iso14443a(&mut self) -> Result<Iso14443a<'_, I, IrqPin, D>, crate::Error<I::Error>> {
+        Iso14443a::new(self.inner, false)
+    }
+
+    pub fn inner(&self) -> &St25r39<I, IrqPin, D> {
+        self.inner
+    }
+
+    /// The chip, for operations that don't need the field off such as measurements.
+    ///
+    /// Turning the field or the chip off through this makes cards lose their state.
+    pub fn inner_mut(&mut self) -> &mut St25r39<I, IrqPin, D> {
+        self.inner
     }
 }
 
-impl<'d, I: Interface, IrqPin: InputPin + Wait> Drop for Iso14443a<'d, I, IrqPin> {
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> Drop for Session<'_, I, IrqPin, D> {
     fn drop(&mut self) {
         if self.inner.mode_off().is_err() {
+            warn!("Failed to set field off on Session drop");
+        }
+    }
+}
+
+impl<'d, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> Iso14443a<'d, I, IrqPin, D> {
+    fn new(inner: &'d mut St25r39<I, IrqPin, D>, owns_field: bool) -> Result<Self, crate::Error<I::Error>> {
+        let agc_reset = inner.config.agc_reset;
+        let mut this = Iso14443a {
+            inner,
+            owns_field,
+            rx_gain_reset: false,
+            agc_reset,
+            demod: DemodConfig::default(),
+            #[cfg(feature = "embassy-time")]
+            response_delay: None,
+            rx_ok: false,
+            guard_time: Timings::DEFAULT.guard_time,
+            squelch: Timings::DEFAULT.squelch,
+            anticoll_guard_time: Timings::DEFAULT.anticoll_guard_time,
+            anticoll_timeout_1fc: Timings::DEFAULT.anticoll_timeout_1fc,
+            card_detect: None,
+            modulation: Modulation::DEFAULT,
+            partial_rx: false,
+            collision_policy: CollisionPolicy::Strict,
+            rx_reset: false,
+        };
+        // Undo changes from a previous session.
+        this.set_demod_config(DemodConfig::default())?;
+        this.set_timings(Timings::DEFAULT)?;
+        this.set_modulation(Modulation::DEFAULT)?;
+        Ok(this)
+    }
+}
+
+impl<'d, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> Drop for Iso14443a<'d, I, IrqPin, D> {
+    fn drop(&mut self) {
+        if self.owns_field && self.inner.mode_off().is_err() {
             warn!("Failed to set field off on Iso14443a drop");
         }
     }
 }
 
-impl<'d, I: Interface + 'd, IrqPin: InputPin + Wait + 'd> ll::Reader for Iso14443a<'d, I, IrqPin> {
-    type Error = Error<I::Error>;
+/// Result of [`Iso14443a::transceive_ignore_parity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Received {
+    /// Received frame length in bits, as returned by [`ll::Reader::transceive`].
+    pub bits: usize,
+    /// Whether the card sent at least one byte with wrong parity.
+    pub parity_error: bool,
+    /// The response started but never ended, and `bits` is what was in the FIFO at the timeout.
+    ///
+    /// Only with [`Iso14443a::set_partial_rx`]. Nothing is checked on partial data: the CRC,
+    /// if any, is left in it.
+    pub truncated: bool,
+}
 
-    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], opts: ll::Frame) -> Result<usize, Self::Error> {
+impl<'d, I: Interface + 'd, IrqPin: InputPin + Wait + 'd, D: DelayNs + 'd> Iso14443a<'d, I, IrqPin, D> {
+    /// Change the demodulation settings for the rest of this session.
+    ///
+    /// Some tag and antenna combinations only work on one demodulator channel, or need
+    /// different correlator settings. ST recommends trying the alternatives with difficult tags.
+    pub fn set_demod_config(&mut self, demod: DemodConfig) -> Result<(), crate::Error<I::Error>> {
+        self.inner.regs().rx_conf1().modify(|w| {
+            w.set_ch_sel(demod.demodulator == Demodulator::Pm);
+        })?;
+        self.demod = demod;
+        Ok(())
+    }
+
+    /// Change the frame timings for the rest of this session.
+    ///
+    /// Use [`Timings::MIFARE_CLASSIC`] before a MIFARE Classic authentication: with the
+    /// default timings it fails intermittently.
+    pub fn set_timings(&mut self, timings: Timings) -> Result<(), crate::Error<I::Error>> {
+        self.inner.regs().timer_emv_control().modify(|w| {
+            w.set_mrt_step(regs::TimerEmvControlMrtStep::_64);
+        })?;
+        let mask_rx = (timings.mask_rx_1fc / 64).min(255) as u8;
+        self.inner.regs().mask_rx_timer().write_value(mask_rx)?;
+        let squelch_timer = match timings.squelch {
+            Squelch::Fixed { time_1fc } => (time_1fc / 64).min(255) as u8,
+            Squelch::Dynamic | Squelch::Off => 0,
+        };
+        self.inner.regs().squelch_timer().write_value(squelch_timer)?;
+        self.guard_time = timings.guard_time;
+        self.anticoll_guard_time = timings.anticoll_guard_time;
+        self.anticoll_timeout_1fc = timings.anticoll_timeout_1fc;
+        self.squelch = timings.squelch;
+        Ok(())
+    }
+
+    /// Change the TX modulation for the rest of this session.
+    ///
+    /// For tags that don't respond with the default modulation but work on other readers.
+    pub fn set_modulation(&mut self, modulation: Modulation) -> Result<(), crate::Error<I::Error>> {
+        self.modulation = modulation;
+        // Type B keeps its own modulation, this one is applied when the next Type A frame is sent.
+        if self.inner.frame_om == regs::ModeOm::INI_ISO14443B {
+            return Ok(());
+        }
+        self.inner.write_modulation(&modulation)
+    }
+
+    /// Change when the RX gain is reset for the rest of this session. Default: [`Config::agc_reset`].
+    pub fn set_agc_reset_strategy(&mut self, strategy: AgcResetStrategy) {
+        self.agc_reset = strategy;
+    }
+
+    /// Let the field and the receiver gain settle before the first frame of the session,
+    /// without sending anything.
+    ///
+    /// This measures the field amplitude, then opens the receiver for 1 ms with no frame sent, so
+    /// the AGC adjusts to the field's noise floor. Cards in the field don't notice. It only helps
+    /// with [`AgcResetStrategy::FirstFrameOnly`] or [`AgcResetStrategy::Never`], where the gain
+    /// reached here is kept for the first frame: the other strategies reset the gain before it.
+    /// Call it right after starting the session.
+    pub async fn warm_up(&mut self) -> Result<(), Error<I::Error>> {
         let this = &mut *self.inner;
+        let amplitude = this.measure_amplitude().await?;
+        trace!("warm up: amplitude {}", amplitude);
 
-        Timer::after(Duration::from_millis(1)).await;
-        debug!("TX: {:?} {:02x}", opts, Bytes(tx));
+        this.cmd(Command::Stop).await?;
+        this.cmd(Command::ResetRxgain).await?;
+        this.cmd(Command::UnmaskReceiveData).await?;
+        this.delay.delay_us(duration_us(WARM_UP_RX_TIME)).await;
+        this.cmd(Command::Stop).await?;
+        this.irqs = 0;
+        self.rx_gain_reset = true;
+        Ok(())
+    }
+
+    /// Change how collisions in frames other than anticollision are handled, for the rest of
+    /// this session. Default: [`CollisionPolicy::Strict`].
+    pub fn set_collision_policy(&mut self, policy: CollisionPolicy) {
+        self.collision_policy = policy;
+    }
+
+    /// Return the data received so far when a response starts but doesn't end in time,
+    /// instead of failing with [`Error::Timeout`]. Disabled by default.
+    ///
+    /// For debugging and for protocols with no clear end of frame. This only changes
+    /// [`transceive_ignore_parity`](Self::transceive_ignore_parity), which reports it with
+    /// [`Received::truncated`]: [`ll::Reader::transceive`] still fails with [`Error::Timeout`].
+    pub fn set_partial_rx(&mut self, enabled: bool) {
+        self.partial_rx = enabled;
+    }
+
+    /// Enable detecting whether the card is still in the field from the amplitude, or disable it with `None`.
+    ///
+    /// When enabled, [`card_in_field`](ll::Reader::card_in_field) measures the amplitude,
+    /// so protocols can report a removed card as such instead of retrying. Disabled by default.
+    pub fn set_card_detect(&mut self, card_detect: Option<CardDetect>) {
+        self.card_detect = card_detect;
+    }
+
+    /// Measure the amplitude every `interval` while the session is idle, reporting changes
+    /// of at least `threshold_delta`, such as a card leaving or metal detuning the antenna.
+    ///
+    /// The monitor borrows the session, so it can't run during a transceive: drop it to talk
+    /// to the card again. The field stays on, so cards keep their state.
+    pub fn monitor_amplitude(&mut self, interval: Duration, threshold_delta: u8) -> AmplitudeMonitor<'_, 'd, I, IrqPin, D> {
+        AmplitudeMonitor {
+            iso: self,
+            interval,
+            threshold_delta: threshold_delta.max(1),
+            reference: None,
+        }
+    }
+
+    /// Time between the end of our last frame and the start of the card's response.
+    ///
+    /// `None` if the last transceive got no response. The ST25R39 timers can't be read back,
+    /// so this is measured from when the driver sees the TXE and RXS interrupts. It includes
+    /// IRQ and SPI latency, which is roughly constant for a given setup: good for comparing
+    /// cards or spotting slow ones, not for checking the FDT to the carrier cycle. Needs the
+    /// `embassy-time` feature, for the clock.
+    #[cfg(feature = "embassy-time")]
+    pub fn last_response_delay(&self) -> Option<Duration> {
+        self.response_delay
+    }
+
+    /// Bitrate the card's last response was received at.
+    ///
+    /// `None` if the last transceive didn't receive a frame without framing, parity or CRC
+    /// errors. The ST25R39 doesn't detect the bitrate in reader mode, it only decodes frames at
+    /// the RX bitrate it's set to, so this is that rate when the last frame was received: a
+    /// card answering at another rate after PPS shows up as a failed transceive, not here.
+    pub fn last_rx_bitrate(&mut self) -> Result<Option<Bitrate>, Error<I::Error>> {
+        if !self.rx_ok {
+            return Ok(None);
+        }
+        let rate = self.inner.regs().bit_rate().read()?.rxrate();
+        Ok(Some(Bitrate::from_reg(rate)))
+    }
+
+    /// Like [`ll::Reader::transceive`], but don't fail on parity errors.
+    ///
+    /// Some non-compliant tags send wrong parity on specific bytes. With this, the frame is
+    /// received anyway and the parity error is reported in the result instead. Other errors
+    /// are still returned as usual. Usually combined with [`ll::Crc::None`], to fully check
+    /// the response yourself.
+    pub async fn transceive_ignore_parity(
+        &mut self,
+        tx: &[u8],
+        rx: &mut [u8],
+        opts: ll::Frame,
+    ) -> Result<Received, Error<I::Error>> {
+        self.transceive_inner(tx, rx, opts, RxMode::IgnoreParity).await
+    }
+
+    /// The [`ll::Reader::transceive`] behavior: truncated responses are timeouts.
+    async fn transceive_strict(&mut self, tx: &[u8], rx: &mut [u8], opts: ll::Frame) -> Result<usize, Error<I::Error>> {
+        match self.transceive_inner(tx, rx, opts, RxMode::Strict).await? {
+            res if res.truncated => Err(Error::Timeout),
+            res => Ok(res.bits),
+        }
+    }
+
+    /// [`transceive_frame`](Self::transceive_frame), counted in the [`Health`].
+    async fn transceive_inner(
+        &mut self,
+        tx: &[u8],
+        rx: &mut [u8],
+        opts: ll::Frame,
+        rx_mode: RxMode,
+    ) -> Result<Received, Error<I::Error>> {
+        let res = self.transceive_frame(tx, rx, opts, rx_mode).await;
+        self.inner.health.record(res.as_ref().err());
+        res
+    }
 
-        this.cmd(Command::Stop)?;
-        this.cmd(Command::ResetRxgain)?;
+    async fn transceive_frame(
+        &mut self,
+        tx: &[u8],
+        rx: &mut [u8],
+        opts: ll::Frame,
+        rx_mode: RxMode,
+    ) -> Result<Received, Error<I::Error>> {
+        #[cfg(feature = "embassy-time")]
+        {
+            self.response_delay = None;
+        }
+        self.rx_ok = false;
+        let this = &mut *self.inner;
+        this.error_flags = ErrorFlags::default();
 
-        let mut fwt_ms = 5;
-        let is_anticoll = matches!(opts, ll::Frame::Anticoll { .. });
+        #[cfg(feature = "embassy-time")]
+        if this.field_watchdog().await? {
+            // Turning the field back on resets the modulation.
+            this.write_modulation(&self.modulation)?;
+        }
+        if !this.regs().aux_display().read()?.tx_on() {
+            warn!("transceive with the field off");
+            return Err(Error::FieldNotOn);
+        }
+        let activation = is_activation_frame(opts, tx);
+        let mut frame = FrameSpec::new(opts, tx);
+        if activation {
+            frame.fwt_ms = frame.fwt_ms.max(self.anticoll_timeout_1fc / 13560 + 1);
+        }
+        if frame.fifo_bits.is_some_and(|bits| bits.div_ceil(8) > TX_MAX_LEN) {
+            warn!("TX: {} bytes, more than the max {}", tx.len(), TX_MAX_LEN);
+            return Err(Error::TxTooLong { max_len: TX_MAX_LEN });
+        }
+        let guard_time = match activation {
+            true => self.anticoll_guard_time,
+            false => self.guard_time,
+        };
+        this.delay.delay_us(duration_us(guard_time)).await;
+        debug!("TX: {:?} {:02x}", opts, Bytes(tx));
+        this.log_frame(Direction::Tx, opts, tx);
 
-        let (raw, cmd) = match opts {
-            ll::Frame::ReqA => (true, Command::TransmitReqa),
-            ll::Frame::WupA => (true, Command::TransmitWupa),
-            ll::Frame::Anticoll { bits } => {
-                this.regs().num_tx_bytes2().write_value((bits as u8).into())?;
-                this.regs().num_tx_bytes1().write_value((bits >> 8) as u8)?;
-                this.iface.write_fifo(&tx[..(bits + 7) / 8]).map_err(Error::Interface)?;
-                (true, Command::TransmitWithoutCrc)
+        if !core::mem::take(&mut self.rx_reset) {
+            this.cmd(Command::Stop).await?;
+        }
+        if frame.om != this.frame_om {
+            this.regs().mode().modify(|w| w.set_om(frame.om))?;
+            if frame.om == regs::ModeOm::INI_ISO14443B {
+                this.write_modulation(&MODULATION_B)?;
+                #[cfg(feature = "iso14443b")]
+                this.write_iso14443b_timings()?;
+            } else if this.frame_om == regs::ModeOm::INI_ISO14443B {
+                this.write_modulation(&self.modulation)?;
             }
-            ll::Frame::Standard { timeout_1fc, .. } => {
-                fwt_ms = timeout_1fc / 13560 + 1;
-                let bits = tx.len() * 8;
-                this.regs().num_tx_bytes2().write_value((bits as u8).into())?;
-                this.regs().num_tx_bytes1().write_value((bits >> 8) as u8)?;
-                this.iface.write_fifo(tx).map_err(Error::Interface)?;
-                (false, Command::TransmitWithCrc)
+            this.frame_om = frame.om;
+        }
+        let reset_rx_gain = match self.agc_reset {
+            AgcResetStrategy::Always => true,
+            AgcResetStrategy::FirstFrameOnly => !self.rx_gain_reset,
+            AgcResetStrategy::Never => false,
+            AgcResetStrategy::AnticollOnly => {
+                matches!(opts, ll::Frame::ReqA | ll::Frame::WupA | ll::Frame::Anticoll { .. })
             }
         };
+        if reset_rx_gain {
+            this.cmd(Command::ResetRxgain).await?;
+            self.rx_gain_reset = true;
+        }
+
+        let mut parts: [&[u8]; 2] = [&[], &[]];
+        if let Some(bits) = frame.fifo_bits {
+            this.regs().num_tx_bytes2().write_value((bits as u8).into())?;
+            this.regs().num_tx_bytes1().write_value((bits >> 8) as u8)?;
+            parts[0] = &tx[..(bits + 7) / 8];
+        }
+        // Frames larger than the FIFO are streamed, the rest is written during TX.
+        let tx_len = parts[0].len() + parts[1].len();
+        let (preload, chunk) = if tx_len > FIFO_SIZE {
+            let streaming = this.tx_streaming()?;
+            (streaming.preload.min(FIFO_SIZE), streaming.chunk)
+        } else {
+            (tx_len, tx_len)
+        };
+        let written = this.write_fifo_parts(&parts, 0, preload, usize::MAX).await?;
+
+        let demod = self.demod;
+        let sqm_dyn = self.squelch == Squelch::Dynamic;
         this.regs().corr_conf1().write(|w| {
-            w.0 = 0x13;
-            w.set_corr_s6(!is_anticoll);
+            if frame.bit_collisions {
+                w.0 = demod.anticoll_corr_conf1;
+                w.set_corr_s6(false);
+            } else {
+                w.0 = demod.corr_conf1;
+            }
         })?;
+        this.regs().corr_conf2().write_value(demod.corr_conf2.into())?;
 
         this.regs().iso14443a_nfc().write(|w| {
-            w.set_antcl(is_anticoll);
+            w.set_antcl(frame.bit_collisions);
+            w.set_p_len(self.modulation.pause_len);
+            w.set_no_tx_par(!frame.parity);
+            w.set_no_rx_par(!frame.parity);
         })?;
         this.regs().aux().write(|w| {
-            w.set_no_crc_rx(raw);
+            w.set_no_crc_rx(!frame.hw_crc);
         })?;
         this.regs().rx_conf2().write(|w| {
             // Disable Automatic Gain Control (AGC) for better detection of collisions if using Coherent Receiver
-            w.set_agc_en(!is_anticoll);
+            w.set_agc_en(!frame.bit_collisions);
             w.set_agc_m(true); // AGC operates during complete receive period
             w.set_agc6_3(true); // 0: AGC ratio 3
-            w.set_sqm_dyn(true); // Automatic squelch activation after end of TX
+            w.set_sqm_dyn(sqm_dyn); // Automatic squelch activation after end of TX
+            w.set_amd_sel(demod.demodulator == Demodulator::AmMixer);
         })?;
 
-        this.irqs = 0; // stop already clears all irqs
-        this.cmd(cmd)?;
+        this.irqs = 0; // stop (or reset_rx) already clears all irqs
+        this.cmd(frame.cmd).await?;
 
-        // Wait for tx ended
-        this.irq_wait(Interrupt::Txe).await?;
+        // Wait for tx ended. If TXE never fires (lost IRQ, FIFO misconfigured...) give up
+        // instead of waiting for the generic timeout.
+        let tx_1fc = frame.tx_1fc();
+        let txe_timeout = Duration::from_micros((tx_1fc / 13) as u64) + TXE_TIMEOUT_MARGIN;
+        if let Err(e) = this.irq_wait_txe_streaming(&parts, written, chunk, txe_timeout).await {
+            warn!("TXE did not fire after {} us", txe_timeout.as_micros());
+            this.cmd(Command::Stop).await?;
+            return Err(e.into());
+        }
+        #[cfg(feature = "embassy-time")]
+        let tx_end = Instant::now();
+
+        if rx_mode == RxMode::Skip {
+            this.cmd(Command::Stop).await?;
+            return Ok(Received {
+                bits: 0,
+                parity_error: false,
+                truncated: false,
+            });
+        }
 
-        // Wait for RX started
-        this.irq_wait_timeout(Interrupt::Rxs, Duration::from_millis(fwt_ms as _))
+        // Wait for RX started. A fast response can't be missed here: interrupts latch in the
+        // chip until read, each read is accumulated into `irqs`, and nothing clears `irqs`
+        // between TXE and RX end. If RXS (or even RXE) latched while waiting for TXE, it was
+        // read together with TXE and the wait returns right away.
+        if this.irq(Interrupt::Rxs) {
+            trace!("RXS already pending at TXE");
+        }
+        this.irq_wait_critical(Interrupt::Rxs, Duration::from_millis(frame.fwt_ms as _))
             .await?;
+        #[cfg(feature = "embassy-time")]
+        {
+            self.response_delay = Some(tx_end.elapsed().into());
+        }
 
         // Wait for rx ended or error
-        // The timeout should never hit, it's just for safety.
-        let res = with_timeout(Duration::from_millis(500), async {
-            loop {
-                if this.irq(Interrupt::Err1) {
-                    return Err(Error::Framing);
-                }
-                if this.irq(Interrupt::Par) {
-                    return Err(Error::Parity);
-                }
-                if this.irq(Interrupt::Crc) {
-                    return Err(Error::Crc);
+        let mut parity_error = false;
+        let collision_policy = self.collision_policy;
+        let mut spins = this.busy_poll_spins();
+        let mut timeout = PollTimeout::new(RX_TIMEOUT);
+        let res = loop {
+            if this.irq(Interrupt::Err1) {
+                break Err(Error::Framing);
+            }
+            if this.irq(Interrupt::Par) {
+                if rx_mode != RxMode::IgnoreParity {
+                    break Err(Error::Parity);
                 }
-                if !is_anticoll && this.irq(Interrupt::Col) {
-                    return Err(Error::Collision);
+                parity_error = true;
+            }
+            if this.irq(Interrupt::Crc) {
+                break Err(Error::Crc);
+            }
+            if !frame.bit_collisions && this.irq(Interrupt::Col) {
+                let has_crc = frame.hw_crc;
+                match collision_policy {
+                    CollisionPolicy::Strict => break Err(Error::Collision),
+                    CollisionPolicy::IgnoreIfCrcOk if has_crc => {}
+                    _ => break Err(Error::TransientCollision),
                 }
+            }
 
-                if this.irq(Interrupt::Rxe) {
-                    break;
-                }
+            if this.irq(Interrupt::Rxe) {
+                break Ok(());
+            }
+            if timeout.expired() {
+                break Err(Error::Timeout);
+            }
 
-                yield_now().await;
-                this.irq_update()?;
+            if let Err(e) = this.irq_poll(&mut spins, &mut timeout).await {
+                break Err(e.into());
             }
-            Ok(())
-        })
-        .await;
+        };
+        this.error_flags = ErrorFlags::from_irqs(this.irqs);
 
         match res {
-            Ok(Ok(())) => {}
-            Ok(Err(e)) => return Err(e),
-            Err(_) => return Err(Error::Timeout),
+            Ok(()) => {}
+            Err(Error::Timeout) if self.partial_rx => {
+                let stat = this.fifo_status()?;
+                let rx_bytes = stat.byte_count.min(rx.len());
+                this.read_fifo(&mut rx[..rx_bytes]).await?;
+                this.cmd(Command::Stop).await?;
+                debug!("RX (truncated): {:02x}", Bytes(&rx[..rx_bytes]));
+                let bits = match stat.last_byte_bits {
+                    n if n != 0 && rx_bytes == stat.byte_count => (rx_bytes - 1) * 8 + n as usize,
+                    _ => rx_bytes * 8,
+                };
+                return Ok(Received {
+                    bits,
+                    parity_error,
+                    truncated: true,
+                });
+            }
+            Err(e) => return Err(e),
         }
 
         // If we're here, RX ended without error.
+        self.rx_ok = true;
 
-        let stat = this.regs().fifo_status2().read()?;
-        if stat.fifo_ovr() {
+        let stat = this.fifo_status()?;
+        if !this.fifo_count_valid(&stat)? {
+            warn!("RX: inconsistent FIFO byte count {}", stat.byte_count);
+            return Err(Error::Framing);
+        }
+        if stat.overflow {
             return Err(Error::FifoOverflow);
         }
-        if stat.fifo_unf() {
+        if stat.underflow {
             return Err(Error::FifoUnderflow);
         }
-        if stat.np_lb() {
+        if let Some(expected) = frame.expected_rx_bits {
+            let bits = match stat.last_byte_bits {
+                0 => stat.byte_count * 8,
+                n => stat.byte_count.saturating_sub(1) * 8 + n as usize,
+            };
+            if bits != expected {
+                debug!("RX: got {} bits, expected {}", bits, expected);
+                return Err(Error::UnexpectedAtqaLength);
+            }
+        }
+        // Incomplete last bytes have no parity, nor do any bytes without hardware parity.
+        if stat.missing_parity && frame.parity && !(frame.partial_last_byte && stat.last_byte_bits != 0) {
             return Err(Error::FramingLastByteMissingParity);
         }
 
-        let mut rx_bytes = this.regs().fifo_status1().read()? as usize;
-        rx_bytes |= (stat.fifo_b() as usize) << 8;
+        let mut rx_bytes = stat.byte_count;
 
-        if let ll::Frame::Anticoll { bits } = opts {
-            let full_bytes = bits / 8;
-            rx[..full_bytes].copy_from_slice(&tx[..full_bytes]);
-            this.iface
-                .read_fifo(&mut rx[full_bytes..][..rx_bytes])
-                .map_err(Error::Interface)?;
-            if bits % 8 != 0 {
-                let half_byte = tx[full_bytes] & (1 << bits) - 1;
-                rx[full_bytes] |= half_byte
-            }
+        if frame.bit_collisions {
+            let bits = frame.fifo_bits.unwrap_or(0);
+            let (rx_bytes, rx_bits) = this.read_anticoll_response(tx, bits, rx, rx_bytes).await?;
+            debug!("RX: {:02x} bits: {}", Bytes(&rx[..rx_bytes]), rx_bits);
+            this.log_frame(Direction::Rx, opts, &rx[..rx_bytes]);
 
-            let rx_bits = if this.irq(Interrupt::Col) {
-                let coll = this.regs().collision_status().read()?;
-                coll.c_byte() as usize * 8 + coll.c_bit() as usize
-            } else {
-                full_bytes * 8 + rx_bytes * 8
-            };
-            debug!("RX: {:02x} bits: {}", Bytes(rx), rx_bits);
-
-            Ok(rx_bits)
+            Ok(Received {
+                bits: rx_bits,
+                parity_error,
+                truncated: false,
+            })
         } else {
             // Remove received CRC
-            if !raw {
+            if frame.hw_crc {
                 if rx_bytes < 2 {
                     return Err(Error::ResponseTooShort);
                 }
@@ -236,9 +1157,259 @@ impl<'d, I: Interface + 'd, IrqPin: InputPin + Wait + 'd> ll::Reader for Iso1444
                 return Err(Error::ResponseTooLong);
             }
 
-            this.iface.read_fifo(&mut rx[..rx_bytes]).map_err(Error::Interface)?;
+            this.read_fifo(&mut rx[..rx_bytes]).await?;
             debug!("RX: {:02x}", Bytes(&rx[..rx_bytes]));
-            Ok(rx_bytes * 8)
+            this.log_frame(Direction::Rx, opts, &rx[..rx_bytes]);
+            let bits = match stat.last_byte_bits {
+                n if frame.partial_last_byte && n != 0 && rx_bytes != 0 => (rx_bytes - 1) * 8 + n as usize,
+                _ => rx_bytes * 8,
+            };
+            Ok(Received {
+                bits,
+                parity_error,
+                truncated: false,
+            })
+        }
+    }
+}
+
+impl<'d, I: Interface + 'd, IrqPin: InputPin + Wait + 'd, D: DelayNs + 'd> ll::Reader for Iso14443a<'d, I, IrqPin, D> {
+    type Error = Error<I::Error>;
+
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], opts: ll::Frame) -> Result<usize, Self::Error> {
+        match self.transceive_strict(tx, rx, opts).await {
+            Err(Error::Timeout) if self.inner.config.demod_retry && is_activation_frame(opts, tx) => {
+                let demod = self.demod;
+                let retry = DemodConfig {
+                    demodulator: demod.demodulator.other_channel(),
+                    ..demod
+                };
+                debug!("no response, retrying with {:?}", retry.demodulator);
+                self.set_demod_config(retry)?;
+                match self.transceive_strict(tx, rx, opts).await {
+                    Ok(bits) => Ok(bits),
+                    Err(e) => {
+                        self.set_demod_config(demod)?;
+                        Err(e)
+                    }
+                }
+            }
+            res => res,
+        }
+    }
+
+    /// Returns once TXE fires, then stops the receiver so a response, if any, is dropped.
+    async fn transmit_only(&mut self, tx: &[u8], crc: bool) -> Result<(), Self::Error> {
+        let opts = ll::Frame::Standard {
+            timeout_1fc: ll::TRANSMIT_ONLY_TIMEOUT_1FC,
+            crc: if crc { ll::Crc::A } else { ll::Crc::None },
+        };
+        self.transceive_inner(tx, &mut [], opts, RxMode::Skip).await?;
+        Ok(())
+    }
+
+    async fn delay_1fc(&mut self, time_1fc: u32) {
+        let us = time_1fc as u64 * 1_000 / 13_560;
+        self.inner.delay.delay_us(us as u32).await;
+    }
+
+    /// Stop the no-response timer and clear the FIFO and interrupts, leaving the chip otherwise
+    /// as the last transceive left it. The next transceive skips its initial stop command.
+    async fn reset_rx(&mut self) -> Result<(), Self::Error> {
+        let this = &mut *self.inner;
+        this.cmd(Command::StopNrt).await?;
+        this.cmd(Command::ClearFifo).await?;
+        this.irqs = 0;
+        self.rx_reset = true;
+        Ok(())
+    }
+
+    /// Turns the chip off with `St25r39::mode_off`. Transceives then fail with
+    /// [`Error::FieldNotOn`] until a new session is started.
+    fn field_off(&mut self) {
+        if self.inner.mode_off().is_err() {
+            warn!("Failed to set field off");
+        }
+    }
+
+    async fn card_in_field(&mut self) -> Option<bool> {
+        let card_detect = self.card_detect?;
+        match self.inner.measure_amplitude().await {
+            Ok(amplitude) => {
+                trace!("card detect: amplitude {}", amplitude);
+                Some(amplitude as u16 + card_detect.delta as u16 <= card_detect.empty_amplitude as u16)
+            }
+            Err(_) => {
+                warn!("card detect: amplitude measurement failed");
+                None
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use ll::Reader as _;
+
+    use super::*;
+    use crate::test_util::{MockChip, MockIrq, NoDelay};
+
+    #[test_log::test(tokio::test)]
+    async fn test_response_latched_before_rxs_wait() {
+        // The card answers so fast that RXS and RXE are latched along with TXE, and all three
+        // are read by the TXE wait.
+        let mut chip = MockChip::new();
+        let data = hex!("01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10");
+        chip.responses.push_back([&data[..], &[0xAA, 0xBB]].concat());
+        let mut st = St25r39::new_with_delay(chip, MockIrq, NoDelay, Config::default())
+            .await
+            .unwrap();
+        let mut iso = st.start_iso14443a().await.unwrap();
+
+        let opts = ll::Frame::Standard {
+            timeout_1fc: 65536,
+            crc: ll::Crc::A,
+        };
+        let mut rx = [0; 16];
+        let bits = iso.transceive(&hex!("30 04"), &mut rx, opts).await.unwrap();
+        assert_eq!(bits, 128);
+        assert_eq!(rx, data);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_crc_b_frame_in_iso14443b_mode() {
+        let mut chip = MockChip::new();
+        chip.responses
+            .push_back([&hex!("50 01 02 03 04")[..], &[0xAA, 0xBB]].concat());
+        chip.responses.push_back(hex!("04 00").to_vec());
+        let mut st = St25r39::new_with_delay(chip, MockIrq, NoDelay, Config::default())
+            .await
+            .unwrap();
+        let mut iso = st.start_iso14443a().await.unwrap();
+
+        // REQB: the chip adds and strips the CRC_B, with 10% ASK.
+        let opts = ll::Frame::Standard {
+            timeout_1fc: 65536,
+            crc: ll::Crc::B,
+        };
+        let mut rx = [0; 8];
+        let bits = iso.transceive(&hex!("05 00 00"), &mut rx, opts).await.unwrap();
+        assert_eq!(&rx[..bits / 8], hex!("50 01 02 03 04"));
+        let mode = iso.inner.regs().mode().read().unwrap();
+        assert!(mode.om() == regs::ModeOm::INI_ISO14443B);
+        assert!(mode.tr_am());
+        assert!(!iso.inner.regs().aux().read().unwrap().no_crc_rx());
+
+        // The next Type A frame goes back to ISO14443A, with the session's OOK.
+        let mut rx = [0; 2];
+        iso.transceive(&[0x26], &mut rx, ll::Frame::ReqA).await.unwrap();
+        let mode = iso.inner.regs().mode().read().unwrap();
+        assert!(mode.om() == regs::ModeOm::INI_ISO14443A);
+        assert!(!mode.tr_am());
+    }
+    const CRC_A: ll::Frame = ll::Frame::Standard {
+        timeout_1fc: 65536,
+        crc: ll::Crc::A,
+    };
+
+    async fn new_st25r39(chip: MockChip) -> St25r39<MockChip, MockIrq, NoDelay> {
+        St25r39::new_with_delay(chip, MockIrq, NoDelay, Config::default())
+            .await
+            .unwrap()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_anticoll_partial_byte_bounds() {
+        // 20 bits sent: the card's answer starts in the third byte, which is merged with the
+        // prefix even if nothing else fits.
+        let mut chip = MockChip::new();
+        chip.responses.push_back(Vec::new());
+        chip.responses.push_back(hex!("f5 06 07").to_vec());
+        let mut st = new_st25r39(chip).await;
+        let mut iso = st.start_iso14443a().await.unwrap();
+        let opts = ll::Frame::Anticoll { bits: 20 };
+        let tx = hex!("93 24 01");
+
+        let mut rx = [0; 2];
+        let res = iso.transceive(&tx, &mut rx, opts).await;
+        assert_eq!(res, Err(Error::ResponseTooLong));
+
+        let mut rx = [0; 5];
+        let bits = iso.transceive(&tx, &mut rx, opts).await.unwrap();
+        assert_eq!(bits, 40);
+        // The low 4 bits of the shared byte are ours.
+        assert_eq!(rx, hex!("93 24 f1 06 07"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_tx_too_long() {
+        let mut st = new_st25r39(MockChip::new()).await;
+        let mut iso = st.start_iso14443a().await.unwrap();
+
+        let tx = std::vec![0; TX_MAX_LEN + 1];
+        let res = iso.transceive(&tx, &mut [0; 2], CRC_A).await;
+        assert_eq!(res, Err(Error::TxTooLong { max_len: TX_MAX_LEN }));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_field_not_on() {
+        let mut st = new_st25r39(MockChip::new()).await;
+        let mut iso = st.start_iso14443a().await.unwrap();
+
+        iso.inner.iface.set_field(false);
+        let res = iso.transceive(&hex!("30 04"), &mut [0; 16], CRC_A).await;
+        assert_eq!(res, Err(Error::FieldNotOn));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_partial_rx() {
+        // The response starts but never ends.
+        let mut chip = MockChip::new();
+        chip.transmit_irqs = 1 << Interrupt::Txe as u32 | 1 << Interrupt::Rxs as u32;
+        chip.responses.push_back(hex!("01 02 03").to_vec());
+        chip.responses.push_back(hex!("01 02 03").to_vec());
+        let mut st = new_st25r39(chip).await;
+        let mut iso = st.start_iso14443a().await.unwrap();
+
+        let res = iso.transceive(&hex!("30 04"), &mut [0; 16], CRC_A).await;
+        assert_eq!(res, Err(Error::Timeout));
+
+        iso.set_partial_rx(true);
+        let mut rx = [0; 16];
+        let res = iso.transceive_ignore_parity(&hex!("30 04"), &mut rx, CRC_A).await.unwrap();
+        assert!(res.truncated);
+        assert_eq!(res.bits, 24);
+        assert_eq!(rx[..3], hex!("01 02 03"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_collision_policy() {
+        let mut chip = MockChip::new();
+        chip.transmit_irqs |= 1 << Interrupt::Col as u32;
+        for _ in 0..4 {
+            chip.responses.push_back(hex!("01 02 aa bb").to_vec());
+        }
+        let mut st = new_st25r39(chip).await;
+        let mut iso = st.start_iso14443a().await.unwrap();
+        let no_crc = ll::Frame::Standard {
+            timeout_1fc: 65536,
+            crc: ll::Crc::None,
+        };
+        let mut rx = [0; 4];
+
+        let res = iso.transceive(&hex!("30 04"), &mut rx, CRC_A).await;
+        assert_eq!(res, Err(Error::Collision));
+
+        iso.set_collision_policy(CollisionPolicy::Transient);
+        let res = iso.transceive(&hex!("30 04"), &mut rx, CRC_A).await;
+        assert_eq!(res, Err(Error::TransientCollision));
+
+        // The CRC vouches for the frame, but there's none to check without it.
+        iso.set_collision_policy(CollisionPolicy::IgnoreIfCrcOk);
+        let bits = iso.transceive(&hex!("30 04"), &mut rx, CRC_A).await.unwrap();
+        assert_eq!(rx[..bits / 8], hex!("01 02"));
+        let res = iso.transceive(&hex!("30 04"), &mut rx, no_crc).await;
+        assert_eq!(res, Err(Error::TransientCollision));
+    }
+}
@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(async_fn_in_trait)]
 #![deny(unused_must_use)]
 
@@ -6,33 +6,129 @@
 mod fmt;
 
 mod aat;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod frame_log;
 mod interface;
+#[cfg(feature = "iso14443a")]
 pub mod iso14443a;
+#[cfg(feature = "emulation")]
+pub mod listen;
+mod register;
 mod regs;
+mod resonance;
+#[cfg(test)]
+mod test_util;
+
+use core::time::Duration;
 
 pub use aat::AatConfig;
 use embassy_futures::yield_now;
-use embassy_time::{Duration, Instant};
+#[cfg(feature = "embassy-time")]
+use embassy_time::{block_for, Instant};
 use embedded_hal::digital::InputPin;
+use embedded_hal_async::delay::DelayNs;
 use embedded_hal_async::digital::Wait;
+pub use frame_log::{Direction, Drain, FrameLog, FrameSink, LoggedFrame, FRAME_LOG_DATA_LEN};
 pub use interface::{I2cInterface, Interface, SpiInterface};
+pub use register::{Register, RegisterSpace};
+pub use resonance::{ResonancePoint, ResonanceReport, RESONANCE_POINTS};
+use rnfc_traits::iso14443a_ll as ll;
+#[cfg(feature = "iso14443b")]
+use rnfc_traits::iso14443b;
 
 use self::regs::Regs;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
 
+/// `d` in µs, for `DelayNs::delay_us`. Saturates instead of truncating durations that don't fit.
+fn duration_us(d: Duration) -> u32 {
+    d.as_micros().try_into().unwrap_or(u32::MAX)
+}
+
+/// Wait between interrupt polls without the `embassy-time` feature, see [`PollTimeout`].
+#[cfg(not(feature = "embassy-time"))]
+const POLL_INTERVAL_US: u32 = 10;
+
+/// What a poll costs without the `embassy-time` feature, as measured with the usual 32.768kHz
+/// timer: the `POLL_INTERVAL_US` wait rounds up to a 30.5µs tick, and reading the interrupt
+/// registers over SPI takes about 10µs more.
+#[cfg(not(feature = "embassy-time"))]
+const POLL_COST_US: u32 = 40;
+
+/// Deadline of a polling loop.
+///
+/// With the `embassy-time` feature this is an `Instant`, and the driver yields between polls.
+/// Without it, there's no clock: the driver waits `POLL_INTERVAL_US` between polls with the
+/// delay provider, and counts each poll as `POLL_COST_US`. The timeout is then only as
+/// accurate as that estimate for the actual timer and bus.
+struct PollTimeout {
+    #[cfg(feature = "embassy-time")]
+    deadline: Instant,
+    #[cfg(not(feature = "embassy-time"))]
+    remaining_us: u32,
+}
+
+impl PollTimeout {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            #[cfg(feature = "embassy-time")]
+            deadline: Instant::now() + timeout.try_into().unwrap_or(embassy_time::Duration::MAX),
+            #[cfg(not(feature = "embassy-time"))]
+            remaining_us: duration_us(timeout),
+        }
+    }
+
+    fn expired(&self) -> bool {
+        #[cfg(feature = "embassy-time")]
+        return Instant::now() > self.deadline;
+        #[cfg(not(feature = "embassy-time"))]
+        return self.remaining_us == 0;
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error<T> {
     Interface(T),
     Timeout,
+    /// Waiting on the IRQ pin failed.
+    Irq,
+    /// A setting is out of range, or the call isn't valid in the current mode.
+    Config(ConfigError),
 }
 
-/// Direct commands
+/// What's wrong with a setting or call, see [`Error::Config`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[allow(unused)]
-enum Command {
+pub enum ConfigError {
+    /// [`ActiveTimings::rfca_n`] is above 3.
+    RfcaN,
+    /// A [`SquelchReference`] value is above 15.
+    SquelchReference,
+    /// [`FieldRamp::start_d_res`] is not 1 to 14.
+    FieldRampStart,
+    /// The chip isn't in wakeup mode, see [`St25r39::wait_for_card`].
+    NotInWakeupMode,
+    /// A [`ListenWakeupConfig`] with neither a period nor the external field, which would
+    /// never wake up.
+    NoWakeupSource,
+    /// The UID to emulate isn't 4 or 7 bytes.
+    UidLength,
+    /// The UID size bits in the ATQA to emulate don't match the UID.
+    AtqaUidSize,
+    /// More than [`PT_MEMORY_SIZE`] bytes for the passive target memory.
+    PtMemoryTooLong,
+    /// The [`Interface`] doesn't support the operation.
+    Unsupported,
+    /// The register can't be written, see [`Register::is_writable`].
+    ReadOnlyRegister(Register),
+}
+
+/// Direct commands, see [`St25r39::run_command`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
     /// Puts the chip in default state (same as after power-up)
     SetDefault = 0xC1,
     /// Stops all activities and clears FIFO
@@ -178,6 +274,27 @@ pub struct WakeupConfig {
     pub capacitive: Option<WakeupMethodConfig>,
 }
 
+/// Wake-up settings for card emulation, see [`St25r39::wait_for_reader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ListenWakeupConfig {
+    /// Wake up periodically with the wake-up timer, without measuring anything. `None` to only
+    /// wake up on a reader's field.
+    pub period: Option<WakeupPeriod>,
+    /// Wake up when a reader's field comes on, with the external field detector.
+    pub external_field: bool,
+}
+
+/// What ended [`St25r39::wait_for_reader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ListenWakeupCause {
+    /// The wake-up timer period elapsed.
+    Timer,
+    /// A reader's field came on.
+    ExternalField,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct WakeupMethodConfig {
@@ -193,6 +310,34 @@ pub enum WakeupReference {
     AutoAverage { include_irq_measurement: bool, weight: u8 },
 }
 
+/// Measurement that woke the chip up from [`St25r39::wait_for_card`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeupSource {
+    InductiveAmplitude,
+    InductivePhase,
+    Capacitive,
+}
+
+/// What woke the chip up from [`St25r39::wait_for_card`], see [`St25r39::wakeup_result`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WakeupResult {
+    pub source: WakeupSource,
+    /// Result of the measurement that went past the delta.
+    pub measured: u8,
+    /// Reference it was compared against: the configured one, or the running average with
+    /// [`WakeupReference::AutoAverage`].
+    pub reference: u8,
+}
+
+impl WakeupResult {
+    /// Difference from the reference, positive if the measurement is above it.
+    pub fn delta(&self) -> i16 {
+        self.measured as i16 - self.reference as i16
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 
@@ -239,6 +384,18 @@ pub enum FieldOnError<T> {
     FieldCollision,
     Interface(T),
     Timeout,
+    Irq,
+    Config(ConfigError),
+}
+
+impl Variant {
+    fn from_ic_type(ic_type: regs::IcIdentityIcType) -> Self {
+        match ic_type {
+            regs::IcIdentityIcType::ST25R3916 => Self::St25r3916,
+            regs::IcIdentityIcType::ST25R3916B => Self::St25r3916b,
+            t => Self::Unknown(t.0),
+        }
+    }
 }
 
 impl<T> From<Error<T>> for FieldOnError<T> {
@@ -246,6 +403,539 @@ impl<T> From<Error<T>> for FieldOnError<T> {
         match val {
             Error::Interface(e) => FieldOnError::Interface(e),
             Error::Timeout => FieldOnError::Timeout,
+            Error::Irq => FieldOnError::Irq,
+            Error::Config(e) => FieldOnError::Config(e),
+        }
+    }
+}
+
+/// Time cards need after the field comes on before the first frame.
+pub const FIELD_ON_GUARD_TIME: Duration = Duration::from_millis(5);
+
+/// FIFO size in bytes.
+pub const FIFO_SIZE: usize = 512;
+/// Max TX frame length in bytes, without hardware CRC. Frames larger than the FIFO are streamed,
+/// the limit is from the length registers.
+pub const TX_MAX_LEN: usize = 8191;
+/// Passive target memory size in bytes, see [`Interface::write_pt_memory`].
+pub const PT_MEMORY_SIZE: usize = 48;
+
+/// Chip variant, from the IC identity register.
+///
+/// The driver configures all variants the same way: the register map only describes the
+/// features the variants share, so none of the ST25R3916B's additions are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Variant {
+    /// ST25R3916 or ST25R3917.
+    St25r3916,
+    /// ST25R3916B or ST25R3917B.
+    St25r3916b,
+    /// Unknown IC type. Contains the raw IC type value.
+    Unknown(u8),
+}
+
+/// Registers read back into [`Capabilities::registers`]: the ones configured at init,
+/// which depend on the board's supply and antenna.
+pub const CAPABILITY_REGISTERS: [Register; 6] = [
+    Register::IoConf1,
+    Register::IoConf2,
+    Register::OpControl,
+    Register::TxDriver,
+    Register::RegulatorControl,
+    Register::RegulatorResult,
+];
+
+/// Chip and driver capabilities, see [`St25r39::capabilities`].
+///
+/// Meant to be logged at startup, and included in bug reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Capabilities {
+    pub variant: Variant,
+    /// Silicon revision.
+    pub ic_rev: u8,
+    pub fifo_size: usize,
+    /// Supply mode selected at init, 3.3V (true) or 5V (false).
+    pub supply_3v: bool,
+    pub antenna: AntennaMode,
+    /// Current values of [`CAPABILITY_REGISTERS`], including the effect of
+    /// [`Config::register_overrides`].
+    pub registers: [(Register, u8); CAPABILITY_REGISTERS.len()],
+    /// ISO14443A is supported ([`St25r39::start_iso14443a`]). False if the `iso14443a` feature is disabled.
+    pub iso14443a: bool,
+    /// ISO14443B frames can be sent with `Crc::B` through the ISO14443A session, with the timings
+    /// of `St25r39::set_iso14443b_timings`. False unless the `iso14443a` and `iso14443b` features
+    /// are both enabled.
+    pub iso14443b: bool,
+    /// Passive target emulation is supported (`St25r39::start_listen_a`). False if the `emulation`
+    /// feature is disabled.
+    pub emulation: bool,
+    /// Wakeup mode ([`St25r39::wait_for_card`]) is supported. False on unknown variants, whose
+    /// wakeup timer and measurement registers may differ.
+    pub wakeup: bool,
+    /// Antenna tuning ([`St25r39::aat`]) is supported. False on unknown variants, as for `wakeup`.
+    pub aat: bool,
+}
+
+/// FIFO status, see [`St25r39::fifo_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoStatus {
+    /// Number of bytes in the FIFO, including an incomplete last byte.
+    pub byte_count: usize,
+    /// Data was written to the FIFO while full.
+    pub overflow: bool,
+    /// Data was read from the FIFO while empty.
+    pub underflow: bool,
+    /// Number of valid bits in the last byte, if it's incomplete. 0 if complete.
+    pub last_byte_bits: u8,
+    /// The last received byte had no parity bit.
+    pub missing_parity: bool,
+}
+
+/// Bitrate of NFC-A frames, in either direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bitrate {
+    Kbps106,
+    Kbps212,
+    Kbps424,
+    Kbps848,
+}
+
+impl Bitrate {
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    fn from_reg(val: regs::BitRateE) -> Self {
+        match val {
+            regs::BitRateE::_106 => Self::Kbps106,
+            regs::BitRateE::_212 => Self::Kbps212,
+            regs::BitRateE::_424 => Self::Kbps424,
+            _ => Self::Kbps848,
+        }
+    }
+
+    #[cfg_attr(not(feature = "emulation"), allow(unused))]
+    fn to_reg(self) -> regs::BitRateE {
+        match self {
+            Self::Kbps106 => regs::BitRateE::_106,
+            Self::Kbps212 => regs::BitRateE::_212,
+            Self::Kbps424 => regs::BitRateE::_424,
+            Self::Kbps848 => regs::BitRateE::_848,
+        }
+    }
+}
+
+/// Receiver gain reduction on each channel, see [`St25r39::squelch_reference`].
+///
+/// In the chip's gain reduction steps, 0 to 15: 0 is no reduction, each step reduces the gain
+/// further (`rg2_am`/`rg2_pm` in the datasheet's receiver configuration register 4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SquelchReference {
+    /// AM channel.
+    pub am: u8,
+    /// PM channel.
+    pub pm: u8,
+}
+
+/// Error interrupts pending at the end of the last frame, see [`St25r39::last_error_flags`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ErrorFlags {
+    /// Hard framing error (`err1`).
+    pub framing: bool,
+    /// Soft framing error (`err2`).
+    pub soft_framing: bool,
+    /// Parity error (`par`).
+    pub parity: bool,
+    /// CRC error (`crc`).
+    pub crc: bool,
+    /// Bit collision (`col`).
+    pub collision: bool,
+}
+
+impl ErrorFlags {
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    fn from_irqs(irqs: u32) -> Self {
+        let irq = |irq: Interrupt| irqs & (1 << (irq as u8)) != 0;
+        Self {
+            framing: irq(Interrupt::Err1),
+            soft_framing: irq(Interrupt::Err2),
+            parity: irq(Interrupt::Par),
+            crc: irq(Interrupt::Crc),
+            collision: irq(Interrupt::Col),
+        }
+    }
+
+    /// Any of the flags is set.
+    pub fn any(&self) -> bool {
+        self.framing || self.soft_framing || self.parity || self.crc || self.collision
+    }
+}
+
+/// Tally of ISO14443A frame outcomes since the last reset, see [`St25r39::health`].
+///
+/// The chip has no error counters of its own, so these count what the driver returned. Growing
+/// error rates over time hint at antenna degradation or EMI. Counters saturate instead of wrapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Health {
+    /// Frames sent, successful or not.
+    pub frames: u32,
+    pub timeouts: u32,
+    /// Hard framing errors, including a missing parity on the last byte.
+    pub framing: u32,
+    pub crc: u32,
+    pub parity: u32,
+    /// Collisions, transient or not.
+    pub collisions: u32,
+    /// FIFO overflows and underflows.
+    pub fifo: u32,
+    /// Frames not sent because our field wasn't on, or couldn't be turned back on.
+    pub field: u32,
+    /// Errors talking to the chip itself.
+    pub interface: u32,
+    /// Everything else: unexpected response lengths, frames too long.
+    pub other: u32,
+}
+
+/// Overshoot or undershoot protection settings, see [`St25r39::configure_aws`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AwsConfig {
+    /// 14-bit pattern applied to the driver around modulation edges, MSB first.
+    /// Each bit lasts one carrier cycle (1/fc).
+    pub pattern: u16,
+    /// TX modes the protection is active in (`ov_tx_mode`/`un_tx_mode` in the datasheet).
+    /// 0 disables the protection.
+    pub tx_mode: u8,
+}
+
+impl AwsConfig {
+    /// Protection disabled. This is the chip's reset value.
+    pub const DISABLED: Self = Self { pattern: 0, tx_mode: 0 };
+    /// Short pattern used by the driver unless configured otherwise.
+    pub const DEFAULT: Self = Self {
+        pattern: 0x0003,
+        tx_mode: 0b01,
+    };
+}
+
+impl Default for AwsConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// How the antenna is connected to the RFO pins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AntennaMode {
+    /// Differential, driven by both RFO1 and RFO2.
+    Differential,
+    /// Single-ended on RFO1.
+    SingleRfo1,
+    /// Single-ended on RFO2.
+    SingleRfo2,
+}
+
+/// When to reset the RX gain before a frame, so the AGC re-converges from scratch.
+///
+/// Resetting adapts to coupling changes, such as a card moving or a new card being selected.
+/// With stable coupling (e.g. during an ISO-DEP exchange) it only adds latency and can make
+/// the gain hunt. The AGC is disabled for anticollision frames, where the reset is pointless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AgcResetStrategy {
+    /// Before every frame.
+    Always,
+    /// Only before the first frame of the session.
+    FirstFrameOnly,
+    /// Never, the gain carries over from previous frames and sessions.
+    Never,
+    /// Only before the frames of card activation (REQA, WUPA and anticollision), so each
+    /// newly selected card starts from a fresh gain that's kept for its data exchange.
+    AnticollOnly,
+}
+
+/// Frequency of the clock output on the MCU_CLK pin, divided from the 27.12 MHz crystal.
+/// See [`St25r39::configure_mcu_clock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockDiv {
+    /// 3.39 MHz
+    Div8,
+    /// 6.78 MHz
+    Div4,
+    /// 13.56 MHz
+    Div2,
+}
+
+fn mcu_clock_reg(clock: Option<ClockDiv>) -> regs::IoConf1OutCl {
+    match clock {
+        None => regs::IoConf1OutCl::DISABLED,
+        Some(ClockDiv::Div8) => regs::IoConf1OutCl::_3_39_MHZ,
+        Some(ClockDiv::Div4) => regs::IoConf1OutCl::_6_78_MHZ,
+        Some(ClockDiv::Div2) => regs::IoConf1OutCl::_13_86_MHZ,
+    }
+}
+
+/// Field-on watchdog, see `Config::field_watchdog`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FieldWatchdog {
+    /// Max time the field may stay on continuously.
+    pub max_on: Duration,
+    /// How long the field is turned off when `max_on` is exceeded.
+    pub off_time: Duration,
+}
+
+/// Field switching timings for NFC-DEP active mode, see `St25r39::set_active_timings`.
+///
+/// In active mode, each device turns its field off after sending, and the other turns its own
+/// on to answer. Phones are picky about these, so they're tunable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ActiveTimings {
+    /// Max wait, after turning our field off, for the other device to turn its field on.
+    pub field_wait: Duration,
+    /// Guard time after our field comes on, before we may transmit.
+    pub guard_time: Duration,
+    /// `n` for response RF collision avoidance, 0 to 3: our field comes on T_ADT + n * T_RFW
+    /// after the other device's goes off.
+    pub rfca_n: u8,
+}
+
+impl ActiveTimings {
+    /// NFCIP-1 values: T_ADT up to 2559/fc plus up to 3 T_RFW of 512/fc for the other device's
+    /// field, and the minimum T_ARFG of 1024/fc for the guard time.
+    pub const DEFAULT: Self = Self {
+        field_wait: Duration::from_micros(302),
+        guard_time: Duration::from_micros(76),
+        rfca_n: 0,
+    };
+}
+
+/// A set of chip interrupts, see [`Config::transceive_irqs`].
+///
+/// Bit `n` is bit `n % 8` of interrupt register `n / 8`, in datasheet order: main, timer and
+/// NFC, error and wake-up, passive target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IrqMask(pub u32);
+
+impl IrqMask {
+    pub const ALL: Self = Self(u32::MAX);
+    /// The interrupts ISO14443A transceives wait for: collision, TX and RX events and the
+    /// FIFO water level in the main register, and the framing, parity and CRC errors.
+    pub const TRANSCEIVE: Self = Self(
+        1 << Interrupt::Col as u32
+            | 1 << Interrupt::Txe as u32
+            | 1 << Interrupt::Rxe as u32
+            | 1 << Interrupt::Rxs as u32
+            | 1 << Interrupt::Fwl as u32
+            | 1 << Interrupt::Err1 as u32
+            | 1 << Interrupt::Err2 as u32
+            | 1 << Interrupt::Par as u32
+            | 1 << Interrupt::Crc as u32,
+    );
+
+    /// Whether any interrupt of register `i` is in the set.
+    fn has_register(self, i: u8) -> bool {
+        (self.0 >> (i * 8)) as u8 != 0
+    }
+}
+
+/// How the driver waits for interrupts, see [`Config::poll_strategy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PollStrategy {
+    /// Yield between each poll of the interrupt registers, letting other tasks run. Without the
+    /// `embassy-time` feature, wait a few µs with the delay provider instead.
+    Yield,
+    /// During critical waits (reception start and end), poll up to `spins` times without
+    /// waiting, then fall back to waiting between polls.
+    ///
+    /// This keeps latency low on executors that don't poll the driver often, at the cost of
+    /// blocking other tasks for up to `spins` register reads.
+    BusyPoll { spins: u32 },
+}
+
+/// Streaming of frames larger than the FIFO, see [`St25r39::set_tx_streaming`].
+///
+/// The first `preload` bytes are written before starting TX. The rest is written during TX,
+/// each time the FIFO water level interrupt fires, in chunks of `chunk` bytes with yields in
+/// between. Optimal values depend on the SPI speed versus the TX bitrate: chunks too small
+/// risk a FIFO underflow if the executor is slow to come back, chunks too large waste time
+/// blocking other tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TxStreaming {
+    /// Bytes written before starting TX, capped at [`FIFO_SIZE`].
+    pub preload: usize,
+    /// Bytes written at once when refilling the FIFO.
+    pub chunk: usize,
+}
+
+impl TxStreaming {
+    /// Safe defaults for a TX bitrate in kbps: a full FIFO preload, and chunks of about
+    /// 5 ms worth of TX data.
+    pub const fn for_kbps(kbps: u32) -> Self {
+        Self {
+            preload: FIFO_SIZE,
+            chunk: kbps as usize * 5 / 9 + 1,
+        }
+    }
+}
+
+/// RF collision avoidance settings, see [`St25r39::set_collision_avoidance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionAvoidance {
+    /// How many times to retry after an external field is detected.
+    pub retries: u8,
+    /// Max backoff before each retry. The actual backoff is uniformly random between 0 and this.
+    pub max_backoff: Duration,
+    /// Max random delay before the first check, zero for none.
+    ///
+    /// Readers powered up together, such as on a shared supply, check at the same time and keep
+    /// colliding with each other's fields. A startup delay spreads them apart from the start.
+    pub max_startup_delay: Duration,
+    /// Random number source for the backoff, such as a hardware RNG.
+    ///
+    /// Pollers backing off by the same amount would collide again, so this should
+    /// differ between devices, not just between calls.
+    pub rng: fn() -> u32,
+}
+
+impl CollisionAvoidance {
+    /// Uniformly random delay between 0 and `max`, in µs.
+    fn random_delay_us(&self, max: Duration) -> u32 {
+        let max_us = duration_us(max);
+        if max_us == 0 {
+            0
+        } else {
+            ((self.rng)() as u64 % (max_us as u64 + 1)) as u32
+        }
+    }
+}
+
+/// Soft start of our field, see [`Config::field_ramp`].
+///
+/// The TX driver comes on at `start_d_res`, then its resistance is lowered one step every
+/// `step_time` down to full power, which spreads the inrush current over
+/// `start_d_res * step_time`. That time adds to each field on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FieldRamp {
+    /// Driver resistance the field comes on at, 1 to 14 (`d_res` in the datasheet). Higher
+    /// values are weaker, 15 is high impedance.
+    pub start_d_res: u8,
+    /// Time between each step.
+    pub step_time: Duration,
+}
+
+/// Driver configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Delay after each direct command.
+    ///
+    /// Some silicon revisions drop direct commands issued back-to-back (such as the
+    /// Stop + ResetRxgain sequence at the start of every transceive), this gives
+    /// them time to settle. Waited with the delay provider, except in sync calls such as
+    /// [`St25r39::mode_off`] and drops, which block, or skip it without the `embassy-time`
+    /// feature. Default: no delay.
+    pub command_delay: Duration,
+    /// When to reset the RX gain in Iso14443a sessions, see [`AgcResetStrategy`].
+    ///
+    /// Sessions can override it with `Iso14443a::set_agc_reset_strategy`. Default: [`AgcResetStrategy::Always`].
+    pub agc_reset: AgcResetStrategy,
+    /// Retry activation frames on the other demodulator channel when the card doesn't answer.
+    ///
+    /// Some cards' coupling favors one channel (AM or PM). With this, when REQA, WUPA,
+    /// anticollision or SELECT gets no response in an Iso14443a session, it's retried once on
+    /// the other channel, which the session keeps using if it works. This doubles the
+    /// worst-case latency, including when polling with no card in the field. Default: false.
+    pub demod_retry: bool,
+    /// Antenna connection, must match the board. A mismatch roughly halves the TX power.
+    ///
+    /// Only applied at init (and [`St25r39::reset`]), changing it with [`St25r39::set_config`] alone has no effect.
+    /// Default: differential.
+    pub antenna: AntennaMode,
+    /// Registers written at the end of init, in order, overriding the driver's defaults.
+    ///
+    /// For board-specific tuning not covered by the other options, such as driver timing.
+    /// Applied at init (and [`St25r39::reset`]), and again over the overshoot/undershoot
+    /// protection registers each time the driver writes them (see [`St25r39::configure_aws`]).
+    /// Other registers the driver writes later, such as the TX driver when the field comes on,
+    /// can't be overridden this way.
+    /// Init fails with [`ConfigError::ReadOnlyRegister`] if an override targets a read-only
+    /// register, without applying any. Default: none.
+    pub register_overrides: &'static [(Register, u8)],
+    /// Limit how long the field stays on continuously, for regulatory regimes that require it.
+    ///
+    /// Checked before each transceive: if the field has been on for longer than `max_on`, it's
+    /// turned off for `off_time` then back on. Cards lose their state when the field goes off,
+    /// so this should be well above the length of a transaction. Needs the `embassy-time`
+    /// feature, for the clock. Default: none.
+    #[cfg(feature = "embassy-time")]
+    pub field_watchdog: Option<FieldWatchdog>,
+    /// How to wait for interrupts. Default: [`PollStrategy::Yield`].
+    pub poll_strategy: PollStrategy,
+    /// Clock output on the MCU_CLK pin, set early in init for boards where the MCU runs from it.
+    ///
+    /// Only applied at init (and [`St25r39::reset`]), see [`St25r39::configure_mcu_clock`] to
+    /// change it later. Default: disabled.
+    pub mcu_clock: Option<ClockDiv>,
+    /// Check the FIFO byte count after each received frame before reading it.
+    ///
+    /// Some silicon revisions report more bytes than were received after certain error
+    /// conditions, so the driver would read garbage past the frame. With this, a count above
+    /// [`FIFO_SIZE`], or that changes when read again after the end of the frame, fails the
+    /// transceive with a framing error instead. Costs an extra status read per frame. Default: false.
+    pub fifo_count_check: bool,
+    /// Interrupts enabled while the field is on for ISO14443A, on top of [`IrqMask::TRANSCEIVE`]
+    /// which the driver always needs.
+    ///
+    /// The others are masked, so they don't raise the IRQ pin, and transceives only read the
+    /// interrupt registers that have enabled interrupts. With [`IrqMask::TRANSCEIVE`] that's the
+    /// main and error registers, which halves the register reads per poll iteration. Masked
+    /// interrupts stay pending in the chip until the next full read, such as a direct command.
+    /// Wake-up and listen modes set their own masks. Default: [`IrqMask::ALL`].
+    pub transceive_irqs: IrqMask,
+    /// Keep the crystal oscillator running in [`St25r39::mode_off`], only turning off the field,
+    /// receiver and field detector.
+    ///
+    /// The next [`St25r39::mode_on`], and so the next ISO14443A session or listen start, then
+    /// doesn't wait for the oscillator to start, which takes a few ms depending on the crystal.
+    /// Meanwhile the chip draws its ready mode current, in the mA range, instead of the
+    /// power-down one, in the µA range. Default: false.
+    pub keep_oscillator: bool,
+    /// Ramp the field up when turning it on, instead of switching the driver straight to full power.
+    ///
+    /// For weak supplies that droop or brown out on the inrush current. The ramp is done
+    /// before [`St25r39::field_on`] returns, so before [`FIELD_ON_GUARD_TIME`] starts.
+    /// Default: none, the field comes on at full power.
+    pub field_ramp: Option<FieldRamp>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            command_delay: Duration::ZERO,
+            agc_reset: AgcResetStrategy::Always,
+            demod_retry: false,
+            antenna: AntennaMode::Differential,
+            register_overrides: &[],
+            #[cfg(feature = "embassy-time")]
+            field_watchdog: None,
+            poll_strategy: PollStrategy::Yield,
+            mcu_clock: None,
+            fifo_count_check: false,
+            transceive_irqs: IrqMask::ALL,
+            keep_oscillator: false,
+            field_ramp: None,
         }
     }
 }
@@ -257,36 +947,264 @@ enum Mode {
     Wakeup,
 }
 
-pub struct St25r39<I: Interface, IrqPin: InputPin + Wait> {
+/// ST25R39xx driver.
+///
+/// `D` provides the guard time delays and timeouts. Any HAL delay works, see
+/// [`new_with_delay`](Self::new_with_delay). `new` uses `embassy_time::Delay`.
+pub struct St25r39<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> {
     iface: I,
     irq: IrqPin,
+    delay: D,
     irqs: u32,
     mode: Mode,
+    /// Detected at init.
+    variant: Variant,
+    config: Config,
+    frame_log: Option<&'static dyn FrameSink>,
+    /// Overshoot and undershoot protection, see `configure_aws`.
+    aws: (AwsConfig, AwsConfig),
+    /// When the field was last turned on, None if it's off.
+    #[cfg(feature = "embassy-time")]
+    field_on_since: Option<Instant>,
+    collision_avoidance: Option<CollisionAvoidance>,
+    /// `None` to derive it from the TX bitrate.
+    tx_streaming: Option<TxStreaming>,
+    #[cfg(feature = "embassy-time")]
+    active_timings: ActiveTimings,
+    /// See `last_active_turnaround`.
+    #[cfg(feature = "embassy-time")]
+    active_turnaround: Option<Duration>,
+    /// See `last_error_flags`.
+    error_flags: ErrorFlags,
+    health: Health,
+    /// Operation mode set for the last frame: ISO14443A, or Topaz or ISO14443B for the Type 1 and
+    /// CRC_B frames sent through the ISO14443A session.
+    frame_om: regs::ModeOm,
+    /// See `set_iso14443b_timings`.
+    #[cfg(feature = "iso14443b")]
+    iso14443b_timings: iso14443b::Timings,
 }
 
-impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
+#[cfg(feature = "embassy-time")]
+impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin, embassy_time::Delay> {
     pub async fn new(iface: I, irq: IrqPin) -> Result<Self, Error<I::Error>> {
+        Self::new_with_config(iface, irq, Default::default()).await
+    }
+
+    pub async fn new_with_config(iface: I, irq: IrqPin, config: Config) -> Result<Self, Error<I::Error>> {
+        Self::new_with_delay(iface, irq, embassy_time::Delay, config).await
+    }
+}
+
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> St25r39<I, IrqPin, D> {
+    /// Like `new_with_config`, with any delay provider instead of `embassy_time::Delay`.
+    pub async fn new_with_delay(iface: I, irq: IrqPin, delay: D, config: Config) -> Result<Self, Error<I::Error>> {
         let mut this = Self {
             iface,
             irq,
+            delay,
             irqs: 0,
             mode: Mode::On,
+            variant: Variant::Unknown(0),
+            config,
+            frame_log: None,
+            aws: (AwsConfig::DEFAULT, AwsConfig::DEFAULT),
+            #[cfg(feature = "embassy-time")]
+            field_on_since: None,
+            collision_avoidance: None,
+            tx_streaming: None,
+            #[cfg(feature = "embassy-time")]
+            active_timings: ActiveTimings::DEFAULT,
+            #[cfg(feature = "embassy-time")]
+            active_turnaround: None,
+            error_flags: ErrorFlags::default(),
+            health: Health::default(),
+            frame_om: regs::ModeOm::INI_ISO14443A,
+            #[cfg(feature = "iso14443b")]
+            iso14443b_timings: iso14443b::Timings::default(),
         };
         this.init().await?;
         Ok(this)
     }
 
+    /// Record all transceived frames into `log`, or stop recording if `None`.
+    ///
+    /// Useful for inspecting what happened after a failed transaction,
+    /// when live logging isn't feasible.
+    pub fn set_frame_log(&mut self, log: Option<&'static dyn FrameSink>) {
+        self.frame_log = log;
+    }
+
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    fn log_frame(&self, direction: Direction, frame: ll::Frame, data: &[u8]) {
+        if let Some(log) = self.frame_log {
+            log.record(direction, frame, data);
+        }
+    }
+
     fn regs(&mut self) -> Regs<I> {
         Regs::new(&mut self.iface)
     }
 
-    fn cmd(&mut self, cmd: Command) -> Result<(), Error<I::Error>> {
-        self.iface.do_command(cmd as u8).map_err(Error::Interface)
+    /// Read any register, for generic tooling such as register dumps.
+    ///
+    /// Reading the interrupt registers clears them, which can break an ongoing operation.
+    pub fn read_register(&mut self, reg: Register) -> Result<u8, Error<I::Error>> {
+        self.iface.read_reg(reg.addr()).map_err(Error::Interface)
+    }
+
+    /// Write any register, bypassing the driver's state.
+    ///
+    /// Fails with [`ConfigError::ReadOnlyRegister`] if the register is read-only, see
+    /// [`Register::is_writable`].
+    pub fn write_register(&mut self, reg: Register, val: u8) -> Result<(), Error<I::Error>> {
+        if !reg.is_writable() {
+            return Err(Error::Config(ConfigError::ReadOnlyRegister(reg)));
+        }
+        self.iface.write_reg(reg.addr(), val).map_err(Error::Interface)
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    /// Soft-reset the chip and initialize it again, with the current config.
+    ///
+    /// Use this to recover from an unknown state without power cycling the chip. All
+    /// register state is lost, including the antenna tuning. Safe to call between sessions.
+    ///
+    /// The state of the last session is cleared too, such as the field-on time and the last
+    /// error flags. Settings made with the setters and the [`health`](Self::health) counters
+    /// are kept.
+    pub async fn reset(&mut self) -> Result<(), Error<I::Error>> {
+        debug!("resetting chip");
+        self.irqs = 0;
+        // `init` leaves the oscillator enabled, same as after `new`.
+        self.mode = Mode::On;
+        #[cfg(feature = "embassy-time")]
+        {
+            self.field_on_since = None;
+            self.active_turnaround = None;
+        }
+        self.error_flags = ErrorFlags::default();
+        self.frame_om = regs::ModeOm::INI_ISO14443A;
+        self.init().await
+    }
+
+    async fn cmd(&mut self, cmd: Command) -> Result<(), Error<I::Error>> {
+        self.iface.do_command(cmd as u8).map_err(Error::Interface)?;
+        if !self.config.command_delay.is_zero() {
+            self.delay.delay_us(duration_us(self.config.command_delay)).await;
+        }
+        Ok(())
+    }
+
+    /// Like [`cmd`](Self::cmd), blocking for the delay, for sync contexts such as `Drop`.
+    ///
+    /// Without the `embassy-time` feature there's nothing to block with, and the delay is
+    /// skipped. Sync contexts only issue one command, so there's nothing after it to drop.
+    fn cmd_blocking(&mut self, cmd: Command) -> Result<(), Error<I::Error>> {
+        self.iface.do_command(cmd as u8).map_err(Error::Interface)?;
+        #[cfg(feature = "embassy-time")]
+        if !self.config.command_delay.is_zero() {
+            block_for(self.config.command_delay.try_into().unwrap_or(embassy_time::Duration::MAX));
+        }
+        Ok(())
+    }
+
+    /// Write to the FIFO in bursts of at most [`Interface::max_burst`] bytes, yielding in between.
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    async fn write_fifo(&mut self, data: &[u8]) -> Result<(), Error<I::Error>> {
+        let max_burst = self.iface.max_burst().unwrap_or(usize::MAX).max(1);
+        for (i, chunk) in data.chunks(max_burst).enumerate() {
+            if i != 0 {
+                yield_now().await;
+            }
+            self.iface.write_fifo(chunk).map_err(Error::Interface)?;
+        }
+        Ok(())
+    }
+
+    /// Write bytes `skip..skip + max` of the concatenation of `parts` to the FIFO, in chunks of
+    /// `chunk` bytes with yields in between. Returns how many bytes were written.
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    async fn write_fifo_parts(
+        &mut self,
+        parts: &[&[u8]],
+        mut skip: usize,
+        max: usize,
+        chunk: usize,
+    ) -> Result<usize, Error<I::Error>> {
+        let mut written = 0;
+        for part in parts {
+            if skip >= part.len() {
+                skip -= part.len();
+                continue;
+            }
+            let n = (part.len() - skip).min(max - written);
+            for (i, c) in part[skip..][..n].chunks(chunk.max(1)).enumerate() {
+                if i != 0 {
+                    yield_now().await;
+                }
+                self.write_fifo(c).await?;
+            }
+            written += n;
+            skip = 0;
+        }
+        Ok(written)
+    }
+
+    /// Wait for TXE, refilling the FIFO with the rest of `parts` on each water level interrupt.
+    ///
+    /// `written` is how many bytes of `parts` were preloaded.
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    async fn irq_wait_txe_streaming(
+        &mut self,
+        parts: &[&[u8]],
+        mut written: usize,
+        chunk: usize,
+        timeout: Duration,
+    ) -> Result<(), Error<I::Error>> {
+        let total: usize = parts.iter().map(|p| p.len()).sum();
+        let mut timeout = PollTimeout::new(timeout);
+        self.irq_update_critical()?;
+        while !self.irq(Interrupt::Txe) {
+            if written < total && self.irq(Interrupt::Fwl) {
+                self.irqs &= !(1 << (Interrupt::Fwl as u8));
+                let free = FIFO_SIZE - self.fifo_status()?.byte_count;
+                written += self.write_fifo_parts(parts, written, free, chunk).await?;
+                trace!("TX streaming: {}/{} bytes written", written, total);
+            }
+            if timeout.expired() {
+                return Err(Error::Timeout);
+            }
+            self.poll_delay(&mut timeout).await;
+            self.irq_update_critical()?;
+        }
+        Ok(())
+    }
+
+    /// Read from the FIFO in bursts of at most [`Interface::max_burst`] bytes, yielding in between.
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    async fn read_fifo(&mut self, data: &mut [u8]) -> Result<(), Error<I::Error>> {
+        let max_burst = self.iface.max_burst().unwrap_or(usize::MAX).max(1);
+        for (i, chunk) in data.chunks_mut(max_burst).enumerate() {
+            if i != 0 {
+                yield_now().await;
+            }
+            self.iface.read_fifo(chunk).map_err(Error::Interface)?;
+        }
+        Ok(())
     }
 
-    async fn cmd_wait(&mut self, cmd: Command) -> Result<(), Error<I::Error>> {
+    /// Run a direct command, and wait for it to complete.
+    ///
+    /// Only for commands signaling completion with the "direct command terminated" interrupt:
+    /// measurements, calibrations and [`Command::AdjustRegulators`]. Others, such as the
+    /// transmit commands, will time out.
+    pub async fn run_command(&mut self, cmd: Command) -> Result<(), Error<I::Error>> {
         self.irq_clear()?;
-        self.cmd(cmd)?;
+        self.cmd(cmd).await?;
         self.irq_wait(Interrupt::Dct).await
     }
 
@@ -298,15 +1216,16 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
     }
 
     async fn init(&mut self) -> Result<(), Error<I::Error>> {
-        self.cmd(Command::SetDefault)?;
+        self.cmd(Command::SetDefault).await?;
+
+        let id = self.regs().ic_identity().read()?;
+        trace!("ic_type = {:02x} ic_rev = {:02x}", id.ic_type().0, id.ic_rev().0);
+        self.variant = Variant::from_ic_type(id.ic_type());
 
         self.regs().test_unk().write(|w| {
             w.set_dis_overheat_prot(true);
         })?;
 
-        let id = self.regs().ic_identity().read()?;
-        trace!("ic_type = {:02x} ic_rev = {:02x}", id.ic_type().0, id.ic_rev().0);
-
         // Enable OSC
         self.enable_osc().await?;
 
@@ -326,10 +1245,14 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
             w.set_sup_3v(sup3v);
         })?;
 
-        // Disable MCU_CLK
+        // Set MCU_CLK and antenna mode
+        let antenna = self.config.antenna;
+        let out_cl = mcu_clock_reg(self.config.mcu_clock);
         self.regs().io_conf1().write(|w| {
-            w.set_out_cl(regs::IoConf1OutCl::DISABLED);
+            w.set_out_cl(out_cl);
             w.set_lf_clk_off(true);
+            w.set_single(antenna != AntennaMode::Differential);
+            w.set_rfo2(antenna == AntennaMode::SingleRfo2);
         })?;
 
         // Enable minimum non-overlap
@@ -368,34 +1291,317 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         self.regs().regulator_control().write(|w| w.set_reg_s(true))?;
         self.regs().regulator_control().write(|w| w.set_reg_s(false))?;
 
-        self.cmd_wait(Command::AdjustRegulators).await?;
+        self.run_command(Command::AdjustRegulators).await?;
 
         let res = self.regs().regulator_result().read()?.0;
         trace!("reg result = {}", res);
 
+        let overrides = self.config.register_overrides;
+        if let Some(&(reg, _)) = overrides.iter().find(|(reg, _)| !reg.is_writable()) {
+            warn!("register override of read-only register {:?}", reg);
+            return Err(Error::Config(ConfigError::ReadOnlyRegister(reg)));
+        }
+        for &(reg, val) in overrides {
+            trace!("register override {:?} = {:02x}", reg, val);
+            self.write_register(reg, val)?;
+        }
+
         Ok(())
     }
 
-    pub(crate) fn mode_off(&mut self) -> Result<(), Error<I::Error>> {
-        self.mode = Mode::Off;
-        self.cmd(Command::Stop)?;
+    /// Turn everything off, including the field. Does nothing if already off, so it's safe to call
+    /// more than once (e.g. explicitly and then again on session drop).
+    ///
+    /// The oscillator stays on with [`Config::keep_oscillator`].
+    pub fn mode_off(&mut self) -> Result<(), Error<I::Error>> {
+        if self.mode == Mode::Off {
+            return Ok(());
+        }
+        self.cmd_blocking(Command::Stop)?;
         // disable everything
-        self.regs().op_control().write(|_| {})?;
+        let keep_osc = self.config.keep_oscillator;
+        self.regs().op_control().write(|w| w.set_en(keep_osc))?;
+        // Only mark as off once it actually is, so a failed attempt is retried.
+        self.mode = Mode::Off;
+        #[cfg(feature = "embassy-time")]
+        {
+            self.field_on_since = None;
+        }
+        Ok(())
+    }
+
+    /// Chip variant, detected at init.
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Report the chip variant, its configuration as read back from the chip, and the enabled technologies.
+    pub fn capabilities(&mut self) -> Result<Capabilities, Error<I::Error>> {
+        let id = self.regs().ic_identity().read()?;
+        let variant = Variant::from_ic_type(id.ic_type());
+        let supply_3v = self.regs().io_conf2().read()?.sup_3v();
+        let mut registers = CAPABILITY_REGISTERS.map(|reg| (reg, 0));
+        for (reg, val) in &mut registers {
+            *val = self.read_register(*reg)?;
+        }
+
+        Ok(Capabilities {
+            variant,
+            ic_rev: id.ic_rev().0,
+            fifo_size: FIFO_SIZE,
+            supply_3v,
+            antenna: self.config.antenna,
+            registers,
+            iso14443a: cfg!(feature = "iso14443a"),
+            iso14443b: cfg!(all(feature = "iso14443a", feature = "iso14443b")),
+            emulation: cfg!(feature = "emulation"),
+            wakeup: !matches!(variant, Variant::Unknown(_)),
+            aat: !matches!(variant, Variant::Unknown(_)),
+        })
+    }
+
+    /// Read the FIFO status.
+    ///
+    /// Useful for implementing custom protocols on top of [`raw`](Self::raw).
+    pub fn fifo_status(&mut self) -> Result<FifoStatus, Error<I::Error>> {
+        let count = self.regs().fifo_status1().read()?;
+        let stat = self.regs().fifo_status2().read()?;
+        Ok(FifoStatus {
+            byte_count: count as usize | (stat.fifo_b() as usize) << 8,
+            overflow: stat.fifo_ovr(),
+            underflow: stat.fifo_unf(),
+            last_byte_bits: stat.fifo_lb(),
+            missing_parity: stat.np_lb(),
+        })
+    }
+
+    /// Whether the FIFO status of a received frame is plausible, see [`Config::fifo_count_check`].
+    ///
+    /// Always true when the check is disabled.
+    #[cfg(any(feature = "iso14443a", feature = "emulation"))]
+    fn fifo_count_valid(&mut self, stat: &FifoStatus) -> Result<bool, Error<I::Error>> {
+        if !self.config.fifo_count_check {
+            return Ok(true);
+        }
+        if stat.byte_count > FIFO_SIZE || (stat.byte_count == 0 && stat.last_byte_bits != 0) {
+            return Ok(false);
+        }
+        // RX is over, so the count can't change anymore.
+        Ok(self.fifo_status()?.byte_count == stat.byte_count)
+    }
+
+    /// Program Type B frame timings, matching what was (or will be) sent to the card in ATTRIB.
+    ///
+    /// TR0 has no register, the card's response is simply expected after the mask receive time.
+    /// The timings are kept across resets, and written again whenever a CRC_B frame switches the
+    /// chip to ISO14443B mode. Default: `iso14443b::Timings::default()`.
+    #[cfg(feature = "iso14443b")]
+    pub fn set_iso14443b_timings(&mut self, timings: &iso14443b::Timings) -> Result<(), Error<I::Error>> {
+        self.iso14443b_timings = *timings;
+        self.write_iso14443b_timings()
+    }
+
+    #[cfg(feature = "iso14443b")]
+    fn write_iso14443b_timings(&mut self) -> Result<(), Error<I::Error>> {
+        let timings = self.iso14443b_timings;
+        self.regs().iso14443b_1().modify(|w| w.set_egt(timings.egt_etu.min(6)))?;
+        self.regs().iso14443b_2().modify(|w| {
+            w.set_tr1(match timings.tr1_min {
+                iso14443b::Tr1::Default => regs::Iso14443b2Tr1::_80FS80FS,
+                // The chip has a single reduced setting, 64/fs at 106 kbps.
+                _ => regs::Iso14443b2Tr1::_64FS32FS,
+            });
+            w.set_no_sof(!timings.sof);
+            w.set_no_eof(!timings.eof);
+        })?;
+        Ok(())
+    }
+
+    /// Configure overshoot and undershoot protection (active wave shaping).
+    ///
+    /// This shapes the modulation edges to lower EMC emissions, which can be needed
+    /// to pass certification. Applied now, and every time the field is turned on.
+    /// Default: [`AwsConfig::DEFAULT`] for both.
+    ///
+    /// The patterns are timed in carrier cycles, not bits, so their relative length grows
+    /// with the TX bit rate: patterns tuned for 106 kbps are usually too long for higher
+    /// bit rates. Reconfigure when switching bit rates.
+    pub fn configure_aws(&mut self, overshoot: AwsConfig, undershoot: AwsConfig) -> Result<(), Error<I::Error>> {
+        self.aws = (overshoot, undershoot);
+        self.write_aws()
+    }
+
+    /// Configure the clock output on the MCU_CLK pin, or disable it if `enable` is false.
+    ///
+    /// Also updates [`Config::mcu_clock`], so it's kept across [`reset`](Self::reset).
+    ///
+    /// If the MCU uses this clock as its system clock source, changing it at runtime glitches
+    /// or stops the MCU clock. Such boards should set it with [`Config::mcu_clock`] instead,
+    /// and leave it alone afterwards.
+    pub fn configure_mcu_clock(&mut self, div: ClockDiv, enable: bool) -> Result<(), Error<I::Error>> {
+        self.config.mcu_clock = enable.then_some(div);
+        let out_cl = mcu_clock_reg(self.config.mcu_clock);
+        self.regs().io_conf1().modify(|w| w.set_out_cl(out_cl))?;
+        Ok(())
+    }
+
+    /// Configure RF collision avoidance when turning the field on.
+    ///
+    /// Before turning its field on, the chip checks for an external field. Without collision
+    /// avoidance (the default), an external field fails with [`FieldOnError::FieldCollision`] straight
+    /// away. With it, the check is retried after a random backoff, as required for NFC Forum
+    /// poller compliance, and only fails once the retries are exhausted.
+    pub fn set_collision_avoidance(&mut self, collision_avoidance: Option<CollisionAvoidance>) {
+        self.collision_avoidance = collision_avoidance;
+    }
+
+    /// Tune streaming of frames larger than the FIFO, or `None` for [`TxStreaming::for_kbps`]
+    /// at the current TX bitrate (the default).
+    pub fn set_tx_streaming(&mut self, tx_streaming: Option<TxStreaming>) {
+        self.tx_streaming = tx_streaming;
+    }
+
+    /// Set the field switching timings for NFC-DEP active mode, used by [`Raw::active_field_off`]
+    /// and [`Raw::active_field_on`]. Default: [`ActiveTimings::DEFAULT`].
+    ///
+    /// Fails with [`ConfigError::RfcaN`] if `rfca_n` is above 3.
+    #[cfg(feature = "embassy-time")]
+    pub fn set_active_timings(&mut self, timings: ActiveTimings) -> Result<(), Error<I::Error>> {
+        if timings.rfca_n > 3 {
+            return Err(Error::Config(ConfigError::RfcaN));
+        }
+        self.active_timings = timings;
+        Ok(())
+    }
+
+    /// Turnaround measured by the last active mode field switch: from one device's field going
+    /// off to the other's coming on.
+    ///
+    /// `None` if the other device's field didn't come on in time, or before any switch.
+    /// It's measured by polling the interrupts, so it can be late by the polling latency.
+    #[cfg(feature = "embassy-time")]
+    pub fn last_active_turnaround(&self) -> Option<Duration> {
+        self.active_turnaround
+    }
+
+    /// Error interrupts that were pending when the last ISO14443A frame ended, or failed.
+    ///
+    /// A transceive returns the first error it checks for, this has all of them: for example
+    /// whether a CRC error came with parity errors. Cleared at the start of each transceive.
+    pub fn last_error_flags(&self) -> ErrorFlags {
+        self.error_flags
+    }
+
+    /// Counts of the ISO14443A frames sent and the errors they failed with, since the chip was
+    /// initialized or [`reset_health_counters`](Self::reset_health_counters) was called.
+    pub fn health(&self) -> Health {
+        self.health
+    }
+
+    pub fn reset_health_counters(&mut self) {
+        self.health = Health::default();
+    }
+
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    fn tx_streaming(&mut self) -> Result<TxStreaming, Error<I::Error>> {
+        if let Some(tx_streaming) = self.tx_streaming {
+            return Ok(tx_streaming);
+        }
+        let kbps = match self.regs().bit_rate().read()?.txrate() {
+            regs::BitRateE::_106 => 106,
+            regs::BitRateE::_212 => 212,
+            regs::BitRateE::_424 => 424,
+            _ => 848,
+        };
+        Ok(TxStreaming::for_kbps(kbps))
+    }
+
+    fn write_aws(&mut self) -> Result<(), Error<I::Error>> {
+        let (overshoot, undershoot) = self.aws;
+        self.regs().overshoot_conf1().write(|w| {
+            w.set_ov_pattern((overshoot.pattern >> 8) as u8);
+            w.set_ov_tx_mode0(overshoot.tx_mode & 0x01 != 0);
+            w.set_ov_tx_mode1(overshoot.tx_mode & 0x02 != 0);
+        })?;
+        self.regs()
+            .overshoot_conf2()
+            .write(|w| w.set_ov_pattern(overshoot.pattern as u8))?;
+        self.regs().undershoot_conf1().write(|w| {
+            w.set_un_pattern((undershoot.pattern >> 8) as u8);
+            w.set_un_tx_mode0(undershoot.tx_mode & 0x01 != 0);
+            w.set_un_tx_mode1(undershoot.tx_mode & 0x02 != 0);
+        })?;
+        self.regs()
+            .undershoot_conf2()
+            .write(|w| w.set_un_pattern(undershoot.pattern as u8))?;
+
+        // Register overrides win over the configured protection.
+        const AWS_REGISTERS: [Register; 4] = [
+            Register::OvershootConf1,
+            Register::OvershootConf2,
+            Register::UndershootConf1,
+            Register::UndershootConf2,
+        ];
+        for &(reg, val) in self.config.register_overrides {
+            if AWS_REGISTERS.contains(&reg) {
+                self.write_register(reg, val)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Measure the supply voltage (VDD), in millivolts.
+    ///
+    /// The resolution is 23.4 mV. Fails with [`Error::Timeout`] if the measurement doesn't complete.
+    pub async fn measure_supply_voltage(&mut self) -> Result<u16, Error<I::Error>> {
+        // `measure_vdd` overwrites the regulator settings from init, put them back after.
+        let regulator = self.regs().regulator_control().read()?;
+        let res = self.measure_vdd().await;
+        self.regs().regulator_control().write_value(regulator)?;
+        Ok(res? as u16)
+    }
+
+    /// Current receiver gain reduction, as last set by the automatic squelch and AGC.
+    ///
+    /// Read after receiving in a controlled environment to calibrate a value to lock with
+    /// [`set_squelch_reference`](Self::set_squelch_reference).
+    pub fn squelch_reference(&mut self) -> Result<SquelchReference, Error<I::Error>> {
+        let state = self.regs().gain_red_state().read()?;
+        Ok(SquelchReference {
+            am: state.gs_am(),
+            pm: state.gs_pm(),
+        })
+    }
+
+    /// Set the receiver gain reduction manually.
+    ///
+    /// The automatic squelch adjusts on top of it, to lock the value also use `Squelch::Off` or
+    /// `Squelch::Fixed` in the ISO14443A timings. Cleared by [`reset`](Self::reset).
+    ///
+    /// Fails with [`ConfigError::SquelchReference`] if a value is above 15.
+    pub fn set_squelch_reference(&mut self, val: SquelchReference) -> Result<(), Error<I::Error>> {
+        if val.am > 0x0F || val.pm > 0x0F {
+            return Err(Error::Config(ConfigError::SquelchReference));
+        }
+        self.regs().rx_conf4().write(|w| {
+            w.set_rg2_am(val.am);
+            w.set_rg2_pm(val.pm);
+        })?;
         Ok(())
     }
 
     pub async fn measure_amplitude(&mut self) -> Result<u8, Error<I::Error>> {
-        self.cmd_wait(Command::MeasureAmplitude).await?;
+        self.run_command(Command::MeasureAmplitude).await?;
         self.regs().ad_result().read()
     }
 
     pub async fn measure_phase(&mut self) -> Result<u8, Error<I::Error>> {
-        self.cmd_wait(Command::MeasurePhase).await?;
+        self.run_command(Command::MeasurePhase).await?;
         self.regs().ad_result().read()
     }
 
     pub async fn measure_capacitance(&mut self) -> Result<u8, Error<I::Error>> {
-        self.cmd_wait(Command::MeasureCapacitance).await?;
+        self.run_command(Command::MeasureCapacitance).await?;
         self.regs().ad_result().read()
     }
 
@@ -406,14 +1612,14 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
             w.set_cs_g(0b01); // 6.5v/pF, highest one
         })?;
 
-        // Don't use `cmd_wait`, the irq only fires in Ready mode (op_control.en = 1).
+        // Don't use `run_command`, the irq only fires in Ready mode (op_control.en = 1).
         // Instead, wait for cap_sensor_result.cs_cal_end
-        self.cmd(Command::CalibrateCSensor)?;
+        self.cmd(Command::CalibrateCSensor).await?;
 
-        let deadline = Instant::now() + DEFAULT_TIMEOUT;
+        let mut timeout = PollTimeout::new(DEFAULT_TIMEOUT);
 
         let res = loop {
-            if Instant::now() > deadline {
+            if timeout.expired() {
                 return Err(Error::Timeout);
             }
 
@@ -425,14 +1631,21 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
                 break res;
             }
 
-            yield_now().await;
+            self.poll_delay(&mut timeout).await;
         };
         Ok(res.cs_cal_val())
     }
 
-    pub(crate) async fn mode_on(&mut self) -> Result<(), Error<I::Error>> {
+    /// Enable the oscillator, regulators and external field detector, with our field still off.
+    ///
+    /// This is the first step before [`field_on`](Self::field_on). In between, the external
+    /// field can be checked, for example with [`measure_amplitude`](Self::measure_amplitude).
+    /// `start_iso14443a` does both, plus the guard time.
+    pub async fn mode_on(&mut self) -> Result<(), Error<I::Error>> {
         self.mode = Mode::On;
         self.enable_osc().await?;
+        // Wake-up mode masks everything but its own interrupts.
+        self.irq_set_mask(0)?;
 
         self.regs().op_control().modify(|w| {
             w.set_en_fd(regs::OpControlEnFd::AUTO_EFD);
@@ -443,6 +1656,34 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         Ok(())
     }
 
+    /// Go idle between polls: the oscillator and regulators stay on, but our field is off.
+    ///
+    /// It draws more than [`mode_off`](Self::mode_off) but much less than with the field on,
+    /// and wakes up faster: [`resume`](Self::resume) from idle only turns the
+    /// field on and waits for [`FIELD_ON_GUARD_TIME`], while powering up from [`mode_off`](Self::mode_off)
+    /// also waits for the crystal oscillator to start, which takes a few ms depending on the
+    /// crystal. Cards lose their state when the field goes off.
+    pub async fn idle(&mut self) -> Result<(), Error<I::Error>> {
+        match self.mode {
+            Mode::On => self.field_off(),
+            Mode::Off | Mode::Wakeup => {
+                self.cmd(Command::Stop).await?;
+                self.mode_on().await
+            }
+        }
+    }
+
+    /// Turn the field back on after [`idle`](Self::idle), and wait for [`FIELD_ON_GUARD_TIME`]
+    /// so cards are ready for the first frame.
+    pub async fn resume(&mut self) -> Result<(), FieldOnError<I::Error>> {
+        if self.mode != Mode::On {
+            self.mode_on().await?;
+        }
+        self.field_on().await?;
+        self.delay.delay_us(duration_us(FIELD_ON_GUARD_TIME)).await;
+        Ok(())
+    }
+
     /// Change into wakeup mode, return immediately.
     /// The IRQ pin will go high on wakeup.
     pub async fn wait_for_card(&mut self, config: WakeupConfig) -> Result<(), Error<I::Error>> {
@@ -451,9 +1692,10 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         self.mode = Mode::Wakeup;
         debug!("Entering wakeup mode");
 
-        self.cmd(Command::Stop)?;
+        self.cmd(Command::Stop).await?;
         self.regs().op_control().write(|_| {})?;
         self.regs().mode().write(|w| w.set_om(regs::ModeOm::INI_ISO14443A))?;
+        self.frame_om = regs::ModeOm::INI_ISO14443A;
 
         let mut wtc = regs::WupTimerControl(0);
         let mut irqs = 0;
@@ -553,17 +1795,143 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         self.irq_set_mask(!irqs)?;
 
         debug!("Entered wakeup mode, waiting for pin IRQ");
-        self.irq.wait_for_high().await.unwrap();
+        self.irq.wait_for_high().await.map_err(|_| Error::Irq)?;
         debug!("got pin IRQ!");
 
         Ok(())
     }
 
-    async fn field_on(&mut self) -> Result<(), FieldOnError<I::Error>> {
+    /// Read which measurement woke the chip up from [`wait_for_card`](Self::wait_for_card),
+    /// and how far it was from its reference.
+    ///
+    /// Call it after `wait_for_card` returns, before leaving wakeup mode. This doesn't start
+    /// the oscillator, so it's cheap enough to filter out wakes from environmental drift
+    /// before polling. If several measurements went past their delta, the first one of
+    /// amplitude, phase and capacitance is returned. `None` if no measurement woke the chip
+    /// up, for example if the IRQ pin went high for another reason.
+    ///
+    /// Fails with [`ConfigError::NotInWakeupMode`] if not in wakeup mode.
+    pub fn wakeup_result(&mut self) -> Result<Option<WakeupResult>, Error<I::Error>> {
+        if self.mode != Mode::Wakeup {
+            return Err(Error::Config(ConfigError::NotInWakeupMode));
+        }
+        self.irq_update()?;
+
+        let res = if self.irq(Interrupt::Wam) {
+            let auto = self.regs().amplitude_measure_conf().read()?.am_ae();
+            WakeupResult {
+                source: WakeupSource::InductiveAmplitude,
+                measured: self.regs().amplitude_measure_result().read()?,
+                reference: match auto {
+                    true => self.regs().amplitude_measure_aa_result().read()?,
+                    false => self.regs().amplitude_measure_ref().read()?,
+                },
+            }
+        } else if self.irq(Interrupt::Wph) {
+            let auto = self.regs().phase_measure_conf().read()?.pm_ae();
+            WakeupResult {
+                source: WakeupSource::InductivePhase,
+                measured: self.regs().phase_measure_result().read()?,
+                reference: match auto {
+                    true => self.regs().phase_measure_aa_result().read()?,
+                    false => self.regs().phase_measure_ref().read()?,
+                },
+            }
+        } else if self.irq(Interrupt::Wcap) {
+            let auto = self.regs().capacitance_measure_conf().read()?.cm_ae();
+            WakeupResult {
+                source: WakeupSource::Capacitive,
+                measured: self.regs().capacitance_measure_result().read()?,
+                reference: match auto {
+                    true => self.regs().capacitance_measure_aa_result().read()?,
+                    false => self.regs().capacitance_measure_ref().read()?,
+                },
+            }
+        } else {
+            return Ok(None);
+        };
+
+        debug!("wakeup result: {:?}, delta {}", res, res.delta());
+        Ok(Some(res))
+    }
+
+    /// Sleep in wakeup mode until it's time to emulate a card, then return what woke it up.
+    ///
+    /// The chip wakes up by itself, on its timer or on a reader's field, and raises the IRQ pin,
+    /// so the MCU can sleep in the meantime. Only the detector and the timer run, with the
+    /// oscillator off. Start emulating with [`start_listen_a`](Self::start_listen_a) afterwards.
+    ///
+    /// The field detector only fires when the field comes on, if a reader's field is already on
+    /// when this is called it's only noticed at the next timer period, if any.
+    ///
+    /// Fails with [`ConfigError::NoWakeupSource`] if `config` has neither a period nor the
+    /// external field enabled.
+    pub async fn wait_for_reader(&mut self, config: ListenWakeupConfig) -> Result<ListenWakeupCause, Error<I::Error>> {
+        if config.period.is_none() && !config.external_field {
+            return Err(Error::Config(ConfigError::NoWakeupSource));
+        }
+        self.mode_on().await?;
+
+        self.mode = Mode::Wakeup;
+        debug!("Entering wakeup mode for listen");
+
+        self.cmd(Command::Stop).await?;
+        self.regs().op_control().write(|_| {})?;
+
+        let mut wtc = regs::WupTimerControl(0);
+        let mut irqs = 0;
+        if let Some(period) = config.period {
+            wtc.set_wur(period as u8 & 0x10 == 0);
+            wtc.set_wut(period as u8 & 0x0F);
+            // Interrupt on each timeout, with no measurement enabled that's all it does.
+            wtc.set_wto(true);
+            irqs |= 1 << Interrupt::Wt as u32;
+        }
+        if config.external_field {
+            irqs |= 1 << Interrupt::Eon as u32;
+        }
+
+        self.irq_clear()?;
+
+        self.regs().wup_timer_control().write_value(wtc)?;
+        self.regs().op_control().write(|w| {
+            w.set_wu(config.period.is_some());
+            if config.external_field {
+                w.set_en_fd(regs::OpControlEnFd::AUTO_EFD);
+            }
+        })?;
+        self.irq_set_mask(!irqs)?;
+
+        debug!("Entered wakeup mode, waiting for pin IRQ");
+        self.irq.wait_for_high().await.map_err(|_| Error::Irq)?;
+        self.irq_update()?;
+        let cause = match self.irq(Interrupt::Eon) {
+            true => ListenWakeupCause::ExternalField,
+            false => ListenWakeupCause::Timer,
+        };
+        self.irqs = 0;
+        debug!("woke up for listen: {:?}", cause);
+
+        Ok(cause)
+    }
+
+    /// Turn our field on for ISO14443A polling, with initial RF collision avoidance.
+    ///
+    /// Requires [`mode_on`](Self::mode_on) first. Cards need [`FIELD_ON_GUARD_TIME`] after
+    /// the field comes on before the first frame, which is left to the caller. Fails with
+    /// [`FieldOnError::FieldCollision`] if another device's field is on, see
+    /// [`set_collision_avoidance`](Self::set_collision_avoidance), and with
+    /// [`ConfigError::FieldRampStart`] if [`Config::field_ramp`] is out of range.
+    pub async fn field_on(&mut self) -> Result<(), FieldOnError<I::Error>> {
+        let ramp = self.config.field_ramp;
+        if ramp.is_some_and(|ramp| !(1..15).contains(&ramp.start_d_res)) {
+            return Err(FieldOnError::Config(ConfigError::FieldRampStart));
+        }
         self.regs().mode().write(|w| {
             w.set_om(regs::ModeOm::INI_ISO14443A);
             w.set_tr_am(false); // use OOK
         })?;
+        self.frame_om = regs::ModeOm::INI_ISO14443A;
         self.regs().tx_driver().write(|w| {
             w.set_am_mod(regs::TxDriverAmMod::_12PERCENT);
         })?;
@@ -573,11 +1941,8 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
             w.set_res_am(false);
         })?;
 
-        // Default over/under shoot protiection
-        self.regs().overshoot_conf1().write_value(0x40.into())?;
-        self.regs().overshoot_conf2().write_value(0x03.into())?;
-        self.regs().undershoot_conf1().write_value(0x40.into())?;
-        self.regs().undershoot_conf2().write_value(0x03.into())?;
+        // Over/under shoot protection
+        self.write_aws()?;
 
         self.regs().aux().write(|w| {
             w.set_dis_corr(false); // Enable correlator reception
@@ -605,33 +1970,109 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         // GT is done by software
         self.regs().field_on_gt().write_value(0)?;
 
+        if let Some(ca) = self.collision_avoidance {
+            let delay_us = ca.random_delay_us(ca.max_startup_delay);
+            self.delay.delay_us(delay_us).await;
+        }
+        if let Some(ramp) = ramp {
+            self.regs().tx_driver().modify(|w| w.set_d_res(ramp.start_d_res))?;
+        }
+
+        let mut retries = 0;
+        while !self.initial_rf_collision().await? {
+            let Some(ca) = self.collision_avoidance else {
+                return Err(FieldOnError::FieldCollision);
+            };
+            if retries >= ca.retries {
+                debug!("external field still present after {} retries", retries);
+                return Err(FieldOnError::FieldCollision);
+            }
+            retries += 1;
+
+            let backoff_us = ca.random_delay_us(ca.max_backoff);
+            debug!("external field present, retrying in {} us", backoff_us);
+            self.delay.delay_us(backoff_us).await;
+        }
+
+        self.regs().op_control().modify(|w| {
+            w.set_tx_en(true);
+            w.set_rx_en(true);
+        })?;
+        if let Some(ramp) = ramp {
+            for d_res in (0..ramp.start_d_res).rev() {
+                self.delay.delay_us(duration_us(ramp.step_time)).await;
+                self.regs().tx_driver().modify(|w| w.set_d_res(d_res))?;
+            }
+        }
+        self.irq_set_mask(!self.transceive_irqs().0)?;
+        #[cfg(feature = "embassy-time")]
+        {
+            self.field_on_since = Some(Instant::now());
+        }
+
+        Ok(())
+    }
+
+    /// Turn the field on if there's no external field. Returns false if there is.
+    async fn initial_rf_collision(&mut self) -> Result<bool, Error<I::Error>> {
         self.irq_clear()?; // clear
-        self.cmd(Command::InitialRfCollision)?;
+        self.cmd(Command::InitialRfCollision).await?;
 
         loop {
             if self.irq(Interrupt::Cac) {
-                return Err(FieldOnError::FieldCollision);
+                return Ok(false);
             }
             if self.irq(Interrupt::Apon) {
-                break;
+                return Ok(true);
             }
 
             self.irq_update()?;
         }
+    }
 
+    /// Turn our field off, keeping the mode on so it can be turned back on quickly.
+    ///
+    /// Cards lose their state when the field goes off. See [`mode_off`](Self::mode_off) to
+    /// turn everything off.
+    pub fn field_off(&mut self) -> Result<(), Error<I::Error>> {
+        self.cmd_blocking(Command::Stop)?;
         self.regs().op_control().modify(|w| {
-            w.set_tx_en(true);
-            w.set_rx_en(true);
+            w.set_tx_en(false);
+            w.set_rx_en(false);
         })?;
-
+        #[cfg(feature = "embassy-time")]
+        {
+            self.field_on_since = None;
+        }
         Ok(())
     }
 
+    /// Enforce [`Config::field_watchdog`]: cycle the field if it has been on for too long.
+    /// Returns whether it was cycled.
+    #[cfg(feature = "embassy-time")]
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    async fn field_watchdog(&mut self) -> Result<bool, FieldOnError<I::Error>> {
+        let (Some(wd), Some(since)) = (self.config.field_watchdog, self.field_on_since) else {
+            return Ok(false);
+        };
+        if Duration::from(since.elapsed()) <= wd.max_on {
+            return Ok(false);
+        }
+
+        warn!("field on for more than {} ms, turning it off", wd.max_on.as_millis());
+        self.field_off()?;
+        self.delay.delay_us(duration_us(wd.off_time)).await;
+
+        self.field_on().await?;
+        self.delay.delay_us(duration_us(FIELD_ON_GUARD_TIME)).await;
+        Ok(true)
+    }
+
     async fn measure_vdd(&mut self) -> Result<u32, Error<I::Error>> {
         self.regs()
             .regulator_control()
             .write(|w| w.set_mpsv(regs::RegulatorControlMpsv::VDD))?;
-        self.cmd_wait(Command::MeasureVdd).await?;
+        self.run_command(Command::MeasureVdd).await?;
         let res = self.regs().ad_result().read()? as u32;
 
         // result is in units of 23.4mV
@@ -646,18 +2087,67 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
     }
 
     async fn irq_wait_timeout(&mut self, irq: Interrupt, timeout: Duration) -> Result<(), Error<I::Error>> {
-        let deadline = Instant::now() + timeout;
+        let mut timeout = PollTimeout::new(timeout);
         self.irq_update()?;
         while !self.irq(irq) {
-            if Instant::now() > deadline {
+            if timeout.expired() {
                 return Err(Error::Timeout);
             }
-            yield_now().await;
+            self.poll_delay(&mut timeout).await;
             self.irq_update()?;
         }
         Ok(())
     }
 
+    /// Like [`irq_wait_timeout`](Self::irq_wait_timeout), following [`Config::poll_strategy`].
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    async fn irq_wait_critical(&mut self, irq: Interrupt, timeout: Duration) -> Result<(), Error<I::Error>> {
+        let mut timeout = PollTimeout::new(timeout);
+        let mut spins = self.busy_poll_spins();
+        self.irq_update_critical()?;
+        while !self.irq(irq) {
+            if timeout.expired() {
+                return Err(Error::Timeout);
+            }
+            self.irq_poll(&mut spins, &mut timeout).await?;
+        }
+        Ok(())
+    }
+
+    /// Iterations left to busy-poll for during a critical wait.
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    fn busy_poll_spins(&self) -> u32 {
+        match self.config.poll_strategy {
+            PollStrategy::Yield => 0,
+            PollStrategy::BusyPoll { spins } => spins,
+        }
+    }
+
+    /// Update irqs, waiting a poll interval first once `spins` is used up.
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    async fn irq_poll(&mut self, spins: &mut u32, timeout: &mut PollTimeout) -> Result<(), Error<I::Error>> {
+        if *spins > 0 {
+            *spins -= 1;
+        } else {
+            self.poll_delay(timeout).await;
+        }
+        self.irq_update_critical()
+    }
+
+    /// Let other tasks run between two polls, see [`PollTimeout`].
+    async fn poll_delay(&mut self, timeout: &mut PollTimeout) {
+        #[cfg(feature = "embassy-time")]
+        {
+            let _ = timeout;
+            yield_now().await;
+        }
+        #[cfg(not(feature = "embassy-time"))]
+        {
+            self.delay.delay_us(POLL_INTERVAL_US).await;
+            timeout.remaining_us = timeout.remaining_us.saturating_sub(POLL_COST_US);
+        }
+    }
+
     async fn irq_wait(&mut self, irq: Interrupt) -> Result<(), Error<I::Error>> {
         self.irq_wait_timeout(irq, DEFAULT_TIMEOUT).await
     }
@@ -669,6 +2159,23 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         Ok(())
     }
 
+    /// Like [`irq_update`](Self::irq_update), only reading the registers with interrupts in
+    /// [`Config::transceive_irqs`].
+    #[cfg_attr(not(feature = "iso14443a"), allow(unused))]
+    fn irq_update_critical(&mut self) -> Result<(), Error<I::Error>> {
+        let enabled = self.transceive_irqs();
+        for i in 0..4 {
+            if enabled.has_register(i) {
+                self.irqs |= (self.regs().irq_main(i).read()? as u32) << (i * 8);
+            }
+        }
+        Ok(())
+    }
+
+    fn transceive_irqs(&self) -> IrqMask {
+        IrqMask(self.config.transceive_irqs.0 | IrqMask::TRANSCEIVE.0)
+    }
+
     fn irq_clear(&mut self) -> Result<(), Error<I::Error>> {
         self.irq_update()?;
         self.irqs = 0;
@@ -682,16 +2189,16 @@ impl<I: Interface, IrqPin: InputPin + Wait> St25r39<I, IrqPin> {
         Ok(())
     }
 
-    pub fn raw(&mut self) -> Raw<'_, I, IrqPin> {
+    pub fn raw(&mut self) -> Raw<'_, I, IrqPin, D> {
         Raw { inner: self }
     }
 }
 
-pub struct Raw<'a, I: Interface, IrqPin: InputPin + Wait> {
-    inner: &'a mut St25r39<I, IrqPin>,
+pub struct Raw<'a, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> {
+    inner: &'a mut St25r39<I, IrqPin, D>,
 }
 
-impl<'a, I: Interface, IrqPin: InputPin + Wait> Raw<'a, I, IrqPin> {
+impl<'a, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> Raw<'a, I, IrqPin, D> {
     pub async fn field_on(&mut self) -> Result<(), FieldOnError<I::Error>> {
         self.inner.mode_on().await?;
         self.inner.field_on().await?;
@@ -701,6 +2208,73 @@ impl<'a, I: Interface, IrqPin: InputPin + Wait> Raw<'a, I, IrqPin> {
         self.inner.mode_off()?;
         Ok(())
     }
+
+    /// Turn our field off and wait for the other device to turn its own on, as in NFC-DEP
+    /// active mode after sending a frame.
+    ///
+    /// Returns the measured turnaround, or `None` if no field came on within
+    /// [`ActiveTimings::field_wait`]. See [`St25r39::last_active_turnaround`]. Needs the
+    /// `embassy-time` feature, for measuring the turnaround.
+    #[cfg(feature = "embassy-time")]
+    pub async fn active_field_off(&mut self) -> Result<Option<Duration>, Error<I::Error>> {
+        let this = &mut *self.inner;
+        this.irq_clear()?;
+        this.regs().op_control().modify(|w| w.set_tx_en(false))?;
+        this.field_on_since = None;
+
+        let off_at = Instant::now();
+        let turnaround = match this.irq_wait_timeout(Interrupt::Eon, this.active_timings.field_wait).await {
+            Ok(()) => Some(Duration::from(off_at.elapsed())),
+            Err(Error::Timeout) => None,
+            Err(e) => return Err(e),
+        };
+        debug!("active: field off, turnaround {:?} us", turnaround.map(|t| t.as_micros()));
+        this.active_turnaround = turnaround;
+        Ok(turnaround)
+    }
+
+    /// Wait for the other device to turn its field off, then turn ours on with response RF
+    /// collision avoidance and wait the guard time, as in NFC-DEP active mode before sending a frame.
+    ///
+    /// The chip must already be in an NFCIP-1 active mode. Returns the measured turnaround, see
+    /// [`St25r39::last_active_turnaround`]. Fails with [`FieldOnError::FieldCollision`] if another
+    /// field came on first. Needs the `embassy-time` feature, for measuring the turnaround.
+    #[cfg(feature = "embassy-time")]
+    pub async fn active_field_on(&mut self) -> Result<Duration, FieldOnError<I::Error>> {
+        let this = &mut *self.inner;
+        let timings = this.active_timings;
+        this.regs().aux().modify(|w| w.set_nfc_n(timings.rfca_n))?;
+        this.irq_clear()?;
+        this.cmd(Command::ResponseRfCollisionN).await?;
+
+        let mut timeout = PollTimeout::new(DEFAULT_TIMEOUT);
+        let mut off_at = None;
+        let turnaround = loop {
+            this.irq_update()?;
+            if off_at.is_none() && this.irq(Interrupt::Eof) {
+                off_at = Some(Instant::now());
+            }
+            if this.irq(Interrupt::Cac) {
+                this.active_turnaround = None;
+                return Err(FieldOnError::FieldCollision);
+            }
+            if this.irq(Interrupt::Apon) {
+                // The external field was already off if we missed its interrupt.
+                break off_at.map(|t: Instant| t.elapsed().into()).unwrap_or(Duration::ZERO);
+            }
+            if timeout.expired() {
+                return Err(FieldOnError::Timeout);
+            }
+            this.poll_delay(&mut timeout).await;
+        };
+        this.field_on_since = Some(Instant::now());
+        this.active_turnaround = Some(turnaround);
+        debug!("active: field on, turnaround {} us", turnaround.as_micros());
+
+        this.delay.delay_us(duration_us(timings.guard_time)).await;
+        Ok(turnaround)
+    }
+
     pub async fn driver_hi_z(&mut self) -> Result<(), Error<I::Error>> {
         self.inner.mode_off()?;
         self.inner.regs().tx_driver().write(|w| {
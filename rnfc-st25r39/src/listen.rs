@@ -0,0 +1,341 @@
+#[cfg(feature = "embassy-time")]
+use embassy_time::Instant;
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use heapless::Vec;
+use rnfc_traits::iso14443a_target::{Identity, Target};
+
+use crate::fmt::Bytes;
+use crate::iso14443a::Error;
+use crate::*;
+
+/// Length of the NFC-A part of the passive target memory.
+const PT_MEMORY_A_LEN: usize = 15;
+/// Offset of SENS_RES in the passive target memory. NFCID1 is at the start.
+const PT_MEMORY_SENS_RES: usize = 10;
+/// Offset of the SEL_RES bytes in the passive target memory, one per cascade level.
+const PT_MEMORY_SEL_RES: usize = 12;
+/// SAK bit indicating the UID is not complete yet.
+const SAK_CASCADE: u8 = 0x04;
+/// SAK bit advertising ISO-DEP (ISO14443-4) support.
+const SAK_ISO_DEP: u8 = 0x20;
+/// First byte of a random UID (RID).
+#[cfg(feature = "embassy-time")]
+const RANDOM_UID_PREFIX: u8 = 0x08;
+/// Longest UID the chip can emulate.
+const UID_MAX_LEN: usize = 7;
+
+/// Emulated UID, see [`EmulationConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EmulationUid<'a> {
+    /// This UID, 4 or 7 bytes.
+    Fixed(&'a [u8]),
+    /// A single-size random UID (RID), 0x08 then 3 random bytes, generated anew on each
+    /// activation like a real card with RIDs. The bytes are derived from the time, not
+    /// suitable for anything security-related. Needs the `embassy-time` feature, for the clock.
+    #[cfg(feature = "embassy-time")]
+    Random,
+}
+
+/// Activation parameters of an emulated NFC-A tag, see [`ListenA::listen_emulated`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EmulationConfig<'a> {
+    pub uid: EmulationUid<'a>,
+    /// ATQA (SENS_RES). Its UID size bits must match the UID.
+    pub atqa: [u8; 2],
+    /// SAK (SEL_RES). The ISO-DEP bit is set by [`iso_dep`](Self::iso_dep), the cascade bit
+    /// by the chip.
+    pub sak: u8,
+    /// Advertise ISO-DEP support in the SAK.
+    pub iso_dep: bool,
+}
+
+pub use crate::Bitrate;
+
+impl Bitrate {
+    fn from_nfc_rate(val: u8) -> Self {
+        match val & 0x03 {
+            0 => Self::Kbps106,
+            1 => Self::Kbps212,
+            2 => Self::Kbps424,
+            _ => Self::Kbps848,
+        }
+    }
+}
+
+/// An ST25 chip enabled in NFC-A listen mode, emulating a card. See [`Target`].
+///
+/// The chip answers anticollision by itself, frames from readers polling for other
+/// technologies are ignored.
+///
+/// The chip listens in bitrate detection mode: it recognizes the bitrate of the first frame from
+/// the reader, and responses are sent at that same rate. See [`bitrate`](Self::bitrate).
+pub struct ListenA<'d, I: Interface, IrqPin: InputPin + Wait, D: DelayNs> {
+    inner: &'d mut St25r39<I, IrqPin, D>,
+    bitrate: Option<Bitrate>,
+}
+
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> St25r39<I, IrqPin, D> {
+    pub async fn start_listen_a(&mut self) -> Result<ListenA<'_, I, IrqPin, D>, Error<I::Error>> {
+        self.mode_on().await?;
+        self.cmd(Command::Stop).await?;
+
+        self.regs().bit_rate().write(|w| {
+            w.set_rxrate(regs::BitRateE::_106);
+            w.set_txrate(regs::BitRateE::_106);
+        })?;
+        // Automatic anticollision for NFC-A only.
+        self.regs().passive_target().write(|w| {
+            w.set_d_106_ac_a(false);
+            w.set_d_212_424_1r(true);
+            w.set_d_ac_ap2p(true);
+        })?;
+        // Respond with load modulation.
+        self.regs().aux_mod().write(|w| {
+            w.set_lm_dri(true);
+            w.set_lm_ext(false);
+        })?;
+        self.regs().iso14443a_nfc().write(|_| {})?;
+        self.regs().aux().write(|_| {})?;
+
+        self.irq_set_mask(0)?;
+        self.regs().op_control().modify(|w| {
+            w.set_rx_en(true);
+            w.set_tx_en(false);
+        })?;
+
+        Ok(ListenA {
+            inner: self,
+            bitrate: None,
+        })
+    }
+}
+
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> ListenA<'_, I, IrqPin, D> {
+    /// Bitrate the reader in the field is using, `None` if it hasn't sent anything yet.
+    ///
+    /// Detection restarts on each [`listen`](Target::listen), since the next reader may use another rate.
+    pub fn bitrate(&self) -> Option<Bitrate> {
+        self.bitrate
+    }
+
+    /// Wait for a reader to select us, answering anticollision as configured in `config`.
+    /// Returns the UID that was presented, which is new each time with `EmulationUid::Random`.
+    ///
+    /// This is [`listen`](Target::listen) with the activation parameters spelled out, for
+    /// presenting tags with specific characteristics to a reader under test.
+    ///
+    /// Fails with [`ConfigError::UidLength`] if the UID isn't 4 or 7 bytes, and with
+    /// [`ConfigError::AtqaUidSize`] if the UID size bits in the ATQA don't match it.
+    pub async fn listen_emulated(&mut self, config: &EmulationConfig<'_>) -> Result<Vec<u8, UID_MAX_LEN>, Error<I::Error>> {
+        let uid = match config.uid {
+            EmulationUid::Fixed(uid) => {
+                if uid.len() != 4 && uid.len() != 7 {
+                    return Err(Error::Config(ConfigError::UidLength));
+                }
+                Vec::from_slice(uid).unwrap()
+            }
+            #[cfg(feature = "embassy-time")]
+            EmulationUid::Random => {
+                let mut uid = Vec::new();
+                uid.push(RANDOM_UID_PREFIX).unwrap();
+                uid.extend_from_slice(&random_bytes()).unwrap();
+                uid
+            }
+        };
+        // ATQA bits 7-6: 0 for single size, 1 for double.
+        let atqa_uid_len = match config.atqa[0] >> 6 {
+            0 => 4,
+            1 => 7,
+            _ => 10,
+        };
+        if atqa_uid_len != uid.len() {
+            warn!("ATQA says the UID is {} bytes, but it's {}", atqa_uid_len, uid.len());
+            return Err(Error::Config(ConfigError::AtqaUidSize));
+        }
+
+        let sak = match config.iso_dep {
+            true => config.sak | SAK_ISO_DEP,
+            false => config.sak & !SAK_ISO_DEP,
+        };
+        let identity = Identity {
+            uid: &uid,
+            atqa: config.atqa,
+            sak,
+        };
+        self.listen(&identity).await?;
+        Ok(uid)
+    }
+
+    /// Handle the "bitrate recognised" interrupt, if pending: read the detected rate and answer at it.
+    fn update_bitrate(&mut self) -> Result<(), Error<I::Error>> {
+        let this = &mut *self.inner;
+        if !this.irq(Interrupt::Nfct) {
+            return Ok(());
+        }
+        this.irqs &= !(1 << (Interrupt::Nfct as u8));
+
+        let bitrate = Bitrate::from_nfc_rate(this.regs().nfcip1_bit_rate().read()?.nfc_rate());
+        this.regs().bit_rate().write(|w| {
+            w.set_rxrate(bitrate.to_reg());
+            w.set_txrate(bitrate.to_reg());
+        })?;
+        debug!("listen: detected bitrate {:?}", bitrate);
+        self.bitrate = Some(bitrate);
+        Ok(())
+    }
+}
+
+/// 3 bytes for a random UID, hashed from the current time.
+#[cfg(feature = "embassy-time")]
+fn random_bytes() -> [u8; 3] {
+    // splitmix64 finalizer, spreads the few changing low bits of the tick count over the output.
+    let mut x = Instant::now().as_ticks();
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^= x >> 31;
+    let b = x.to_le_bytes();
+    [b[0], b[1], b[2]]
+}
+
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> Drop for ListenA<'_, I, IrqPin, D> {
+    fn drop(&mut self) {
+        if self.inner.mode_off().is_err() {
+            warn!("Failed to turn off on ListenA drop");
+        }
+    }
+}
+
+impl<'d, I: Interface + 'd, IrqPin: InputPin + Wait + 'd, D: DelayNs + 'd> Target for ListenA<'d, I, IrqPin, D> {
+    type Error = Error<I::Error>;
+
+    async fn listen(&mut self, identity: &Identity<'_>) -> Result<(), Self::Error> {
+        self.bitrate = None;
+        let this = &mut *self.inner;
+
+        let nfc_id = match identity.uid.len() {
+            4 => regs::AuxNfcId::_4BYTES,
+            7 => regs::AuxNfcId::_7BYTES,
+            n => {
+                warn!("unsupported UID length {}, must be 4 or 7", n);
+                return Err(Error::Config(ConfigError::UidLength));
+            }
+        };
+
+        let mut mem = [0; PT_MEMORY_A_LEN];
+        mem[..identity.uid.len()].copy_from_slice(identity.uid);
+        mem[PT_MEMORY_SENS_RES..][..2].copy_from_slice(&identity.atqa);
+        let sak = identity.sak & !SAK_CASCADE;
+        if identity.uid.len() == 7 {
+            mem[PT_MEMORY_SEL_RES] = sak | SAK_CASCADE;
+            mem[PT_MEMORY_SEL_RES + 1] = sak;
+        } else {
+            mem[PT_MEMORY_SEL_RES] = sak;
+            mem[PT_MEMORY_SEL_RES + 1] = sak;
+        }
+        this.iface.write_pt_memory(&mem)?;
+        this.regs().aux().write(|w| w.set_nfc_id(nfc_id))?;
+        // After recognising the bitrate, the chip switches the mode to the detected technology
+        // by itself, so set it back for the next reader.
+        this.regs().mode().write(|w| {
+            w.set_targ(true);
+            w.set_om(regs::ModeOm::TARG_BITRATE_DETECTION);
+        })?;
+        this.frame_om = regs::ModeOm::INI_ISO14443A;
+
+        this.irq_clear()?;
+        this.cmd(Command::GotoSense).await?;
+        debug!("listen: waiting for activation, uid={}", Bytes(identity.uid));
+
+        loop {
+            self.update_bitrate()?;
+            // Active after SELECT from the Idle state or, after HLTA, from the Halt state.
+            if self.inner.irq(Interrupt::WuA) || self.inner.irq(Interrupt::WuAX) {
+                debug!("listen: activated");
+                return Ok(());
+            }
+            yield_now().await;
+            self.inner.irq_update()?;
+        }
+    }
+
+    async fn receive(&mut self, rx: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        loop {
+            self.inner.irq_update()?;
+            self.update_bitrate()?;
+            let this = &mut *self.inner;
+            if this.irq(Interrupt::Eof) {
+                debug!("listen: field off");
+                this.irqs = 0;
+                return Ok(None);
+            }
+            if !this.irq(Interrupt::Rxe) {
+                yield_now().await;
+                continue;
+            }
+
+            let corrupted = this.irq(Interrupt::Err1) || this.irq(Interrupt::Par) || this.irq(Interrupt::Crc);
+            this.irqs = 0;
+
+            let stat = this.fifo_status()?;
+            let corrupted = corrupted || !this.fifo_count_valid(&stat)?;
+            if corrupted || stat.byte_count < 2 || stat.byte_count - 2 > rx.len() {
+                debug!("listen: ignoring bad frame, {} bytes", stat.byte_count);
+                this.cmd(Command::ClearFifo).await?;
+                continue;
+            }
+
+            // Remove received CRC
+            let n = stat.byte_count - 2;
+            this.read_fifo(&mut rx[..n]).await?;
+            this.cmd(Command::ClearFifo).await?;
+            debug!("listen RX: {:02x}", Bytes(&rx[..n]));
+
+            if rx[..n] == [0x50, 0x00] {
+                debug!("listen: HLTA");
+                this.cmd(Command::GotoSleep).await?;
+                return Ok(None);
+            }
+            return Ok(Some(n));
+        }
+    }
+
+    async fn send(&mut self, tx: &[u8]) -> Result<(), Self::Error> {
+        let this = &mut *self.inner;
+        debug!("listen TX: {:02x}", Bytes(tx));
+        // Responses aren't streamed, they must fit in the FIFO.
+        if tx.len() > FIFO_SIZE {
+            return Err(Error::TxTooLong { max_len: FIFO_SIZE });
+        }
+
+        let bits = tx.len() * 8;
+        this.regs().num_tx_bytes2().write_value((bits as u8).into())?;
+        this.regs().num_tx_bytes1().write_value((bits >> 8) as u8)?;
+        this.write_fifo(tx).await?;
+        this.irqs = 0;
+        this.cmd(Command::TransmitWithCrc).await?;
+        this.irq_wait(Interrupt::Txe).await?;
+        Ok(())
+    }
+
+    async fn send_short(&mut self, nibble: u8) -> Result<(), Self::Error> {
+        let this = &mut *self.inner;
+        debug!("listen TX: {:x} (4 bits)", nibble);
+
+        this.regs().num_tx_bytes2().write_value(4.into())?;
+        this.regs().num_tx_bytes1().write_value(0)?;
+        this.write_fifo(&[nibble & 0x0F]).await?;
+        this.irqs = 0;
+        this.cmd(Command::TransmitWithoutCrc).await?;
+        this.irq_wait(Interrupt::Txe).await?;
+        Ok(())
+    }
+
+    async fn sleep(&mut self) -> Result<(), Self::Error> {
+        self.inner.cmd(Command::GotoSleep).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+/// Register space, see [`Register::space`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegisterSpace {
+    A,
+    B,
+    Test,
+}
+
+/// A chip register, for [`Config::register_overrides`](crate::Config::register_overrides) and
+/// [`St25r39::read_register`](crate::St25r39::read_register).
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Register {
+    IoConf1 = 0,
+    IoConf2 = 1,
+    OpControl = 2,
+    Mode = 3,
+    BitRate = 4,
+    Iso14443aNfc = 5,
+    Iso14443b1 = 6,
+    Iso14443b2 = 7,
+    PassiveTarget = 8,
+    StreamMode = 9,
+    Aux = 10,
+    RxConf1 = 11,
+    RxConf2 = 12,
+    RxConf3 = 13,
+    RxConf4 = 14,
+    MaskRxTimer = 15,
+    NoResponseTimer1 = 16,
+    NoResponseTimer2 = 17,
+    TimerEmvControl = 18,
+    Gpt1 = 19,
+    Gpt2 = 20,
+    Ppon2 = 21,
+    IrqMaskMain = 22,
+    IrqMaskTimerNfc = 23,
+    IrqMaskErrorWup = 24,
+    IrqMaskTarget = 25,
+    IrqMain = 26,
+    IrqTimerNfc = 27,
+    IrqErrorWup = 28,
+    IrqTarget = 29,
+    FifoStatus1 = 30,
+    FifoStatus2 = 31,
+    CollisionStatus = 32,
+    PassiveTargetStatus = 33,
+    NumTxBytes1 = 34,
+    NumTxBytes2 = 35,
+    Nfcip1BitRate = 36,
+    AdResult = 37,
+    AntTuneA = 38,
+    AntTuneB = 39,
+    TxDriver = 40,
+    PtMod = 41,
+    FieldThresholdActv = 42,
+    FieldThresholdDeactv = 43,
+    RegulatorControl = 44,
+    RssiResult = 45,
+    GainRedState = 46,
+    CapSensorControl = 47,
+    CapSensorResult = 48,
+    AuxDisplay = 49,
+    WupTimerControl = 50,
+    AmplitudeMeasureConf = 51,
+    AmplitudeMeasureRef = 52,
+    AmplitudeMeasureAaResult = 53,
+    AmplitudeMeasureResult = 54,
+    PhaseMeasureConf = 55,
+    PhaseMeasureRef = 56,
+    PhaseMeasureAaResult = 57,
+    PhaseMeasureResult = 58,
+    CapacitanceMeasureConf = 59,
+    CapacitanceMeasureRef = 60,
+    CapacitanceMeasureAaResult = 61,
+    CapacitanceMeasureResult = 62,
+    IcIdentity = 63,
+    EmdSupConf = 69,
+    SubcStartTime = 70,
+    P2pRxConf = 75,
+    CorrConf1 = 76,
+    CorrConf2 = 77,
+    SquelchTimer = 79,
+    FieldOnGt = 85,
+    AuxMod = 104,
+    TxDriverTiming = 105,
+    ResAmMod = 106,
+    TxDriverStatus = 107,
+    RegulatorResult = 108,
+    OvershootConf1 = 112,
+    OvershootConf2 = 113,
+    UndershootConf1 = 114,
+    UndershootConf2 = 115,
+    TestUnk = 132,
+}
+
+impl Register {
+    /// All registers, in address order.
+    pub const ALL: [Self; 81] = [
+        Self::IoConf1,
+        Self::IoConf2,
+        Self::OpControl,
+        Self::Mode,
+        Self::BitRate,
+        Self::Iso14443aNfc,
+        Self::Iso14443b1,
+        Self::Iso14443b2,
+        Self::PassiveTarget,
+        Self::StreamMode,
+        Self::Aux,
+        Self::RxConf1,
+        Self::RxConf2,
+        Self::RxConf3,
+        Self::RxConf4,
+        Self::MaskRxTimer,
+        Self::NoResponseTimer1,
+        Self::NoResponseTimer2,
+        Self::TimerEmvControl,
+        Self::Gpt1,
+        Self::Gpt2,
+        Self::Ppon2,
+        Self::IrqMaskMain,
+        Self::IrqMaskTimerNfc,
+        Self::IrqMaskErrorWup,
+        Self::IrqMaskTarget,
+        Self::IrqMain,
+        Self::IrqTimerNfc,
+        Self::IrqErrorWup,
+        Self::IrqTarget,
+        Self::FifoStatus1,
+        Self::FifoStatus2,
+        Self::CollisionStatus,
+        Self::PassiveTargetStatus,
+        Self::NumTxBytes1,
+        Self::NumTxBytes2,
+        Self::Nfcip1BitRate,
+        Self::AdResult,
+        Self::AntTuneA,
+        Self::AntTuneB,
+        Self::TxDriver,
+        Self::PtMod,
+        Self::FieldThresholdActv,
+        Self::FieldThresholdDeactv,
+        Self::RegulatorControl,
+        Self::RssiResult,
+        Self::GainRedState,
+        Self::CapSensorControl,
+        Self::CapSensorResult,
+        Self::AuxDisplay,
+        Self::WupTimerControl,
+        Self::AmplitudeMeasureConf,
+        Self::AmplitudeMeasureRef,
+        Self::AmplitudeMeasureAaResult,
+        Self::AmplitudeMeasureResult,
+        Self::PhaseMeasureConf,
+        Self::PhaseMeasureRef,
+        Self::PhaseMeasureAaResult,
+        Self::PhaseMeasureResult,
+        Self::CapacitanceMeasureConf,
+        Self::CapacitanceMeasureRef,
+        Self::CapacitanceMeasureAaResult,
+        Self::CapacitanceMeasureResult,
+        Self::IcIdentity,
+        Self::EmdSupConf,
+        Self::SubcStartTime,
+        Self::P2pRxConf,
+        Self::CorrConf1,
+        Self::CorrConf2,
+        Self::SquelchTimer,
+        Self::FieldOnGt,
+        Self::AuxMod,
+        Self::TxDriverTiming,
+        Self::ResAmMod,
+        Self::TxDriverStatus,
+        Self::RegulatorResult,
+        Self::OvershootConf1,
+        Self::OvershootConf2,
+        Self::UndershootConf1,
+        Self::UndershootConf2,
+        Self::TestUnk,
+    ];
+
+    /// Register address, with space B registers offset by 0x40 and test registers by 0x80.
+    pub fn addr(self) -> u8 {
+        self as u8
+    }
+
+    /// Space the register is in.
+    pub fn space(self) -> RegisterSpace {
+        match self.addr() {
+            0x00..=0x3F => RegisterSpace::A,
+            0x40..=0x7F => RegisterSpace::B,
+            _ => RegisterSpace::Test,
+        }
+    }
+
+    /// Whether the register can be written. Interrupt, status and measurement result registers
+    /// are read-only: the interrupt registers are cleared by reading them.
+    pub fn is_writable(self) -> bool {
+        !matches!(
+            self,
+            Self::IrqMain
+                | Self::IrqTimerNfc
+                | Self::IrqErrorWup
+                | Self::IrqTarget
+                | Self::FifoStatus1
+                | Self::FifoStatus2
+                | Self::CollisionStatus
+                | Self::PassiveTargetStatus
+                | Self::Nfcip1BitRate
+                | Self::AdResult
+                | Self::RssiResult
+                | Self::GainRedState
+                | Self::CapSensorResult
+                | Self::AuxDisplay
+                | Self::AmplitudeMeasureAaResult
+                | Self::AmplitudeMeasureResult
+                | Self::PhaseMeasureAaResult
+                | Self::PhaseMeasureResult
+                | Self::CapacitanceMeasureAaResult
+                | Self::CapacitanceMeasureResult
+                | Self::IcIdentity
+                | Self::TxDriverStatus
+                | Self::RegulatorResult
+        )
+    }
+}
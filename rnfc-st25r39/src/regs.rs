@@ -2924,6 +2924,7 @@ impl From<FieldThresholdDeactvRfe> for u8 {
 pub struct IcIdentityIcType(pub u8);
 impl IcIdentityIcType {
     pub const ST25R3916: Self = Self(0x05);
+    pub const ST25R3916B: Self = Self(0x06);
 }
 impl From<u8> for IcIdentityIcType {
     fn from(val: u8) -> Self {
@@ -3307,6 +3308,7 @@ impl From<StreamModeScf> for u8 {
 pub struct ModeOm(pub u8);
 impl ModeOm {
     pub const INI_NFC: Self = Self(0);
+    pub const TARG_BITRATE_DETECTION: Self = Self(0);
     pub const INI_ISO14443A: Self = Self(0x01);
     pub const TARG_NFCA: Self = Self(0x01);
     pub const INI_ISO14443B: Self = Self(0x02);
@@ -0,0 +1,100 @@
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+
+use crate::{Error, Interface, St25r39};
+
+/// Number of points in a [`ResonanceReport`].
+pub const RESONANCE_POINTS: usize = 16;
+
+/// Phase reading for a 90° phase shift between RFO and RFI, which is where the antenna resonates.
+const PHASE_RESONANT: u8 = 0x80;
+
+/// One point of the resonance sweep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResonancePoint {
+    /// Value written to both antenna tuning capacitors (ant_tune_a, ant_tune_b).
+    pub tune: u8,
+    /// Raw amplitude measurement.
+    pub amplitude: u8,
+    /// Raw phase measurement.
+    pub phase: u8,
+}
+
+/// Result of [`St25r39::measure_resonance`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResonanceReport {
+    /// Raw measurements, with `tune` increasing from 0 to 255.
+    pub points: [ResonancePoint; RESONANCE_POINTS],
+    /// Point with the highest amplitude.
+    pub amplitude_peak: ResonancePoint,
+    /// Tune value where the phase crosses 90°, interpolated between the two points around it.
+    /// None if it's not crossed within the tuning range.
+    pub phase_crossing: Option<u8>,
+}
+
+impl<I: Interface, IrqPin: InputPin + Wait, D: DelayNs> St25r39<I, IrqPin, D> {
+    /// Characterize the antenna resonance, for production QC.
+    ///
+    /// Sweeps the antenna tuning capacitors over their whole range and measures amplitude
+    /// and phase at each step. Unlike [`aat`](Self::aat), this doesn't change the tuning:
+    /// it's restored when done. Thresholds on the report are board-specific. A missing
+    /// phase crossing or a flat amplitude usually means a soldering or antenna defect.
+    ///
+    /// The field must be on, and no card should be near the antenna.
+    pub async fn measure_resonance(&mut self) -> Result<ResonanceReport, Error<I::Error>> {
+        let old_a = self.regs().ant_tune_a().read()?;
+        let old_b = self.regs().ant_tune_b().read()?;
+
+        let res = self.resonance_sweep().await;
+
+        self.regs().ant_tune_a().write_value(old_a)?;
+        self.regs().ant_tune_b().write_value(old_b)?;
+
+        let points = res?;
+        let amplitude_peak = *points.iter().max_by_key(|p| p.amplitude).unwrap();
+        let phase_crossing = points.windows(2).find_map(|w| phase_crossing(w[0], w[1]));
+
+        debug!(
+            "resonance: amplitude peak {} at tune {}, phase crossing {:?}",
+            amplitude_peak.amplitude, amplitude_peak.tune, phase_crossing
+        );
+
+        Ok(ResonanceReport {
+            points,
+            amplitude_peak,
+            phase_crossing,
+        })
+    }
+
+    async fn resonance_sweep(&mut self) -> Result<[ResonancePoint; RESONANCE_POINTS], Error<I::Error>> {
+        let mut points = [ResonancePoint::default(); RESONANCE_POINTS];
+        for (i, p) in points.iter_mut().enumerate() {
+            let tune = (i * 255 / (RESONANCE_POINTS - 1)) as u8;
+            self.regs().ant_tune_a().write_value(tune)?;
+            self.regs().ant_tune_b().write_value(tune)?;
+
+            // Wait for caps to settle.
+            self.delay.delay_ms(1).await;
+
+            *p = ResonancePoint {
+                tune,
+                amplitude: self.measure_amplitude().await?,
+                phase: self.measure_phase().await?,
+            };
+            trace!("resonance: tune={} amp={} pha={}", p.tune, p.amplitude, p.phase);
+        }
+        Ok(points)
+    }
+}
+
+fn phase_crossing(a: ResonancePoint, b: ResonancePoint) -> Option<u8> {
+    let (lo, hi) = (a.phase.min(b.phase), a.phase.max(b.phase));
+    if !(lo..=hi).contains(&PHASE_RESONANT) || lo == hi {
+        return None;
+    }
+    let frac = (PHASE_RESONANT as i32 - a.phase as i32) * (b.tune as i32 - a.tune as i32) / (b.phase as i32 - a.phase as i32);
+    Some((a.tune as i32 + frac) as u8)
+}
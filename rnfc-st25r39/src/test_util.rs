@@ -0,0 +1,164 @@
+//! Mock chip shared by the tests.
+//!
+//! [`MockChip`] is a register file that latches interrupts when the driver issues commands,
+//! and answers transmits with the queued responses. It doesn't model timing: everything a
+//! command causes is latched by the time it returns.
+
+use core::convert::Infallible;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use crate::{Command, Interface, Interrupt};
+
+const IRQ_MAIN: u8 = 26;
+const FIFO_STATUS1: u8 = 30;
+const FIFO_STATUS2: u8 = 31;
+const AUX_DISPLAY: u8 = 49;
+const IC_IDENTITY: u8 = 63;
+
+/// An ST25R3916 that reports the oscillator running and, unless [`set_field`](Self::set_field)
+/// says otherwise, the field on.
+pub struct MockChip {
+    regs: [u8; 256],
+    /// Latched interrupts, cleared when their register is read.
+    irqs: u32,
+    /// Interrupts latched by each transmit command.
+    pub transmit_irqs: u32,
+    /// Responses loaded into the FIFO by the next transmits, including the CRC if any.
+    pub responses: VecDeque<Vec<u8>>,
+    rx_fifo: VecDeque<u8>,
+}
+
+impl MockChip {
+    pub fn new() -> Self {
+        let mut regs = [0; 256];
+        regs[IC_IDENTITY as usize] = 0x05 << 3;
+        // osc_ok, tx_on
+        regs[AUX_DISPLAY as usize] = 1 << 4 | 1 << 5;
+        Self {
+            regs,
+            irqs: 0,
+            transmit_irqs: 1 << Interrupt::Txe as u32 | 1 << Interrupt::Rxs as u32 | 1 << Interrupt::Rxe as u32,
+            responses: VecDeque::new(),
+            rx_fifo: VecDeque::new(),
+        }
+    }
+
+    /// Report the field on or off in the auxiliary display register.
+    pub fn set_field(&mut self, on: bool) {
+        let tx_on = 1 << 5;
+        match on {
+            true => self.regs[AUX_DISPLAY as usize] |= tx_on,
+            false => self.regs[AUX_DISPLAY as usize] &= !tx_on,
+        }
+    }
+
+    fn latch(&mut self, irq: Interrupt) {
+        self.irqs |= 1 << irq as u32;
+    }
+
+    fn transmit(&mut self) {
+        self.rx_fifo = self.responses.pop_front().unwrap_or_default().into();
+        self.irqs |= self.transmit_irqs;
+    }
+}
+
+impl Interface for MockChip {
+    type Error = Infallible;
+
+    fn do_command(&mut self, cmd: u8) -> Result<(), Self::Error> {
+        const TRANSMITS: [Command; 4] = [
+            Command::TransmitWithCrc,
+            Command::TransmitWithoutCrc,
+            Command::TransmitReqa,
+            Command::TransmitWupa,
+        ];
+        if TRANSMITS.iter().any(|&c| c as u8 == cmd) {
+            self.transmit();
+        } else if cmd == Command::InitialRfCollision as u8 {
+            self.latch(Interrupt::Apon);
+        } else {
+            // Everything else terminates right away, commands without the interrupt included.
+            self.latch(Interrupt::Dct);
+        }
+        Ok(())
+    }
+
+    fn read_reg(&mut self, reg: u8) -> Result<u8, Self::Error> {
+        Ok(match reg {
+            IRQ_MAIN..=29 => {
+                let shift = (reg - IRQ_MAIN) * 8;
+                let val = (self.irqs >> shift) as u8;
+                self.irqs &= !(0xFF << shift);
+                val
+            }
+            FIFO_STATUS1 => self.rx_fifo.len() as u8,
+            FIFO_STATUS2 => ((self.rx_fifo.len() >> 8) as u8) << 6,
+            _ => self.regs[reg as usize],
+        })
+    }
+
+    fn write_reg(&mut self, reg: u8, val: u8) -> Result<(), Self::Error> {
+        if reg != AUX_DISPLAY && reg != IC_IDENTITY {
+            self.regs[reg as usize] = val;
+        }
+        Ok(())
+    }
+
+    fn read_fifo(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        for b in data {
+            *b = self.rx_fifo.pop_front().expect("FIFO underflow");
+        }
+        Ok(())
+    }
+
+    fn write_fifo(&mut self, _data: &[u8]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// IRQ pin that's never waited on in the tested paths.
+pub struct MockIrq;
+
+impl embedded_hal::digital::ErrorType for MockIrq {
+    type Error = Infallible;
+}
+
+impl embedded_hal::digital::InputPin for MockIrq {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
+impl embedded_hal_async::digital::Wait for MockIrq {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Delay that returns right away, the mock has no timing.
+pub struct NoDelay;
+
+impl embedded_hal_async::delay::DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
@@ -0,0 +1,19 @@
+pub use crate::iso14443a_ll::Error;
+
+/// A FeliCa (NFC-F) reader.
+pub trait Reader {
+    type Error: Error;
+
+    /// Send a frame and receive the response.
+    ///
+    /// `tx` and `rx` start with the length byte and don't include the CRC, the reader adds and checks it.
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Self::Error>;
+}
+
+impl<T: Reader> Reader for &mut T {
+    type Error = T::Error;
+
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Self::Error> {
+        T::transceive(self, tx, rx, timeout_1fc).await
+    }
+}
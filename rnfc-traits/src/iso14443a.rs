@@ -10,6 +10,16 @@ pub trait Reader {
     fn uid(&self) -> &[u8];
     fn atqa(&self) -> [u8; 2];
     fn sak(&self) -> u8;
+
+    /// Whether the card is still in the field, see [`iso14443a_ll::Reader::card_in_field`](crate::iso14443a_ll::Reader::card_in_field).
+    async fn card_in_field(&mut self) -> Option<bool> {
+        None
+    }
+
+    /// Clear the receiver before a retransmission, see [`iso14443a_ll::Reader::reset_rx`](crate::iso14443a_ll::Reader::reset_rx).
+    async fn reset_rx(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 impl<T: Reader> Reader for &mut T {
@@ -28,4 +38,10 @@ impl<T: Reader> Reader for &mut T {
     fn sak(&self) -> u8 {
         T::sak(self)
     }
+    async fn card_in_field(&mut self) -> Option<bool> {
+        T::card_in_field(self).await
+    }
+    async fn reset_rx(&mut self) -> Result<(), Self::Error> {
+        T::reset_rx(self).await
+    }
 }
@@ -1,13 +1,113 @@
 use core::convert::Infallible;
 use core::fmt::Debug;
 
-#[derive(Debug)]
+/// CRC appended on TX and checked (and stripped) on RX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Crc {
+    /// ISO14443-3 Type A CRC.
+    A,
+    /// ISO14443-3 Type B CRC.
+    ///
+    /// This only selects the CRC. SOF/EOF/EGT framing is determined by the mode the reader is in.
+    B,
+    /// No CRC.
+    None,
+}
+
+impl Crc {
+    /// Compute the CRC of `data`, in transmission order. Returns `None` for [`Crc::None`].
+    pub fn compute(self, data: &[u8]) -> Option<[u8; 2]> {
+        let (init, invert) = match self {
+            Self::A => (0x6363, false),
+            Self::B => (0xFFFF, true),
+            Self::None => return None,
+        };
+        let mut crc: u16 = init;
+        for &b in data {
+            crc ^= b as u16;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+            }
+        }
+        if invert {
+            crc = !crc;
+        }
+        Some(crc.to_le_bytes())
+    }
+}
+
+/// Block check character of an anticollision UID part: the XOR of its bytes.
+///
+/// Sent after the 4 UID bytes of a cascade level, in anticollision responses and SELECT.
+pub fn bcc(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |acc, b| acc ^ b)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Frame {
-    Standard { timeout_1fc: u32 },
+    Standard {
+        timeout_1fc: u32,
+        crc: Crc,
+    },
     WupA,
     ReqA,
-    Anticoll { bits: usize },
+    Anticoll {
+        bits: usize,
+    },
+    /// Bit-oriented frame of `bits` bits, without CRC. The response length is returned with bit
+    /// granularity too. See [`Reader::transceive_bits`].
+    Bits {
+        bits: usize,
+        timeout_1fc: u32,
+    },
+    /// Byte-oriented frame with CRC and parity chosen independently, for vendor commands that
+    /// need a combination [`Frame::Standard`] can't express. The response length is returned
+    /// with bit granularity, since without parity it's a raw bit stream.
+    Custom {
+        timeout_1fc: u32,
+        crc: Crc,
+        parity: Parity,
+    },
+    /// NFC Forum Type 1 Tag (Topaz) frame, sent with the reader's T1T framing. No CRC is added
+    /// or checked: `tx` already ends with its CRC_B, and the response is returned with its CRC_B.
+    Type1 {
+        timeout_1fc: u32,
+    },
+}
+
+/// Parity handling, see [`Frame::Custom`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Parity {
+    /// Odd parity bit after each byte, added on TX and checked (and stripped) on RX.
+    Hardware,
+    /// No parity bits are added on TX or expected on RX: parity, if any, is computed by the
+    /// caller and included in the bit stream, in `tx` and in the response.
+    None,
+}
+
+/// Timeout used by [`Reader::transceive_bits`].
+pub const TRANSCEIVE_BITS_TIMEOUT_1FC: u32 = 65536;
+
+/// Timeout used by the default [`Reader::transmit_only`], for ignoring a response.
+pub const TRANSMIT_ONLY_TIMEOUT_1FC: u32 = 65536;
+
+/// Merge the known prefix of an anticollision frame into the received response.
+///
+/// `frame` must hold the received bytes at their position in the frame: starting at byte
+/// `prefix_bits / 8`, with the low `prefix_bits % 8` bits of that first byte zero, since
+/// they were sent by us, not received. This copies the first `prefix_bits` bits of
+/// `prefix` into `frame`, including the partial byte shared by the prefix and the response.
+pub fn merge_anticoll_prefix(frame: &mut [u8], prefix: &[u8], prefix_bits: usize) {
+    let full_bytes = prefix_bits / 8;
+    frame[..full_bytes].copy_from_slice(&prefix[..full_bytes]);
+    let partial_bits = prefix_bits % 8;
+    if partial_bits != 0 {
+        let mask = (1u8 << partial_bits) - 1;
+        frame[full_bytes] = (frame[full_bytes] & !mask) | (prefix[full_bytes] & mask);
+    }
 }
 
 #[non_exhaustive]
@@ -39,6 +139,66 @@ pub trait Reader {
     type Error: Error;
 
     async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], opts: Frame) -> Result<usize, Self::Error>;
+
+    /// Send the first `tx_bits` bits of `tx`, without CRC, and receive the response. Returns the
+    /// response length in bits.
+    ///
+    /// For sub-byte frames, such as short frames or 4-bit ACK/NAK responses. The last byte of
+    /// `rx` is incomplete if the result isn't a multiple of 8, its valid bits are the low ones.
+    async fn transceive_bits(&mut self, tx: &[u8], tx_bits: usize, rx: &mut [u8]) -> Result<usize, Self::Error> {
+        let opts = Frame::Bits {
+            bits: tx_bits,
+            timeout_1fc: TRANSCEIVE_BITS_TIMEOUT_1FC,
+        };
+        self.transceive(tx, rx, opts).await
+    }
+
+    /// Whether a card is still in the field, if the reader can tell without talking to it,
+    /// for example from the field amplitude. `None` if it can't, which is the default.
+    ///
+    /// Used when a card stops answering, to tell a removed card from a slow one.
+    async fn card_in_field(&mut self) -> Option<bool> {
+        None
+    }
+
+    /// Send a frame no response is expected to, such as HLTA, with a CRC_A if `crc`.
+    ///
+    /// Readers that can return as soon as the frame is sent. By default this transceives, and
+    /// ignores the response along with timeouts and corruption errors.
+    async fn transmit_only(&mut self, tx: &[u8], crc: bool) -> Result<(), Self::Error> {
+        let opts = Frame::Standard {
+            timeout_1fc: TRANSMIT_ONLY_TIMEOUT_1FC,
+            crc: if crc { Crc::A } else { Crc::None },
+        };
+        let mut rx = [0; 32];
+        match self.transceive(tx, &mut rx, opts).await {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(e.kind(), ErrorKind::Timeout | ErrorKind::Corruption) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Wait `time_1fc` with the field on, for guard times between frames.
+    ///
+    /// Does nothing by default, for readers that can't time it: the next frame then goes out
+    /// right away.
+    async fn delay_1fc(&mut self, time_1fc: u32) {
+        let _ = time_1fc;
+    }
+
+    /// Clear the receiver and FIFO, keeping the TX configuration, before retransmitting a frame.
+    ///
+    /// For retry loops such as ISO-DEP R(NAK) recovery: readers that can, skip part of the full
+    /// reset of the next transceive. Does nothing by default.
+    async fn reset_rx(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Turn the field off and leave the reader idle, when a poll is cancelled mid-flight.
+    ///
+    /// Not async so it can run from a drop guard. The field stays off until the reader turns it
+    /// back on, see its docs. Does nothing by default.
+    fn field_off(&mut self) {}
 }
 
 impl<T: Reader> Reader for &mut T {
@@ -47,4 +207,24 @@ impl<T: Reader> Reader for &mut T {
     async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], opts: Frame) -> Result<usize, Self::Error> {
         T::transceive(self, tx, rx, opts).await
     }
+
+    async fn card_in_field(&mut self) -> Option<bool> {
+        T::card_in_field(self).await
+    }
+
+    async fn transmit_only(&mut self, tx: &[u8], crc: bool) -> Result<(), Self::Error> {
+        T::transmit_only(self, tx, crc).await
+    }
+
+    async fn delay_1fc(&mut self, time_1fc: u32) {
+        T::delay_1fc(self, time_1fc).await
+    }
+
+    async fn reset_rx(&mut self) -> Result<(), Self::Error> {
+        T::reset_rx(self).await
+    }
+
+    fn field_off(&mut self) {
+        T::field_off(self)
+    }
 }
@@ -0,0 +1,66 @@
+//! ISO14443-3 Type A listen mode (the card side), for card emulation.
+//!
+//! The reader chip answers REQA/WUPA, anticollision and SELECT on its own, using the
+//! [`Identity`] it's given. Only frames after activation reach the [`Target`].
+
+pub use crate::iso14443a_ll::Error;
+
+/// What the target answers during anticollision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Identity<'a> {
+    /// UID (NFCID1). 4, 7 or 10 bytes, targets may not support all sizes.
+    pub uid: &'a [u8],
+    /// ATQA (SENS_RES).
+    pub atqa: [u8; 2],
+    /// SAK (SEL_RES) of the last cascade level. The cascade bit is handled by the target.
+    pub sak: u8,
+}
+
+pub trait Target {
+    type Error: Error;
+
+    /// Wait for a reader to select us, answering anticollision with `identity`.
+    ///
+    /// Frames for other technologies are ignored.
+    async fn listen(&mut self, identity: &Identity<'_>) -> Result<(), Self::Error>;
+
+    /// Receive a frame from the reader, with its CRC checked and removed. Returns its length.
+    ///
+    /// Returns `None` if we got deactivated instead: the field went off, or the reader sent
+    /// HLTA (which is handled by the target). Call [`listen`](Self::listen) again to wait for
+    /// the next activation. Corrupted frames are ignored.
+    async fn receive(&mut self, rx: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+
+    /// Send a response, with CRC.
+    async fn send(&mut self, tx: &[u8]) -> Result<(), Self::Error>;
+
+    /// Send a 4-bit response without CRC, such as a Type 2 Tag ACK or NAK.
+    async fn send_short(&mut self, nibble: u8) -> Result<(), Self::Error>;
+
+    /// Go to the HALT state, until the reader wakes us up again with WUPA.
+    ///
+    /// For higher layer deactivation such as ISO-DEP DESELECT. Call [`listen`](Self::listen)
+    /// to wait for the next activation.
+    async fn sleep(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<T: Target> Target for &mut T {
+    type Error = T::Error;
+
+    async fn listen(&mut self, identity: &Identity<'_>) -> Result<(), Self::Error> {
+        T::listen(self, identity).await
+    }
+    async fn receive(&mut self, rx: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        T::receive(self, rx).await
+    }
+    async fn send(&mut self, tx: &[u8]) -> Result<(), Self::Error> {
+        T::send(self, tx).await
+    }
+    async fn send_short(&mut self, nibble: u8) -> Result<(), Self::Error> {
+        T::send_short(self, nibble).await
+    }
+    async fn sleep(&mut self) -> Result<(), Self::Error> {
+        T::sleep(self).await
+    }
+}
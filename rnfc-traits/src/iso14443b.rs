@@ -0,0 +1,72 @@
+//! ISO14443-3 Type B.
+
+/// Minimum TR0, the guard time between the end of a PCD frame and the start of the PICC's subcarrier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Tr0 {
+    /// 64/fs at 106 kbps. This is the default.
+    Default,
+    /// 48/fs at 106 kbps.
+    Reduced48,
+    /// 16/fs at 106 kbps.
+    Reduced16,
+}
+
+/// Minimum TR1, the time the PICC sends unmodulated subcarrier before the SOF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Tr1 {
+    /// 80/fs at 106 kbps. This is the default.
+    Default,
+    /// 64/fs at 106 kbps.
+    Reduced64,
+    /// 32/fs at 106 kbps.
+    Reduced32,
+}
+
+/// Type B frame timings.
+///
+/// Sent to the card in ATTRIB, and programmed into the reader so it matches what the
+/// card will do. The defaults are what every card supports. Cards with a poor
+/// implementation may need more relaxed timings than they advertise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timings {
+    pub tr0_min: Tr0,
+    pub tr1_min: Tr1,
+    /// Extra guard time between PCD characters, in etu. 0 to 6.
+    pub egt_etu: u8,
+    /// The card sends a SOF. Only cards that support it may be asked to suppress it.
+    pub sof: bool,
+    /// The card sends an EOF. Only cards that support it may be asked to suppress it.
+    pub eof: bool,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            tr0_min: Tr0::Default,
+            tr1_min: Tr1::Default,
+            egt_etu: 0,
+            sof: true,
+            eof: true,
+        }
+    }
+}
+
+impl Timings {
+    /// Param 1 byte of ATTRIB, telling the card which timings to use.
+    pub fn attrib_param1(&self) -> u8 {
+        let tr0 = match self.tr0_min {
+            Tr0::Default => 0b00,
+            Tr0::Reduced48 => 0b01,
+            Tr0::Reduced16 => 0b10,
+        };
+        let tr1 = match self.tr1_min {
+            Tr1::Default => 0b00,
+            Tr1::Reduced64 => 0b01,
+            Tr1::Reduced32 => 0b10,
+        };
+        tr0 << 6 | tr1 << 4 | (!self.eof as u8) << 3 | (!self.sof as u8) << 2
+    }
+}
@@ -0,0 +1,28 @@
+pub use crate::iso14443a_ll::Error;
+
+pub trait Reader {
+    type Error: Error;
+
+    /// Send a request and receive the response.
+    ///
+    /// `tx` and `rx` don't include the CRC, the reader adds and checks it.
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Self::Error>;
+
+    /// Send a lone EOF and receive the response.
+    ///
+    /// Tags hold the response to write commands sent with the Option flag until the reader
+    /// sends an EOF.
+    async fn send_eof(&mut self, rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Self::Error>;
+}
+
+impl<T: Reader> Reader for &mut T {
+    type Error = T::Error;
+
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Self::Error> {
+        T::transceive(self, tx, rx, timeout_1fc).await
+    }
+
+    async fn send_eof(&mut self, rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Self::Error> {
+        T::send_eof(self, rx, timeout_1fc).await
+    }
+}
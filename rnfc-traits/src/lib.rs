@@ -4,7 +4,17 @@
 // This must go FIRST so that other mods see its macros.
 mod fmt;
 
+#[cfg(feature = "felica")]
+pub mod felica;
+#[cfg(feature = "iso14443a")]
 pub mod iso14443a;
 pub mod iso14443a_ll;
+#[cfg(feature = "emulation")]
+pub mod iso14443a_target;
+#[cfg(feature = "iso14443b")]
+pub mod iso14443b;
+#[cfg(feature = "iso15693")]
+pub mod iso15693;
 
+#[cfg(feature = "iso-dep")]
 pub mod iso_dep;
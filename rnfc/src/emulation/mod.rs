@@ -0,0 +1,108 @@
+//! Card emulation: NFC Forum tags served over a [`Target`](rnfc_traits::iso14443a_target::Target).
+//!
+//! The emulators handle the protocol after activation. Anticollision is done by the target,
+//! with the [`Identity`](rnfc_traits::iso14443a_target::Identity) the emulator provides.
+
+mod type2;
+mod type4;
+
+pub use type2::{emulate_type2_ndef, Type2Emulator};
+pub use type4::Type4Emulator;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    Lower(E),
+    /// The NDEF message doesn't fit in the emulated tag.
+    BufferTooSmall,
+}
+
+#[cfg(test)]
+mod mock {
+    use rnfc_traits::iso14443a_ll::ErrorKind;
+    use rnfc_traits::iso14443a_target::{Identity, Target};
+
+    /// Expected target operation, and the frame received or sent.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Op {
+        Listen,
+        Rx(&'static [u8]),
+        /// The field goes off.
+        Off,
+        Tx(&'static [u8]),
+        Short(u8),
+        Sleep,
+        /// The next listen or receive fails with this error.
+        Fail(ErrorKind),
+    }
+
+    pub struct MockTarget {
+        expected: &'static [Op],
+        pos: usize,
+    }
+
+    impl MockTarget {
+        pub fn new(expected: &'static [Op]) -> Self {
+            Self { expected, pos: 0 }
+        }
+
+        /// All expected operations happened.
+        pub fn done(&self) -> bool {
+            self.pos == self.expected.len()
+        }
+
+        fn next(&mut self, got: Op) -> Op {
+            let Some(&expected) = self.expected.get(self.pos) else {
+                panic!("unexpected op!\n         got: {:02x?}", got);
+            };
+            let matches = match (expected, got) {
+                (Op::Rx(_) | Op::Off, Op::Rx(_)) => true,
+                (Op::Fail(_), Op::Listen | Op::Rx(_)) => true,
+                (a, b) => a == b,
+            };
+            if !matches {
+                panic!("unexpected op!\n    expected: {:02x?}\n         got: {:02x?}", expected, got);
+            }
+            self.pos += 1;
+            expected
+        }
+    }
+
+    impl Target for MockTarget {
+        type Error = ErrorKind;
+
+        async fn listen(&mut self, _identity: &Identity<'_>) -> Result<(), Self::Error> {
+            match self.next(Op::Listen) {
+                Op::Fail(e) => Err(e),
+                _ => Ok(()),
+            }
+        }
+
+        async fn receive(&mut self, rx: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+            match self.next(Op::Rx(&[])) {
+                Op::Rx(frame) => {
+                    rx[..frame.len()].copy_from_slice(frame);
+                    Ok(Some(frame.len()))
+                }
+                Op::Fail(e) => Err(e),
+                _ => Ok(None),
+            }
+        }
+
+        async fn send(&mut self, tx: &[u8]) -> Result<(), Self::Error> {
+            // Leak so the op can hold it, it's only tests.
+            self.next(Op::Tx(std::vec::Vec::leak(tx.to_vec())));
+            Ok(())
+        }
+
+        async fn send_short(&mut self, nibble: u8) -> Result<(), Self::Error> {
+            self.next(Op::Short(nibble));
+            Ok(())
+        }
+
+        async fn sleep(&mut self) -> Result<(), Self::Error> {
+            self.next(Op::Sleep);
+            Ok(())
+        }
+    }
+}
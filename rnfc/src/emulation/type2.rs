@@ -0,0 +1,244 @@
+use core::convert::Infallible;
+
+use rnfc_traits::iso14443a_ll::ErrorKind;
+use rnfc_traits::iso14443a_target::{Error as _, Identity, Target};
+
+use super::Error;
+use crate::fmt::Bytes;
+
+const PAGE_SIZE: usize = 4;
+/// Pages before the data area: UID, lock bytes and capability container.
+const HEADER_PAGES: usize = 4;
+const CC_PAGE: usize = 3;
+/// Memory size of [`emulate_type2_ndef`] tags, for a data area of 496 bytes.
+const STATIC_TAG_SIZE: usize = 512;
+
+const CMD_READ: u8 = 0x30;
+const CMD_WRITE: u8 = 0xA2;
+
+const ACK: u8 = 0x0A;
+const NAK_INVALID_ARGUMENT: u8 = 0x00;
+
+/// Cascade tag, included in the BCC of the first UID part.
+const CASCADE_TAG: u8 = 0x88;
+
+const NDEF_MAGIC: u8 = 0xE1;
+const NDEF_VERSION: u8 = 0x10;
+const CC_ACCESS_READ_WRITE: u8 = 0x00;
+const CC_ACCESS_READ_ONLY: u8 = 0x0F;
+const TLV_NDEF: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+/// Emulated NFC Forum Type 2 Tag, with `N` bytes of memory.
+///
+/// Memory is laid out like an NTAG: UID and lock bytes in pages 0-2, the capability container
+/// in page 3, and the NDEF TLV from page 4 on. `N` must be a multiple of 4, the data area is
+/// `N - 16` bytes. Readers can write the data area, changes are visible with [`memory`](Self::memory),
+/// unless the tag is made [read-only](Self::set_read_only).
+pub struct Type2Emulator<T: Target, const N: usize> {
+    target: T,
+    uid: [u8; 7],
+    memory: [u8; N],
+    read_only: bool,
+}
+
+impl<T: Target, const N: usize> Type2Emulator<T, N>
+where
+    T::Error: crate::fmt::Format,
+{
+    /// Create a tag with a 7-byte `uid`, holding the `ndef` message.
+    pub fn new(target: T, uid: [u8; 7], ndef: &[u8]) -> Result<Self, Error<T::Error>> {
+        assert!(N % PAGE_SIZE == 0 && N > HEADER_PAGES * PAGE_SIZE);
+
+        let mut memory = [0; N];
+        memory[..3].copy_from_slice(&uid[..3]);
+        memory[3] = CASCADE_TAG ^ uid[0] ^ uid[1] ^ uid[2];
+        memory[4..8].copy_from_slice(&uid[3..]);
+        memory[8] = uid[3] ^ uid[4] ^ uid[5] ^ uid[6];
+        // Internal byte, lock bytes 0.
+        memory[9] = 0x48;
+
+        let data_len = N - HEADER_PAGES * PAGE_SIZE;
+        memory[CC_PAGE * PAGE_SIZE..][..PAGE_SIZE].copy_from_slice(&[
+            NDEF_MAGIC,
+            NDEF_VERSION,
+            (data_len / 8).min(0xFF) as u8,
+            CC_ACCESS_READ_WRITE,
+        ]);
+
+        let data = &mut memory[HEADER_PAGES * PAGE_SIZE..];
+        let header_len = if ndef.len() < 0xFF { 2 } else { 4 };
+        if header_len + ndef.len() + 1 > data.len() {
+            return Err(Error::BufferTooSmall);
+        }
+        data[0] = TLV_NDEF;
+        if ndef.len() < 0xFF {
+            data[1] = ndef.len() as u8;
+        } else {
+            data[1] = 0xFF;
+            data[2..4].copy_from_slice(&(ndef.len() as u16).to_be_bytes());
+        }
+        data[header_len..][..ndef.len()].copy_from_slice(ndef);
+        data[header_len + ndef.len()] = TLV_TERMINATOR;
+
+        Ok(Self {
+            target,
+            uid,
+            memory,
+            read_only: false,
+        })
+    }
+
+    /// Make the tag read-only, or writable again. The capability container tells readers,
+    /// and writes are refused. Tags are writable by default.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+        self.memory[CC_PAGE * PAGE_SIZE + 3] = match read_only {
+            true => CC_ACCESS_READ_ONLY,
+            false => CC_ACCESS_READ_WRITE,
+        };
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.target
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    /// Whole tag memory, including any writes from readers.
+    pub fn memory(&self) -> &[u8; N] {
+        &self.memory
+    }
+
+    /// Wait for a reader to select us, then serve it until it deactivates us.
+    pub async fn serve(&mut self) -> Result<(), Error<T::Error>> {
+        let identity = Identity {
+            uid: &self.uid,
+            atqa: [0x44, 0x00],
+            sak: 0x00,
+        };
+        self.target.listen(&identity).await.map_err(Error::Lower)?;
+
+        let mut rx = [0; 16];
+        while let Some(n) = self.target.receive(&mut rx).await.map_err(Error::Lower)? {
+            self.handle(&rx[..n]).await.map_err(Error::Lower)?;
+        }
+        Ok(())
+    }
+
+    async fn handle(&mut self, frame: &[u8]) -> Result<(), T::Error> {
+        let pages = N / PAGE_SIZE;
+        match *frame {
+            [CMD_READ, page] if (page as usize) < pages => {
+                // Reads past the end roll over to page 0.
+                let mut tx = [0; 16];
+                for (i, b) in tx.iter_mut().enumerate() {
+                    *b = self.memory[(page as usize * PAGE_SIZE + i) % N];
+                }
+                self.target.send(&tx).await
+            }
+            [CMD_WRITE, page, ref data @ ..] if data.len() == PAGE_SIZE => {
+                let page = page as usize;
+                if self.read_only || page < HEADER_PAGES || page >= pages {
+                    debug!("type2: write to read-only page {}", page);
+                    return self.target.send_short(NAK_INVALID_ARGUMENT).await;
+                }
+                self.memory[page * PAGE_SIZE..][..PAGE_SIZE].copy_from_slice(data);
+                self.target.send_short(ACK).await
+            }
+            _ => {
+                debug!("type2: unsupported command {}", Bytes(frame));
+                self.target.send_short(NAK_INVALID_ARGUMENT).await
+            }
+        }
+    }
+}
+
+/// Emulate a read-only Type 2 Tag holding `ndef`, such as a URL for phones to open on tap.
+///
+/// Serves readers forever: after each deactivation, such as a phone polling again or the field
+/// going off, the tag waits for the next activation. Transient errors (timeouts and corrupted
+/// frames) are logged and the tag goes back to listening, so this only returns on other errors.
+/// The tag has 496 bytes for the NDEF message, use [`Type2Emulator`] for other sizes or to
+/// accept writes.
+pub async fn emulate_type2_ndef<T: Target>(target: T, uid: [u8; 7], ndef: &[u8]) -> Result<Infallible, Error<T::Error>>
+where
+    T::Error: crate::fmt::Format,
+{
+    let mut tag = Type2Emulator::<T, STATIC_TAG_SIZE>::new(target, uid, ndef)?;
+    tag.set_read_only(true);
+    loop {
+        match tag.serve().await {
+            Ok(()) => {}
+            Err(Error::Lower(e)) if matches!(e.kind(), ErrorKind::Timeout | ErrorKind::Corruption) => {
+                warn!("type2: serving failed: {:?}", e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::emulation::mock::{MockTarget, Op};
+
+    const UID: [u8; 7] = hex!("04 d2 9a 52 3a 6b 80");
+
+    #[test]
+    fn test_memory_layout() {
+        let tag = Type2Emulator::<_, 64>::new(MockTarget::new(&[]), UID, &hex!("d1 01 01 54")).unwrap();
+        assert_eq!(
+            tag.memory()[..32],
+            hex!("04 d2 9a c4 52 3a 6b 80 83 48 00 00 e1 10 06 00 03 04 d1 01 01 54 fe 00 00 00 00 00 00 00 00 00")
+        );
+
+        let res = Type2Emulator::<_, 32>::new(MockTarget::new(&[]), UID, &[0; 14]);
+        assert!(matches!(res, Err(Error::BufferTooSmall)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_write() {
+        let mock = MockTarget::new(&[
+            Op::Listen,
+            Op::Rx(&hex!("30 03")),
+            Op::Tx(&hex!("e1 10 06 00 03 04 d1 01 01 54 fe 00 00 00 00 00")),
+            Op::Rx(&hex!("30 0f")),
+            Op::Tx(&hex!("00 00 00 00 04 d2 9a c4 52 3a 6b 80 83 48 00 00")),
+            Op::Rx(&hex!("a2 05 11 22 33 44")),
+            Op::Short(ACK),
+            Op::Rx(&hex!("a2 02 11 22 33 44")),
+            Op::Short(NAK_INVALID_ARGUMENT),
+            Op::Rx(&hex!("30 10")),
+            Op::Short(NAK_INVALID_ARGUMENT),
+            Op::Off,
+        ]);
+        let mut tag = Type2Emulator::<_, 64>::new(mock, UID, &hex!("d1 01 01 54")).unwrap();
+        tag.serve().await.unwrap();
+        assert_eq!(tag.memory()[20..24], hex!("11 22 33 44"));
+        assert!(tag.inner().done());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_emulate_static() {
+        let mock = &mut MockTarget::new(&[
+            Op::Listen,
+            Op::Rx(&hex!("30 03")),
+            Op::Tx(&hex!("e1 10 3e 0f 03 04 d1 01 01 54 fe 00 00 00 00 00")),
+            Op::Off,
+            // The phone polls again.
+            Op::Listen,
+            Op::Rx(&hex!("a2 04 11 22 33 44")),
+            Op::Short(NAK_INVALID_ARGUMENT),
+            Op::Fail(ErrorKind::Timeout),
+            Op::Fail(ErrorKind::Other),
+        ]);
+        let res = emulate_type2_ndef(&mut *mock, UID, &hex!("d1 01 01 54")).await;
+        assert_eq!(res, Err(Error::Lower(ErrorKind::Other)));
+        assert!(mock.done());
+    }
+}
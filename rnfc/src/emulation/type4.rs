@@ -0,0 +1,341 @@
+use rnfc_traits::iso14443a_target::{Identity, Target};
+
+use super::Error;
+use crate::fmt::Bytes;
+
+/// ATS: FSCI=8 (256 bytes), only 106 kbps, FWI=8, no NAD or CID.
+const ATS: [u8; 5] = [0x05, 0x78, 0x80, 0x80, 0x00];
+
+const CMD_RATS: u8 = 0xE0;
+const PCB_DESELECT: u8 = 0xC2;
+
+/// Frame sizes for FSDI 0 to 8. Higher (RFU) values are interpreted as 256.
+const FS_TABLE: [u16; 9] = [16, 24, 32, 40, 48, 64, 96, 128, 256];
+/// Max frame size, not including the CRC.
+const FRAME_MAX_LEN: usize = 254;
+
+const NDEF_AID: [u8; 7] = [0xD2, 0x76, 0x00, 0x00, 0x85, 0x01, 0x01];
+const FILE_CC: u16 = 0xE103;
+const FILE_NDEF: u16 = 0xE104;
+const CC_LEN: usize = 15;
+
+/// Max R-APDU and C-APDU data sizes we announce, low enough to fit in the frames of a reader with a 64-byte FSD.
+const MLE: u16 = 0x003B;
+const MLC: u16 = 0x0034;
+
+const SW_OK: u16 = 0x9000;
+const SW_WRONG_LENGTH: u16 = 0x6700;
+const SW_NOT_FOUND: u16 = 0x6A82;
+const SW_WRONG_OFFSET: u16 = 0x6B00;
+const SW_INS_NOT_SUPPORTED: u16 = 0x6D00;
+const SW_CLA_NOT_SUPPORTED: u16 = 0x6E00;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum File {
+    None,
+    Cc,
+    Ndef,
+}
+
+/// Emulated NFC Forum Type 4 Tag, with an `N`-byte NDEF file.
+///
+/// The NDEF file starts with the 2-byte message length (NLEN), so messages up to `N - 2` bytes
+/// fit. Readers can write it, changes are visible with [`ndef_file`](Self::ndef_file).
+/// Command chaining and CID/NAD are not supported.
+pub struct Type4Emulator<T: Target, const N: usize> {
+    target: T,
+    uid: [u8; 7],
+    cc: [u8; CC_LEN],
+    ndef_file: [u8; N],
+
+    /// Max frame size the reader can receive, including CRC.
+    fsd: usize,
+    /// Block count spin bit: 0 or 1
+    block_num: u8,
+    app_selected: bool,
+    file: File,
+    /// Last block we sent, for retransmission.
+    last_tx: [u8; FRAME_MAX_LEN],
+    last_tx_len: usize,
+}
+
+impl<T: Target, const N: usize> Type4Emulator<T, N>
+where
+    T::Error: crate::fmt::Format,
+{
+    /// Create a tag with a 7-byte `uid`, holding the `ndef` message.
+    pub fn new(target: T, uid: [u8; 7], ndef: &[u8]) -> Result<Self, Error<T::Error>> {
+        assert!(N >= 2 && N <= 0x7FFF);
+        if ndef.len() > N - 2 {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut ndef_file = [0; N];
+        ndef_file[..2].copy_from_slice(&(ndef.len() as u16).to_be_bytes());
+        ndef_file[2..][..ndef.len()].copy_from_slice(ndef);
+
+        let mut cc = [0; CC_LEN];
+        cc[0..2].copy_from_slice(&(CC_LEN as u16).to_be_bytes());
+        cc[2] = 0x20; // mapping version 2.0
+        cc[3..5].copy_from_slice(&MLE.to_be_bytes());
+        cc[5..7].copy_from_slice(&MLC.to_be_bytes());
+        // NDEF File Control TLV
+        cc[7] = 0x04;
+        cc[8] = 0x06;
+        cc[9..11].copy_from_slice(&FILE_NDEF.to_be_bytes());
+        cc[11..13].copy_from_slice(&(N as u16).to_be_bytes());
+        cc[13] = 0x00; // read access
+        cc[14] = 0x00; // write access
+
+        Ok(Self {
+            target,
+            uid,
+            cc,
+            ndef_file,
+            fsd: FS_TABLE[2] as usize,
+            block_num: 1,
+            app_selected: false,
+            file: File::None,
+            last_tx: [0; FRAME_MAX_LEN],
+            last_tx_len: 0,
+        })
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.target
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    /// NDEF file contents, including any writes from readers.
+    pub fn ndef_file(&self) -> &[u8; N] {
+        &self.ndef_file
+    }
+
+    /// Wait for a reader to select us, then serve it until it deactivates us.
+    pub async fn serve(&mut self) -> Result<(), Error<T::Error>> {
+        let identity = Identity {
+            uid: &self.uid,
+            atqa: [0x44, 0x00],
+            sak: 0x20,
+        };
+        self.target.listen(&identity).await.map_err(Error::Lower)?;
+
+        self.block_num = 1;
+        self.app_selected = false;
+        self.file = File::None;
+        self.last_tx_len = 0;
+
+        let mut rx = [0; FRAME_MAX_LEN];
+        while let Some(n) = self.target.receive(&mut rx).await.map_err(Error::Lower)? {
+            let frame = &rx[..n];
+            if frame.is_empty() {
+                debug!("type4: ignoring empty frame");
+                continue;
+            }
+            match frame[0] {
+                CMD_RATS if n == 2 => {
+                    let fsdi = (frame[1] >> 4) as usize;
+                    self.fsd = FS_TABLE[fsdi.min(FS_TABLE.len() - 1)] as usize;
+                    self.target.send(&ATS).await.map_err(Error::Lower)?;
+                }
+                PCB_DESELECT if n == 1 => {
+                    self.target.send(&[PCB_DESELECT]).await.map_err(Error::Lower)?;
+                    self.target.sleep().await.map_err(Error::Lower)?;
+                    return Ok(());
+                }
+                // I-block, no chaining, CID or NAD.
+                0x02 | 0x03 => {
+                    self.block_num ^= 1;
+                    let mut tx = [0; FRAME_MAX_LEN];
+                    tx[0] = 0x02 | self.block_num;
+                    let len = 1 + self.process_apdu(&frame[1..], &mut tx[1..]);
+                    self.send_block(&tx[..len]).await.map_err(Error::Lower)?;
+                }
+                // R(ACK), R(NAK)
+                0xa2 | 0xa3 | 0xb2 | 0xb3 => {
+                    if frame[0] & 1 == self.block_num && self.last_tx_len != 0 {
+                        let tx = self.last_tx;
+                        self.target.send(&tx[..self.last_tx_len]).await.map_err(Error::Lower)?;
+                    } else if frame[0] & 0x10 != 0 {
+                        self.send_block(&[0xa2 | self.block_num]).await.map_err(Error::Lower)?;
+                    }
+                }
+                _ => debug!("type4: ignoring frame {}", Bytes(frame)),
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_block(&mut self, tx: &[u8]) -> Result<(), T::Error> {
+        self.last_tx[..tx.len()].copy_from_slice(tx);
+        self.last_tx_len = tx.len();
+        self.target.send(tx).await
+    }
+
+    /// Process a C-APDU, write the R-APDU to `resp`. Returns the R-APDU length.
+    fn process_apdu(&mut self, apdu: &[u8], resp: &mut [u8]) -> usize {
+        let sw = match self.process_apdu_inner(apdu, resp) {
+            Ok(n) => {
+                resp[n..][..2].copy_from_slice(&SW_OK.to_be_bytes());
+                return n + 2;
+            }
+            Err(sw) => sw,
+        };
+        debug!("type4: APDU {} failed: {:04x}", Bytes(apdu), sw);
+        resp[..2].copy_from_slice(&sw.to_be_bytes());
+        2
+    }
+
+    /// Returns the response data length, or the status word on failure.
+    fn process_apdu_inner(&mut self, apdu: &[u8], resp: &mut [u8]) -> Result<usize, u16> {
+        let [cla, ins, p1, p2, ref body @ ..] = *apdu else {
+            return Err(SW_WRONG_LENGTH);
+        };
+        if cla != 0x00 {
+            return Err(SW_CLA_NOT_SUPPORTED);
+        }
+        let lc_data = || match body {
+            [lc, data @ ..] if data.len() >= *lc as usize => Ok(&data[..*lc as usize]),
+            _ => Err(SW_WRONG_LENGTH),
+        };
+
+        match ins {
+            // SELECT
+            0xA4 => {
+                let data = lc_data()?;
+                match (p1, data) {
+                    (0x04, _) if data == NDEF_AID => {
+                        self.app_selected = true;
+                        self.file = File::None;
+                    }
+                    (0x00, &[hi, lo]) if self.app_selected => match u16::from_be_bytes([hi, lo]) {
+                        FILE_CC => self.file = File::Cc,
+                        FILE_NDEF => self.file = File::Ndef,
+                        _ => return Err(SW_NOT_FOUND),
+                    },
+                    _ => return Err(SW_NOT_FOUND),
+                }
+                Ok(0)
+            }
+            // READ BINARY
+            0xB0 => {
+                let file: &[u8] = match self.file {
+                    File::None => return Err(SW_NOT_FOUND),
+                    File::Cc => &self.cc,
+                    File::Ndef => &self.ndef_file,
+                };
+                let offset = u16::from_be_bytes([p1, p2]) as usize;
+                if offset > file.len() {
+                    return Err(SW_WRONG_OFFSET);
+                }
+                let le = match body {
+                    [0] => 256,
+                    [le] => *le as usize,
+                    _ => return Err(SW_WRONG_LENGTH),
+                };
+                // Leave room for the PCB, status word and CRC.
+                let n = le.min(file.len() - offset).min(self.fsd - 5);
+                resp[..n].copy_from_slice(&file[offset..][..n]);
+                Ok(n)
+            }
+            // UPDATE BINARY
+            0xD6 => {
+                if self.file != File::Ndef {
+                    return Err(SW_NOT_FOUND);
+                }
+                let data = lc_data()?;
+                let offset = u16::from_be_bytes([p1, p2]) as usize;
+                if offset + data.len() > N {
+                    return Err(SW_WRONG_OFFSET);
+                }
+                self.ndef_file[offset..][..data.len()].copy_from_slice(data);
+                Ok(0)
+            }
+            _ => Err(SW_INS_NOT_SUPPORTED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::emulation::mock::{MockTarget, Op};
+
+    const UID: [u8; 7] = hex!("04 d2 9a 52 3a 6b 80");
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_ndef() {
+        let mock = MockTarget::new(&[
+            Op::Listen,
+            Op::Rx(&hex!("e0 80")),
+            Op::Tx(&hex!("05 78 80 80 00")),
+            Op::Rx(&hex!("02 00 a4 04 00 07 d2 76 00 00 85 01 01 00")),
+            Op::Tx(&hex!("02 90 00")),
+            Op::Rx(&hex!("03 00 a4 00 0c 02 e1 03")),
+            Op::Tx(&hex!("03 90 00")),
+            Op::Rx(&hex!("02 00 b0 00 00 0f")),
+            Op::Tx(&hex!("02 00 0f 20 00 3b 00 34 04 06 e1 04 00 20 00 00 90 00")),
+            Op::Rx(&hex!("03 00 a4 00 0c 02 e1 04")),
+            Op::Tx(&hex!("03 90 00")),
+            // Lost response, retransmitted.
+            Op::Rx(&hex!("02 00 b0 00 00 06")),
+            Op::Tx(&hex!("02 00 04 d1 01 01 54 90 00")),
+            Op::Rx(&hex!("b2")),
+            Op::Tx(&hex!("02 00 04 d1 01 01 54 90 00")),
+            Op::Rx(&hex!("03 00 b0 00 00 06")),
+            Op::Tx(&hex!("03 00 04 d1 01 01 54 90 00")),
+            Op::Rx(&hex!("02 00 b0 00 40 01")),
+            Op::Tx(&hex!("02 6b 00")),
+            Op::Rx(&hex!("c2")),
+            Op::Tx(&hex!("c2")),
+            Op::Sleep,
+        ]);
+        let mut tag = Type4Emulator::<_, 32>::new(mock, UID, &hex!("d1 01 01 54")).unwrap();
+        tag.serve().await.unwrap();
+        assert!(tag.inner().done());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_update_ndef() {
+        let mock = MockTarget::new(&[
+            Op::Listen,
+            Op::Rx(&hex!("e0 80")),
+            Op::Tx(&hex!("05 78 80 80 00")),
+            // File select before the application.
+            Op::Rx(&hex!("02 00 a4 00 0c 02 e1 04")),
+            Op::Tx(&hex!("02 6a 82")),
+            Op::Rx(&hex!("03 00 a4 04 00 07 d2 76 00 00 85 01 01 00")),
+            Op::Tx(&hex!("03 90 00")),
+            Op::Rx(&hex!("02 00 a4 00 0c 02 e1 04")),
+            Op::Tx(&hex!("02 90 00")),
+            Op::Rx(&hex!("03 00 d6 00 00 04 00 02 aa bb")),
+            Op::Tx(&hex!("03 90 00")),
+            Op::Rx(&hex!("02 00 ca 00 00 00")),
+            Op::Tx(&hex!("02 6d 00")),
+            Op::Off,
+        ]);
+        let mut tag = Type4Emulator::<_, 32>::new(mock, UID, &[]).unwrap();
+        tag.serve().await.unwrap();
+        assert_eq!(tag.ndef_file()[..4], hex!("00 02 aa bb"));
+        assert!(tag.inner().done());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_empty_frame() {
+        let mock = MockTarget::new(&[
+            Op::Listen,
+            Op::Rx(&[]),
+            Op::Rx(&hex!("e0 80")),
+            Op::Tx(&hex!("05 78 80 80 00")),
+            Op::Off,
+        ]);
+        let mut tag = Type4Emulator::<_, 32>::new(mock, UID, &[]).unwrap();
+        tag.serve().await.unwrap();
+        assert!(tag.inner().done());
+    }
+}
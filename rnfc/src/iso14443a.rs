@@ -1,7 +1,11 @@
+use core::future::Future;
+use core::pin::pin;
+
+use embassy_futures::select::{select, Either};
 use heapless::Vec;
 use rnfc_traits::iso14443a::{Reader, UID_MAX_LEN};
 use rnfc_traits::iso14443a_ll as ll;
-use rnfc_traits::iso14443a_ll::{Frame, Reader as LLReader};
+use rnfc_traits::iso14443a_ll::{Crc, Frame, Reader as LLReader};
 
 use crate::fmt::Bytes;
 
@@ -23,8 +27,183 @@ macro_rules! retry {
     }};
 }
 
+/// Cascade tag, sent as first UID byte in a cascade level to indicate the UID is not complete yet.
+const CASCADE_TAG: u8 = 0x88;
+/// SAK bit indicating the UID is not complete yet.
+const SAK_CASCADE: u8 = 0x04;
+/// First byte of a single-size random UID (RID). Not to be confused with [`CASCADE_TAG`].
+const RANDOM_UID_PREFIX: u8 = 0x08;
+/// Collisions allowed in one cascade level before giving up with [`Error::TooManyCollisions`].
+///
+/// Each collision resolved drops at least one card from the anticollision, so this is
+/// reached with more than this many cards in the field.
+const ANTICOLL_MAX_COLLISIONS: usize = 8;
+
+/// Response timeout used by [`Card::exchange`].
+pub const EXCHANGE_TIMEOUT_1FC: u32 = 65536;
+
+/// Whether `uid` is a random UID (RID), generated anew by the card on every activation.
+///
+/// Random UIDs are always single-size and start with 0x08. They can't be used to
+/// track a card across activations.
+pub fn is_random_uid(uid: &[u8]) -> bool {
+    uid.len() == 4 && uid[0] == RANDOM_UID_PREFIX
+}
+
+/// UID size, as indicated in the ATQA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UidSize {
+    /// 4 bytes, 1 cascade level.
+    Single,
+    /// 7 bytes, 2 cascade levels.
+    Double,
+    /// 10 bytes, 3 cascade levels.
+    Triple,
+}
+
+impl UidSize {
+    /// UID length in bytes.
+    pub fn uid_len(self) -> usize {
+        match self {
+            Self::Single => 4,
+            Self::Double => 7,
+            Self::Triple => 10,
+        }
+    }
+
+    /// Number of cascade levels needed to select a card with this UID size.
+    pub fn cascade_levels(self) -> usize {
+        match self {
+            Self::Single => 1,
+            Self::Double => 2,
+            Self::Triple => 3,
+        }
+    }
+}
+
+/// ATQA (Answer To Request, Type A), the response to REQA/WUPA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Atqa(pub [u8; 2]);
+
+impl Atqa {
+    /// UID size (b7-b8), or `None` if the card uses the RFU value.
+    pub fn uid_size(&self) -> Option<UidSize> {
+        match self.0[0] >> 6 {
+            0b00 => Some(UidSize::Single),
+            0b01 => Some(UidSize::Double),
+            0b10 => Some(UidSize::Triple),
+            _ => None,
+        }
+    }
+
+    /// Bit frame anticollision bits (b1-b5). Compliant cards set exactly one of them.
+    pub fn bit_frame_anticollision(&self) -> u8 {
+        self.0[0] & 0x1F
+    }
+
+    /// Proprietary coding bits (b9-b12).
+    pub fn proprietary(&self) -> u8 {
+        self.0[1] & 0x0F
+    }
+
+    /// RFU bits (b6 and b13-b16), in their position in the ATQA read as a little-endian u16.
+    /// Zero for compliant cards.
+    pub fn rfu(&self) -> u16 {
+        u16::from_le_bytes(self.0) & 0xF020
+    }
+}
+
+impl From<[u8; 2]> for Atqa {
+    fn from(val: [u8; 2]) -> Self {
+        Self(val)
+    }
+}
+
+/// Check the UID we got after selection against the UID size the ATQA announced.
+///
+/// Fails with [`Error::Protocol`] on mismatch if `strict`, only logs otherwise.
+fn check_uid_size<T>(atqa: Atqa, uid: &[u8], strict: bool) -> Result<(), Error<T>> {
+    if let Some(size) = atqa.uid_size() {
+        if size.uid_len() != uid.len() {
+            debug!("ATQA indicates {:?} UID, but got {} bytes", size, uid.len());
+            if strict {
+                return Err(Error::Protocol);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What a card supports, as indicated by its SAK.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CardType {
+    /// Supports ISO-DEP (ISO14443-4), e.g. Type 4 tags.
+    IsoDep,
+    /// Supports NFC-DEP (peer-to-peer).
+    NfcDep,
+    /// Supports both ISO-DEP and NFC-DEP.
+    IsoDepAndNfcDep,
+    /// Type 2 tag, such as MIFARE Ultralight or NTAG.
+    Type2,
+    /// MIFARE Classic. Not NFC Forum compliant.
+    MifareClassic,
+    /// Vendor-specific type, returned by a custom [`SakClassifier`]. The meaning of the value is up to it.
+    Custom(u8),
+}
+
+/// Function deciding the [`CardType`] of a selected card, from its ATQA and SAK.
+///
+/// See [`Poller::set_sak_classifier`].
+pub type SakClassifier = fn(atqa: Atqa, sak: u8) -> CardType;
+
+/// Default [`SakClassifier`], following the NFC Forum interpretation of the SAK.
+///
+/// Custom classifiers can call this for the cards they don't handle specially.
+pub fn classify_sak(_atqa: Atqa, sak: u8) -> CardType {
+    match sak & 0x60 {
+        0x20 => CardType::IsoDep,
+        0x40 => CardType::NfcDep,
+        0x60 => CardType::IsoDepAndNfcDep,
+        _ if sak & 0x18 != 0 => CardType::MifareClassic,
+        _ => CardType::Type2,
+    }
+}
+
+/// How REQA/WUPA is retried when no card answers, see [`Poller::set_request_retry`].
+///
+/// Cards that just entered the field may still be powering up and miss the first request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RequestRetry {
+    /// Requests sent before giving up, including the first.
+    pub attempts: u8,
+    /// Wait between attempts, in 1/fc.
+    ///
+    /// Waited with [`LLReader::delay_1fc`], readers that can't time it retry right away.
+    pub guard_1fc: u32,
+}
+
+impl RequestRetry {
+    /// 4 attempts, 5 ms apart: ISO14443-3 gives cards 5 ms after entering the field to
+    /// accept a request. Adds 15 ms to each poll with no card in the field.
+    pub const DEFAULT: Self = Self {
+        attempts: 4,
+        guard_1fc: 67_800,
+    };
+}
+
+/// Finds and selects ISO14443A cards through a low level reader.
+///
+/// Owns no scratch buffer: anticollision and SELECT frames are at most 7 bytes out and 16 in,
+/// so a buffer in the poller would take as much memory as the locals it replaces.
 pub struct Poller<T: LLReader> {
     reader: T,
+    classifier: SakClassifier,
+    request_retry: RequestRetry,
+    strict_uid_size: bool,
 }
 
 #[derive(Debug)]
@@ -32,20 +211,73 @@ pub struct Poller<T: LLReader> {
 pub enum Error<T> {
     Lower(T),
     Protocol,
+    /// The BCC in an anticollision response didn't match the UID, it was likely corrupted.
+    BccMismatch,
+    /// Anticollision didn't converge within 8 collisions in a cascade level: too many
+    /// cards in the field, or noise that keeps colliding. Asking the user to remove some cards may help.
+    TooManyCollisions,
 }
 
 impl<T: ll::Error> Error<T> {
-    fn is_soft(&self) -> bool {
+    pub(crate) fn is_soft(&self) -> bool {
         match self {
             Self::Lower(l) => l.kind() == ll::ErrorKind::Timeout,
-            Self::Protocol => true,
+            Self::Protocol | Self::BccMismatch => true,
+            Self::TooManyCollisions => false,
         }
     }
 }
 
 impl<T: LLReader> Poller<T> {
     pub fn new(reader: T) -> Self {
-        Self { reader }
+        Self {
+            reader,
+            classifier: classify_sak,
+            request_retry: RequestRetry::DEFAULT,
+            strict_uid_size: true,
+        }
+    }
+
+    /// Set how REQA/WUPA is retried when selecting or enumerating cards. Default:
+    /// [`RequestRetry::DEFAULT`].
+    ///
+    /// `attempts` of 0 is taken as 1, the request is always sent once.
+    pub fn set_request_retry(&mut self, retry: RequestRetry) {
+        self.request_retry = RequestRetry {
+            attempts: retry.attempts.max(1),
+            ..retry
+        };
+    }
+
+    /// Whether a selected card's UID must have the size its ATQA announced. Default: true.
+    ///
+    /// If set, a mismatch fails the selection with [`Error::Protocol`]. Clear it for
+    /// non-compliant cards that get their ATQA wrong, the UID size is then decided by the SAK
+    /// alone and the mismatch is only logged.
+    pub fn set_strict_uid_size(&mut self, strict: bool) {
+        self.strict_uid_size = strict;
+    }
+
+    /// Use a custom function to decide the [`CardType`] of selected cards.
+    ///
+    /// Useful for proprietary cards whose SAK doesn't follow the standard interpretation.
+    /// Default: [`classify_sak`].
+    pub fn set_sak_classifier(&mut self, classifier: SakClassifier) {
+        self.classifier = classifier;
+    }
+
+    pub(crate) fn classify(&self, atqa: [u8; 2], sak: u8) -> CardType {
+        (self.classifier)(Atqa(atqa), sak)
+    }
+
+    pub(crate) fn card(&mut self, uid: Vec<u8, UID_MAX_LEN>, atqa: [u8; 2], sak: u8) -> Card<'_, T> {
+        Card {
+            card_type: self.classify(atqa, sak),
+            reader: &mut self.reader,
+            uid,
+            atqa,
+            sak,
+        }
     }
 
     async fn transceive_wupa(&mut self) -> Result<[u8; 2], Error<T::Error>> {
@@ -62,7 +294,6 @@ impl<T: LLReader> Poller<T> {
         Ok(rx)
     }
 
-    #[allow(unused)]
     async fn transceive_reqa(&mut self) -> Result<[u8; 2], Error<T::Error>> {
         let mut rx = [0; 2];
         let bits = self
@@ -86,15 +317,15 @@ impl<T: LLReader> Poller<T> {
         tx[1] = ((bits / 8) << 4) | (bits % 8);
         tx[2..].copy_from_slice(uid);
 
-        let mut rx = [0; 8];
+        let mut rx = [0; 16];
         let opts = Frame::Anticoll { bits: bits as _ };
         let got_bits = self.reader.transceive(&tx, &mut rx, opts).await.map_err(Error::Lower)?;
 
-        // If first bit is a collision, we haven't learned any new on the UID.
-        // Treat it as a communication error to prevent infinite loops.
+        // If the first bit is a collision, we haven't learned anything new on the UID. It's a
+        // collision like any other: the caller takes the bit as it is and counts it.
         if got_bits as u8 == bits {
             debug!("anticoll: got zero new bits");
-            return Err(Error::Protocol);
+            return Ok(uid_bits);
         }
         if got_bits < 16 {
             debug!("collision too early?");
@@ -110,18 +341,22 @@ impl<T: LLReader> Poller<T> {
             return Ok(new_uid_bits);
         }
 
-        // We got a complete UID. We should have exactly 40 bits. It's a protocol error otherwise:
+        // We got a complete UID. We should have at least 40 bits. It's a protocol error otherwise:
         // If we have 32..39, it means a collision occured during the BCC bit which should be impossible.
-        // If we have more than 40, it means the card is responding with too many bits.
-        if new_uid_bits != 40 {
+        if new_uid_bits < 40 {
             debug!("anticoll: got bad new_uid_bits {}", new_uid_bits);
             return Err(Error::Protocol);
         }
 
-        let bcc = uid[0] ^ uid[1] ^ uid[2] ^ uid[3];
-        if bcc as u8 != rx[6] {
-            debug!("bad BCC");
-            return Err(Error::Protocol);
+        // Some non-compliant cards respond with extra bytes after the BCC. As long as
+        // UID+BCC check out, ignore them.
+        if new_uid_bits > 40 {
+            debug!("anticoll: ignoring {} extra bits after BCC", new_uid_bits - 40);
+        }
+
+        if ll::bcc(uid) != rx[6] {
+            debug!("anticoll: bad BCC {:02x} for uid {}", rx[6], Bytes(uid));
+            return Err(Error::BccMismatch);
         }
 
         // Return the complete UID!
@@ -134,9 +369,12 @@ impl<T: LLReader> Poller<T> {
         tx[0] = 0x93 + cl * 2;
         tx[1] = 0x70; // 7 bytes, 0, bits
         tx[2..6].copy_from_slice(&uid);
-        tx[6] = uid[0] ^ uid[1] ^ uid[2] ^ uid[3];
+        tx[6] = ll::bcc(&uid);
         let mut rx = [0; 1];
-        let opts = Frame::Standard { timeout_1fc: 65536 };
+        let opts = Frame::Standard {
+            timeout_1fc: 65536,
+            crc: Crc::A,
+        };
         let bits = self.reader.transceive(&tx, &mut rx, opts).await.map_err(Error::Lower)?;
         if bits != 8 {
             debug!("SELECT response wrong length: {} bits", bits);
@@ -145,58 +383,151 @@ impl<T: LLReader> Poller<T> {
         Ok(rx[0])
     }
 
-    async fn transceive_hlta(&mut self) -> Result<(), Error<T::Error>> {
-        let tx = [0x50, 0x00];
-        let mut rx = [0; 1];
-        let opts = Frame::Standard { timeout_1fc: 65536 };
-        let _ = self.reader.transceive(&tx, &mut rx, opts).await.map_err(Error::Lower)?;
-        Ok(())
+    pub(crate) async fn transceive_hlta(&mut self) -> Result<(), Error<T::Error>> {
+        self.reader.transmit_only(&[0x50, 0x00], true).await.map_err(Error::Lower)
     }
 
-    pub async fn select_any(&mut self) -> Result<Card<'_, T>, Error<T::Error>> {
-        let atqa = retry!(4, self.transceive_wupa().await)?;
-
+    /// Run anticollision and SELECT for all cascade levels, returning the complete UID and the final SAK.
+    ///
+    /// The card may answer the first anticollision frame with its complete UID and BCC right away
+    /// (as it does when it's alone in the field), in which case we go straight to SELECT.
+    ///
+    /// Whether the UID is complete is decided by the cascade bit in the SAK, since that's what the
+    /// card's state machine follows. Some non-compliant cards set the cascade bit and the cascade
+    /// tag (0x88) inconsistently, if that happens it's logged and the SAK wins.
+    async fn anticoll_select(&mut self) -> Result<(Vec<u8, UID_MAX_LEN>, u8), Error<T::Error>> {
         let mut uid: Vec<u8, UID_MAX_LEN> = Vec::new();
-        let mut sak = 0;
-
-        for cl in 0..4 {
-            if cl == 3 {
-                debug!("too many cascade levels");
-                return Err(Error::Protocol);
-            }
 
+        for cl in 0..3 {
             let mut uid_part = [0; 4];
             let mut uid_bits = 0;
+            let mut collisions = 0;
             loop {
                 uid_bits = retry!(4, self.transceive_anticoll(cl, &mut uid_part, uid_bits).await)?;
                 if uid_bits == 32 {
                     break;
                 }
+                collisions += 1;
+                if collisions > ANTICOLL_MAX_COLLISIONS {
+                    debug!("anticoll: more than {} collisions", ANTICOLL_MAX_COLLISIONS);
+                    return Err(Error::TooManyCollisions);
+                }
                 uid_bits += 1;
             }
 
-            sak = retry!(4, self.transceive_select(cl, uid_part).await)?;
+            let sak = retry!(4, self.transceive_select(cl, uid_part).await)?;
 
-            if uid_part[0] == 0x88 {
-                uid.extend_from_slice(&uid_part[1..]).unwrap();
-            } else {
-                uid.extend_from_slice(&uid_part).unwrap();
-                break;
+            let cascade = sak & SAK_CASCADE != 0;
+            let has_ct = uid_part[0] == CASCADE_TAG;
+            if cascade != has_ct {
+                debug!(
+                    "anticoll: inconsistent cascade, uid_part={} sak={:02x}",
+                    Bytes(&uid_part),
+                    sak
+                );
+            }
+
+            if !cascade {
+                if uid.extend_from_slice(&uid_part).is_err() {
+                    debug!("anticoll: uid too long");
+                    return Err(Error::Protocol);
+                }
+                return Ok((uid, sak));
+            }
+
+            let uid_part = if has_ct { &uid_part[1..] } else { &uid_part[..] };
+            if uid.extend_from_slice(uid_part).is_err() {
+                debug!("anticoll: uid too long");
+                return Err(Error::Protocol);
+            }
+        }
+
+        debug!("too many cascade levels");
+        Err(Error::Protocol)
+    }
+
+    pub async fn select_any(&mut self) -> Result<Card<'_, T>, Error<T::Error>> {
+        let (uid, atqa, sak) = self.activate_any().await?;
+        Ok(self.card(uid, atqa, sak))
+    }
+
+    /// Try to select a card repeatedly, until `deadline` completes.
+    ///
+    /// `deadline` is typically a timer, such as `embassy_time::Timer::at(deadline)`. Returns
+    /// `None` if no card could be selected before it completed. Soft errors (no card,
+    /// protocol errors) are retried, other errors are returned.
+    ///
+    /// This is cancellation-safe: if this future is dropped before it completes, the reader's
+    /// [`field_off`](LLReader::field_off) turns the field off and leaves it idle. Returning,
+    /// including with `None` or an error, leaves the field on for the next poll.
+    pub async fn poll_until(&mut self, deadline: impl Future<Output = ()>) -> Result<Option<Card<'_, T>>, Error<T::Error>> {
+        let mut deadline = pin!(deadline);
+        let mut guard = PollGuard::new(self);
+        loop {
+            // Check the deadline first, so it's noticed even if the reader never returns Pending.
+            match select(deadline.as_mut(), guard.poller().activate_any()).await {
+                Either::First(()) => {
+                    guard.disarm();
+                    return Ok(None);
+                }
+                Either::Second(Ok((uid, atqa, sak))) => return Ok(Some(guard.disarm().card(uid, atqa, sak))),
+                Either::Second(Err(e)) if e.is_soft() => {}
+                Either::Second(Err(e)) => {
+                    guard.disarm();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Wake up and select any card. Returns the UID, ATQA and SAK.
+    async fn activate_any(&mut self) -> Result<(Vec<u8, UID_MAX_LEN>, [u8; 2], u8), Error<T::Error>> {
+        self.activate(true).await
+    }
+
+    /// Send WUPA if `wakeup`, REQA otherwise, following the [`RequestRetry`]. Returns the ATQA.
+    async fn request(&mut self, wakeup: bool) -> Result<[u8; 2], Error<T::Error>> {
+        let mut attempt = 1;
+        loop {
+            let res = match wakeup {
+                true => self.transceive_wupa().await,
+                false => self.transceive_reqa().await,
+            };
+            if res.is_ok() || attempt >= self.request_retry.attempts {
+                return res;
             }
+            attempt += 1;
+            self.reader.delay_1fc(self.request_retry.guard_1fc).await;
         }
+    }
+
+    /// Select any card, including halted ones with WUPA if `wakeup`, or only idle ones with REQA.
+    /// Returns the UID, ATQA and SAK.
+    pub(crate) async fn activate(&mut self, wakeup: bool) -> Result<(Vec<u8, UID_MAX_LEN>, [u8; 2], u8), Error<T::Error>> {
+        let atqa = self.request(wakeup).await?;
+
+        let (uid, sak) = self.anticoll_select().await?;
+        check_uid_size(atqa.into(), &uid, self.strict_uid_size)?;
 
         debug!("Got card! uid={} atqa={} sak={:02}", Bytes(&uid), Bytes(&atqa), sak);
+        if is_random_uid(&uid) {
+            debug!("card uses a random UID");
+        }
 
-        Ok(Card {
-            reader: &mut self.reader,
-            uid,
-            atqa,
-            sak,
-        })
+        Ok((uid, atqa, sak))
     }
 
+    /// Select the card with the given UID, without doing anticollision.
+    ///
+    /// Each cascade level is selected with the known UID bytes right away, so this is faster than
+    /// [`select_any`](Self::select_any) and only the card with that UID answers. The SAK must have
+    /// the cascade bit set on all levels but the last, or this fails with [`Error::Protocol`]: the
+    /// card's UID is not the size given.
+    ///
+    /// Note this doesn't work with random UIDs (see [`is_random_uid`]) obtained in a previous
+    /// activation, since the card will have picked a new one.
     pub async fn select_by_id(&mut self, uid: &[u8]) -> Result<Card<'_, T>, Error<T::Error>> {
-        let atqa = retry!(4, self.transceive_wupa().await)?;
+        let atqa = self.request(true).await?;
 
         let mut sak = 0;
 
@@ -214,99 +545,165 @@ impl<T: LLReader> Poller<T> {
             let uid_part = if cl == cln - 1 {
                 [uid[cl * 3], uid[cl * 3 + 1], uid[cl * 3 + 2], uid[cl * 3 + 3]]
             } else {
-                [0x88, uid[cl * 3], uid[cl * 3 + 1], uid[cl * 3 + 2]]
+                [CASCADE_TAG, uid[cl * 3], uid[cl * 3 + 1], uid[cl * 3 + 2]]
             };
 
             sak = retry!(4, self.transceive_select(cl as u8, uid_part).await)?;
+
+            let cascade = sak & SAK_CASCADE != 0;
+            if cascade != (cl != cln - 1) {
+                debug!("select: unexpected sak {:02x} at cascade level {}", sak, cl + 1);
+                return Err(Error::Protocol);
+            }
         }
+        check_uid_size(atqa.into(), uid, self.strict_uid_size)?;
 
         debug!("Got card! uid={} atqa={} sak={:02}", Bytes(&uid), Bytes(&atqa), sak);
 
-        Ok(Card {
-            reader: &mut self.reader,
-            uid: Vec::from_slice(uid).unwrap(),
-            atqa,
-            sak,
-        })
+        Ok(self.card(Vec::from_slice(uid).unwrap(), atqa, sak))
     }
 
     /// Search for all cards in the field, and return a list of their IDs.
     /// You can connect to one with [`Self::select_by_id`].
+    ///
+    /// Cards with a random UID (see [`is_random_uid`]) can't be connected to this way, since they
+    /// pick a new UID every time they're activated. They're still returned so they can be counted.
     pub async fn search<const N: usize>(&mut self) -> Result<Vec<Vec<u8, UID_MAX_LEN>, N>, Error<T::Error>> {
-        let mut res = Vec::new();
+        let mut cards: Vec<CardInfo, N> = Vec::new();
+        self.enumerate(&mut cards).await?;
+        Ok(cards.into_iter().map(|c| c.uid).collect())
+    }
+
+    /// Find all cards in the field (inventory), appending them to `out`.
+    ///
+    /// Each card found is selected then halted, so it stays quiet while the rest are found.
+    /// Stops when no more cards answer REQA, or when `out` is full. Cards already in `out`
+    /// aren't added again. Returns the number of cards added.
+    ///
+    /// Found cards are left halted: they can be woken up and connected to with [`Self::select_by_id`].
+    pub async fn enumerate<const N: usize>(&mut self, out: &mut Vec<CardInfo, N>) -> Result<usize, Error<T::Error>> {
+        let start = out.len();
 
-        'out: for _ in 0..(N * 4) {
-            let atqa = match retry!(4, self.transceive_reqa().await) {
+        for _ in 0..(N * 4) {
+            if out.is_full() {
+                break;
+            }
+
+            let atqa = match self.request(false).await {
                 Ok(x) => x,
                 Err(e) if e.is_soft() => break,
                 Err(e) => return Err(e),
             };
 
-            let mut uid: Vec<u8, UID_MAX_LEN> = Vec::new();
-            let mut sak = 0;
-
-            for cl in 0..4 {
-                if cl == 3 {
-                    debug!("too many cascade levels");
-                    return Err(Error::Protocol);
-                }
-
-                let mut uid_part = [0; 4];
-                let mut uid_bits = 0;
-                loop {
-                    uid_bits = match retry!(4, self.transceive_anticoll(cl, &mut uid_part, uid_bits).await) {
-                        Ok(x) => x,
-                        Err(e) if e.is_soft() => break 'out,
-                        Err(e) => return Err(e),
-                    };
-                    if uid_bits == 32 {
-                        break;
-                    }
-                    uid_bits += 1;
-                }
-
-                sak = match retry!(4, self.transceive_select(cl, uid_part).await) {
-                    Ok(x) => x,
-                    Err(e) if e.is_soft() => break 'out,
-                    Err(e) => return Err(e),
-                };
-
-                if uid_part[0] == 0x88 {
-                    uid.extend_from_slice(&uid_part[1..]).unwrap();
-                } else {
-                    uid.extend_from_slice(&uid_part).unwrap();
-                    break;
-                }
-            }
+            let (uid, sak) = match self.anticoll_select().await {
+                Ok(x) => x,
+                Err(e) if e.is_soft() => break,
+                Err(e) => return Err(e),
+            };
 
             debug!("Got card! uid={} atqa={} sak={:02}", Bytes(&uid), Bytes(&atqa), sak);
             let _ = self.transceive_hlta().await;
 
-            if !res.contains(&uid) {
-                res.push(uid).unwrap();
-                if res.is_full() {
-                    break;
-                }
+            if !out.iter().any(|c| c.uid == uid) {
+                let card_type = self.classify(atqa, sak);
+                let _ = out.push(CardInfo {
+                    uid,
+                    atqa,
+                    sak,
+                    card_type,
+                });
             }
         }
 
-        Ok(res)
+        Ok(out.len() - start)
+    }
+}
+
+/// Turns the field off if a poll is dropped before it completes, see [`Poller::poll_until`].
+pub(crate) struct PollGuard<'a, T: LLReader>(Option<&'a mut Poller<T>>);
+
+impl<'a, T: LLReader> PollGuard<'a, T> {
+    pub(crate) fn new(poller: &'a mut Poller<T>) -> Self {
+        Self(Some(poller))
+    }
+
+    pub(crate) fn poller(&mut self) -> &mut Poller<T> {
+        self.0.as_deref_mut().unwrap()
+    }
+
+    /// The poll completed, leave the field as it is.
+    pub(crate) fn disarm(mut self) -> &'a mut Poller<T> {
+        self.0.take().unwrap()
+    }
+}
+
+impl<T: LLReader> Drop for PollGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(poller) = self.0.take() {
+            debug!("poll cancelled, turning the field off");
+            poller.reader.field_off();
+        }
     }
 }
 
+/// A card found by [`Poller::enumerate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CardInfo {
+    pub uid: Vec<u8, UID_MAX_LEN>,
+    pub atqa: [u8; 2],
+    pub sak: u8,
+    pub card_type: CardType,
+}
+
 pub struct Card<'d, T: LLReader> {
     reader: &'d mut T,
 
     uid: Vec<u8, UID_MAX_LEN>,
     atqa: [u8; 2],
     sak: u8,
+    card_type: CardType,
+}
+
+impl<'d, T: LLReader + 'd> Card<'d, T> {
+    /// Card type, as decided by the poller's [`SakClassifier`].
+    pub fn card_type(&self) -> CardType {
+        self.card_type
+    }
+
+    /// Whether the card uses a random UID, see [`is_random_uid`].
+    pub fn is_random_uid(&self) -> bool {
+        is_random_uid(&self.uid)
+    }
+
+    /// Send `tx` as a standard frame with CRC, and return the response bytes, CRC stripped.
+    ///
+    /// The response must fit in `N` bytes. Waits [`EXCHANGE_TIMEOUT_1FC`] for it, use
+    /// [`transceive`](Reader::transceive) for another timeout. Fails with [`Error::Protocol`]
+    /// if the response doesn't end on a byte boundary.
+    pub async fn exchange<const N: usize>(&mut self, tx: &[u8]) -> Result<Vec<u8, N>, Error<T::Error>> {
+        let opts = Frame::Standard {
+            timeout_1fc: EXCHANGE_TIMEOUT_1FC,
+            crc: Crc::A,
+        };
+        let mut rx = [0; N];
+        let bits = self.reader.transceive(tx, &mut rx, opts).await.map_err(Error::Lower)?;
+        if bits % 8 != 0 {
+            debug!("exchange: response not a whole number of bytes: {} bits", bits);
+            return Err(Error::Protocol);
+        }
+        Ok(Vec::from_slice(&rx[..bits / 8]).unwrap())
+    }
 }
 
 impl<'d, T: LLReader + 'd> Reader for Card<'d, T> {
     type Error = T::Error;
 
     async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Self::Error> {
-        let opts = Frame::Standard { timeout_1fc };
+        let opts = Frame::Standard {
+            timeout_1fc,
+            crc: Crc::A,
+        };
         let res = self.reader.transceive(tx, rx, opts).await?;
         if res % 8 != 0 {
             panic!("last byte was not complete!");
@@ -325,4 +722,462 @@ impl<'d, T: LLReader + 'd> Reader for Card<'d, T> {
     fn sak(&self) -> u8 {
         self.sak
     }
+
+    async fn card_in_field(&mut self) -> Option<bool> {
+        self.reader.card_in_field().await
+    }
+
+    async fn reset_rx(&mut self) -> Result<(), Self::Error> {
+        self.reader.reset_rx().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::poll_once;
+    use hex_literal::hex;
+    use rnfc_traits::iso14443a::Reader as _;
+
+    use super::*;
+    use crate::test_util::{mock, StuckReader};
+
+    const SELECT: Frame = Frame::Standard {
+        timeout_1fc: 65536,
+        crc: Crc::A,
+    };
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(Crc::A.compute(&hex!("00 00")), Some(hex!("a0 1e")));
+        assert_eq!(Crc::A.compute(&hex!("12 34")), Some(hex!("26 cf")));
+        assert_eq!(Crc::B.compute(&hex!("00 00 00")), Some(hex!("cc c6")));
+        assert_eq!(Crc::None.compute(&hex!("00 00 00")), None);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_transceive_bits() {
+        let frame = Frame::Bits {
+            bits: 7,
+            timeout_1fc: ll::TRANSCEIVE_BITS_TIMEOUT_1FC,
+        };
+        let mut mock = mock!(
+            (frame, "26" => "0a" / 4),
+        );
+        let mut rx = [0; 1];
+        assert_eq!(mock.transceive_bits(&hex!("26"), 7, &mut rx).await.unwrap(), 4);
+        assert_eq!(rx, hex!("0a"));
+    }
+
+    #[test]
+    fn test_bcc() {
+        assert_eq!(ll::bcc(&hex!("01 02 03 04")), 0x04);
+        assert_eq!(ll::bcc(&hex!("88 04 d2 9a")), 0xc4);
+        assert_eq!(ll::bcc(&[]), 0x00);
+    }
+
+    #[test]
+    fn test_classify_sak() {
+        let atqa = Atqa(hex!("44 00"));
+        assert_eq!(classify_sak(atqa, 0x00), CardType::Type2);
+        assert_eq!(classify_sak(atqa, 0x08), CardType::MifareClassic);
+        assert_eq!(classify_sak(atqa, 0x18), CardType::MifareClassic);
+        assert_eq!(classify_sak(atqa, 0x20), CardType::IsoDep);
+        assert_eq!(classify_sak(atqa, 0x28), CardType::IsoDep);
+        assert_eq!(classify_sak(atqa, 0x40), CardType::NfcDep);
+        assert_eq!(classify_sak(atqa, 0x60), CardType::IsoDepAndNfcDep);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_custom_sak_classifier() {
+        fn classifier(atqa: Atqa, sak: u8) -> CardType {
+            match sak {
+                0x88 => CardType::Custom(1),
+                _ => classify_sak(atqa, sak),
+            }
+        }
+
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "88" / 8),
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "20" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        poller.set_sak_classifier(classifier);
+        assert_eq!(poller.select_any().await.unwrap().card_type(), CardType::Custom(1));
+        assert_eq!(poller.select_any().await.unwrap().card_type(), CardType::IsoDep);
+    }
+
+    #[test]
+    fn test_atqa() {
+        let atqa = Atqa(hex!("04 00"));
+        assert_eq!(atqa.uid_size(), Some(UidSize::Single));
+        assert_eq!(atqa.bit_frame_anticollision(), 0x04);
+        assert_eq!(atqa.proprietary(), 0);
+        assert_eq!(atqa.rfu(), 0);
+
+        let atqa = Atqa(hex!("44 03"));
+        assert_eq!(atqa.uid_size(), Some(UidSize::Double));
+        assert_eq!(atqa.uid_size().unwrap().uid_len(), 7);
+        assert_eq!(atqa.bit_frame_anticollision(), 0x04);
+        assert_eq!(atqa.proprietary(), 0x03);
+        assert_eq!(atqa.rfu(), 0);
+
+        let atqa = Atqa(hex!("a1 f0"));
+        assert_eq!(atqa.uid_size(), Some(UidSize::Triple));
+        assert_eq!(atqa.uid_size().unwrap().cascade_levels(), 3);
+        assert_eq!(atqa.bit_frame_anticollision(), 0x01);
+        assert_eq!(atqa.rfu(), 0xF020);
+
+        assert_eq!(Atqa(hex!("c4 00")).uid_size(), None);
+    }
+
+    /// Future that completes after being polled `n` times.
+    struct Countdown(usize);
+
+    impl Future for Countdown {
+        type Output = ();
+
+        fn poll(mut self: core::pin::Pin<&mut Self>, _cx: &mut core::task::Context<'_>) -> core::task::Poll<()> {
+            if self.0 == 0 {
+                return core::task::Poll::Ready(());
+            }
+            self.0 -= 1;
+            core::task::Poll::Pending
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_poll_until() {
+        let mock = mock!(
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.poll_until(Countdown(2)).await.unwrap().unwrap();
+        assert_eq!(card.uid(), hex!("01 02 03 04"));
+        assert!(!poller.reader.field_off);
+    }
+
+    #[test]
+    fn test_poll_until_dropped() {
+        let mut poller = Poller::new(StuckReader::default());
+        assert!(poll_once(poller.poll_until(core::future::pending())).is_pending());
+        assert!(poller.reader.field_off);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_request_retry() {
+        let mock = mock!(
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        poller.set_request_retry(RequestRetry {
+            attempts: 2,
+            guard_1fc: 1000,
+        });
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("01 02 03 04"));
+        assert_eq!(poller.reader.delayed_1fc, 1000);
+
+        // Gives up after the last attempt, without waiting after it.
+        poller.reader = mock!(
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+        );
+        assert!(poller.select_any().await.is_err());
+        assert_eq!(poller.reader.delayed_1fc, 1000);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_request_retry_enumerate_and_select_by_id() {
+        let mock = mock!(
+            (Frame::ReqA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+        );
+        let mut poller = Poller::new(mock);
+        // No attempts is taken as one.
+        poller.set_request_retry(RequestRetry {
+            attempts: 0,
+            guard_1fc: 1000,
+        });
+        let mut cards: heapless::Vec<CardInfo, 4> = heapless::Vec::new();
+        assert_eq!(poller.enumerate(&mut cards).await.unwrap(), 0);
+        assert!(poller.select_by_id(&hex!("01 02 03 04")).await.is_err());
+        assert_eq!(poller.reader.delayed_1fc, 0);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_poll_until_deadline() {
+        let mock = mock!(
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+            (Frame::WupA, "" => timeout),
+        );
+        let mut poller = Poller::new(mock);
+        assert!(poller.poll_until(Countdown(1)).await.unwrap().is_none());
+        // Timing out isn't cancelling, the field stays on for the next poll.
+        assert!(!poller.reader.field_off);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_enumerate() {
+        let mock = mock!(
+            (Frame::ReqA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "08" / 8),
+            (SELECT, "50 00" => timeout),
+            (Frame::ReqA, "" => "44 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 05 06 07 08 0c" / 56),
+            (SELECT, "93 70 05 06 07 08 0c" => "00" / 8),
+            (SELECT, "50 00" => timeout),
+            (Frame::ReqA, "" => timeout),
+            (Frame::ReqA, "" => timeout),
+            (Frame::ReqA, "" => timeout),
+            (Frame::ReqA, "" => timeout),
+        );
+        let mut poller = Poller::new(mock);
+        let mut cards: heapless::Vec<CardInfo, 4> = heapless::Vec::new();
+        assert_eq!(poller.enumerate(&mut cards).await.unwrap(), 2);
+        assert_eq!(cards[0].uid, hex!("01 02 03 04"));
+        assert_eq!(cards[0].card_type, CardType::MifareClassic);
+        assert_eq!(cards[1].uid, hex!("05 06 07 08"));
+        assert_eq!(cards[1].atqa, hex!("44 00"));
+        assert_eq!(cards[1].card_type, CardType::Type2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_single_size() {
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("01 02 03 04"));
+        assert_eq!(card.atqa(), hex!("04 00"));
+        assert_eq!(card.sak(), 0x08);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_random_uid() {
+        // 0x08 is the random UID prefix, not the cascade tag.
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 08 12 34 56 78" / 56),
+            (SELECT, "93 70 08 12 34 56 78" => "20" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("08 12 34 56"));
+        assert_eq!(card.sak(), 0x20);
+        assert!(card.is_random_uid());
+
+        assert!(!is_random_uid(&hex!("01 02 03 04")));
+        assert!(!is_random_uid(&hex!("08 d2 9a 52 3a 6b 80")));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_double_size() {
+        let mock = mock!(
+            (Frame::WupA, "" => "44 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 88 04 d2 9a c4" / 56),
+            (SELECT, "93 70 88 04 d2 9a c4" => "04" / 8),
+            (Frame::Anticoll { bits: 16 }, "95 20 00 00 00 00" => "95 20 52 3a 6b 80 83" / 56),
+            (SELECT, "95 70 52 3a 6b 80 83" => "20" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("04 d2 9a 52 3a 6b 80"));
+        assert_eq!(card.sak(), 0x20);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_collision() {
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 05 00 00 00" / 19),
+            (Frame::Anticoll { bits: 20 }, "93 24 05 00 00 00" => "93 24 05 02 03 04 00" / 56),
+            (SELECT, "93 70 05 02 03 04 00" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("05 02 03 04"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_extra_bytes() {
+        // Card sends garbage after the BCC.
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04 ff ff" / 72),
+            (SELECT, "93 70 01 02 03 04 04" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("01 02 03 04"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_bad_bcc() {
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 05" / 56),
+            // Retried, with the (ignored, since bits is 16) UID bytes from the bad response.
+            (Frame::Anticoll { bits: 16 }, "93 20 01 02 03 04" => "93 20 01 02 03 04 05" / 56),
+            (Frame::Anticoll { bits: 16 }, "93 20 01 02 03 04" => "93 20 01 02 03 04 05" / 56),
+            (Frame::Anticoll { bits: 16 }, "93 20 01 02 03 04" => "93 20 01 02 03 04 05" / 56),
+        );
+        let mut poller = Poller::new(mock);
+        assert!(matches!(poller.select_any().await, Err(Error::BccMismatch)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_by_id() {
+        let mock = mock!(
+            (Frame::WupA, "" => "44 00" / 16),
+            (SELECT, "93 70 88 04 d2 9a c4" => "04" / 8),
+            (SELECT, "95 70 52 3a 6b 80 83" => "20" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_by_id(&hex!("04 d2 9a 52 3a 6b 80")).await.unwrap();
+        assert_eq!(card.uid(), hex!("04 d2 9a 52 3a 6b 80"));
+        assert_eq!(card.sak(), 0x20);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_by_id_wrong_size() {
+        // The UID is given as double size, but the card says it's complete after the first level.
+        let mock = mock!(
+            (Frame::WupA, "" => "44 00" / 16),
+            (SELECT, "93 70 88 04 d2 9a c4" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let res = poller.select_by_id(&hex!("04 d2 9a 52 3a 6b 80")).await;
+        assert!(matches!(res, Err(Error::Protocol)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_cascade_tag_without_cascade_bit() {
+        // UID starts with the cascade tag, but the SAK says it's complete.
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 88 01 02 03 88" / 56),
+            (SELECT, "93 70 88 01 02 03 88" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("88 01 02 03"));
+        assert_eq!(card.sak(), 0x08);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_cascade_bit_without_cascade_tag() {
+        // SAK says the UID is not complete, but there's no cascade tag.
+        let mock = mock!(
+            (Frame::WupA, "" => "44 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "04" / 8),
+            (Frame::Anticoll { bits: 16 }, "95 20 00 00 00 00" => "95 20 05 06 07 08 0c" / 56),
+            (SELECT, "95 70 05 06 07 08 0c" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        // The 8 byte UID doesn't match the double size in the ATQA.
+        poller.set_strict_uid_size(false);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("01 02 03 04 05 06 07 08"));
+        assert_eq!(card.sak(), 0x08);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_select_uid_size_mismatch() {
+        // ATQA says double size, but the card completes its UID after the first level.
+        let mock = mock!(
+            (Frame::WupA, "" => "44 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        assert!(matches!(poller.select_any().await, Err(Error::Protocol)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_exchange() {
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (SELECT, "93 70 01 02 03 04 04" => "08" / 8),
+            (SELECT, "30 04" => "11 22 33 44" / 32),
+            (SELECT, "30 08" => "0a" / 4),
+        );
+        let mut poller = Poller::new(mock);
+        let mut card = poller.select_any().await.unwrap();
+        let res = card.exchange::<16>(&hex!("30 04")).await.unwrap();
+        assert_eq!(res, hex!("11 22 33 44"));
+        let res = card.exchange::<16>(&hex!("30 08")).await;
+        assert!(matches!(res, Err(Error::Protocol)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_anticoll_too_many_collisions() {
+        // Every response collides right after the bit we chose for the previous collision.
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 00 00 00 00" / 17),
+            (Frame::Anticoll { bits: 18 }, "93 22 00 00 00 00" => "93 20 00 00 00 00" / 19),
+            (Frame::Anticoll { bits: 20 }, "93 24 00 00 00 00" => "93 20 00 00 00 00" / 21),
+            (Frame::Anticoll { bits: 22 }, "93 26 00 00 00 00" => "93 20 00 00 00 00" / 23),
+            (Frame::Anticoll { bits: 24 }, "93 30 00 00 00 00" => "93 20 00 00 00 00" / 25),
+            (Frame::Anticoll { bits: 26 }, "93 32 00 00 00 00" => "93 20 00 00 00 00" / 27),
+            (Frame::Anticoll { bits: 28 }, "93 34 00 00 00 00" => "93 20 00 00 00 00" / 29),
+            (Frame::Anticoll { bits: 30 }, "93 36 00 00 00 00" => "93 20 00 00 00 00" / 31),
+            (Frame::Anticoll { bits: 32 }, "93 40 00 00 00 00" => "93 20 00 00 00 00" / 33),
+        );
+        let mut poller = Poller::new(mock);
+        assert!(matches!(poller.select_any().await, Err(Error::TooManyCollisions)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_anticoll_persistent_collision() {
+        // The first new bit always collides, so each frame only learns the bit we chose.
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20" / 16),
+            (Frame::Anticoll { bits: 17 }, "93 21 00 00 00 00" => "93 21" / 17),
+            (Frame::Anticoll { bits: 18 }, "93 22 00 00 00 00" => "93 22" / 18),
+            (Frame::Anticoll { bits: 19 }, "93 23 00 00 00 00" => "93 23" / 19),
+            (Frame::Anticoll { bits: 20 }, "93 24 00 00 00 00" => "93 24" / 20),
+            (Frame::Anticoll { bits: 21 }, "93 25 00 00 00 00" => "93 25" / 21),
+            (Frame::Anticoll { bits: 22 }, "93 26 00 00 00 00" => "93 26" / 22),
+            (Frame::Anticoll { bits: 23 }, "93 27 00 00 00 00" => "93 27" / 23),
+            (Frame::Anticoll { bits: 24 }, "93 30 00 00 00 00" => "93 30" / 24),
+        );
+        let mut poller = Poller::new(mock);
+        assert!(matches!(poller.select_any().await, Err(Error::TooManyCollisions)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_anticoll_collision_on_first_bit() {
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20" / 16),
+            (Frame::Anticoll { bits: 17 }, "93 21 00 00 00 00" => "93 21 04 11 22 33 04" / 56),
+            (SELECT, "93 70 04 11 22 33 04" => "08" / 8),
+        );
+        let mut poller = Poller::new(mock);
+        let card = poller.select_any().await.unwrap();
+        assert_eq!(card.uid(), hex!("04 11 22 33"));
+    }
 }
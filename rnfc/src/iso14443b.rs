@@ -0,0 +1,242 @@
+//! ISO14443-3 Type B.
+
+use rnfc_traits::iso14443b::Timings;
+
+/// ATQB length, not including the CRC. Extended ATQBs are one byte longer.
+pub const ATQB_LEN: usize = 12;
+
+const ATQB_HEADER: u8 = 0x50;
+const CMD_ATTRIB: u8 = 0x1D;
+
+/// Frame sizes for FSCI/FSDI 0 to 8. Higher (RFU) values are interpreted as 256.
+const FS_TABLE: [u16; 9] = [16, 24, 32, 40, 48, 64, 96, 128, 256];
+
+/// Bit rates above the default 106 kbps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bitrate {
+    Kbps106,
+    Kbps212,
+    Kbps424,
+    Kbps848,
+}
+
+impl Bitrate {
+    /// Bit rate divisor D, as coded in ATTRIB param 2.
+    fn code(self) -> u8 {
+        match self {
+            Self::Kbps106 => 0b00,
+            Self::Kbps212 => 0b01,
+            Self::Kbps424 => 0b10,
+            Self::Kbps848 => 0b11,
+        }
+    }
+}
+
+/// Bit rate capability byte of the ATQB protocol info.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BitrateCapability(pub u8);
+
+impl BitrateCapability {
+    /// The card requires the same bit rate in both directions.
+    pub fn same_both_directions(&self) -> bool {
+        self.0 & 0x80 != 0
+    }
+
+    /// The card can send at `bitrate`.
+    pub fn picc_to_pcd(&self, bitrate: Bitrate) -> bool {
+        match bitrate {
+            Bitrate::Kbps106 => true,
+            Bitrate::Kbps212 => self.0 & 0x10 != 0,
+            Bitrate::Kbps424 => self.0 & 0x20 != 0,
+            Bitrate::Kbps848 => self.0 & 0x40 != 0,
+        }
+    }
+
+    /// The card can receive at `bitrate`.
+    pub fn pcd_to_picc(&self, bitrate: Bitrate) -> bool {
+        match bitrate {
+            Bitrate::Kbps106 => true,
+            Bitrate::Kbps212 => self.0 & 0x01 != 0,
+            Bitrate::Kbps424 => self.0 & 0x02 != 0,
+            Bitrate::Kbps848 => self.0 & 0x04 != 0,
+        }
+    }
+}
+
+/// ATQB (Answer To Request, Type B), the response to REQB/WUPB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Atqb {
+    pupi: [u8; 4],
+    application_data: [u8; 4],
+    /// Protocol info, the last byte is only valid if `extended`.
+    protocol_info: [u8; 4],
+    extended: bool,
+}
+
+impl Atqb {
+    /// Parse an ATQB, without CRC. Returns `None` if it's malformed.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let extended = match data.len() {
+            ATQB_LEN => false,
+            n if n == ATQB_LEN + 1 => true,
+            n => {
+                debug!("ATQB wrong length: {}", n);
+                return None;
+            }
+        };
+        if data[0] != ATQB_HEADER {
+            debug!("ATQB wrong header: {:02x}", data[0]);
+            return None;
+        }
+
+        let mut protocol_info = [0; 4];
+        protocol_info[..data.len() - 9].copy_from_slice(&data[9..]);
+        Some(Self {
+            pupi: data[1..5].try_into().unwrap(),
+            application_data: data[5..9].try_into().unwrap(),
+            protocol_info,
+            extended,
+        })
+    }
+
+    /// Pseudo-Unique PICC Identifier.
+    pub fn pupi(&self) -> [u8; 4] {
+        self.pupi
+    }
+
+    pub fn application_data(&self) -> [u8; 4] {
+        self.application_data
+    }
+
+    pub fn bitrate_capability(&self) -> BitrateCapability {
+        BitrateCapability(self.protocol_info[0])
+    }
+
+    /// Max frame size the card can receive (FSC), in bytes, including the CRC.
+    pub fn max_frame_size(&self) -> usize {
+        let fsci = (self.protocol_info[1] >> 4) as usize;
+        FS_TABLE[fsci.min(FS_TABLE.len() - 1)] as usize
+    }
+
+    /// Protocol type (low 4 bits of protocol info byte 2).
+    pub fn protocol_type(&self) -> u8 {
+        self.protocol_info[1] & 0x0F
+    }
+
+    /// The card supports ISO14443-4 (ISO-DEP).
+    pub fn supports_iso_dep(&self) -> bool {
+        self.protocol_type() & 0x09 == 0x01
+    }
+
+    /// Frame Waiting time Integer.
+    pub fn fwi(&self) -> u8 {
+        self.protocol_info[2] >> 4
+    }
+
+    /// The card supports NAD.
+    pub fn supports_nad(&self) -> bool {
+        self.protocol_info[2] & 0x02 != 0
+    }
+
+    /// The card supports CID.
+    pub fn supports_cid(&self) -> bool {
+        self.protocol_info[2] & 0x01 != 0
+    }
+
+    /// Start-up Frame Guard time Integer, only present in extended ATQBs.
+    pub fn sfgi(&self) -> Option<u8> {
+        self.extended.then_some(self.protocol_info[3] >> 4)
+    }
+
+    /// Fastest bit rate supported by both the card and `reader`, usable in both directions.
+    ///
+    /// `reader` is the reader's own capability, coded like the card's.
+    pub fn max_common_bitrate(&self, reader: BitrateCapability) -> Bitrate {
+        let card = self.bitrate_capability();
+        [Bitrate::Kbps848, Bitrate::Kbps424, Bitrate::Kbps212]
+            .into_iter()
+            .find(|&b| card.pcd_to_picc(b) && card.picc_to_pcd(b) && reader.pcd_to_picc(b) && reader.picc_to_pcd(b))
+            .unwrap_or(Bitrate::Kbps106)
+    }
+
+    /// Build the ATTRIB command selecting this card, without CRC.
+    ///
+    /// `fsd` is the max frame size we can receive, rounded down to a supported value.
+    /// Higher layer INF is not supported.
+    pub fn attrib(&self, timings: &Timings, fsd: usize, bitrate: Bitrate, cid: u8) -> [u8; 9] {
+        let fsdi = FS_TABLE.iter().rposition(|&fs| fs as usize <= fsd).unwrap_or(0) as u8;
+        let cid = if self.supports_cid() { cid & 0x0F } else { 0 };
+
+        let mut res = [0; 9];
+        res[0] = CMD_ATTRIB;
+        res[1..5].copy_from_slice(&self.pupi);
+        res[5] = timings.attrib_param1();
+        res[6] = bitrate.code() << 6 | bitrate.code() << 4 | fsdi;
+        res[7] = self.protocol_type() & 0x07;
+        res[8] = cid;
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+
+    const ATQB: [u8; 12] = hex!("50 11 22 33 44 aa bb cc dd 01 81 e1");
+
+    #[test]
+    fn test_atqb_parse() {
+        let atqb = Atqb::parse(&ATQB).unwrap();
+        assert_eq!(atqb.pupi(), hex!("11 22 33 44"));
+        assert_eq!(atqb.application_data(), hex!("aa bb cc dd"));
+        assert_eq!(atqb.max_frame_size(), 256);
+        assert!(atqb.supports_iso_dep());
+        assert_eq!(atqb.fwi(), 14);
+        assert!(!atqb.supports_nad());
+        assert!(atqb.supports_cid());
+        assert_eq!(atqb.sfgi(), None);
+
+        let caps = atqb.bitrate_capability();
+        assert!(!caps.same_both_directions());
+        assert!(caps.pcd_to_picc(Bitrate::Kbps212));
+        assert!(!caps.pcd_to_picc(Bitrate::Kbps424));
+        assert!(!caps.picc_to_pcd(Bitrate::Kbps212));
+
+        let atqb = Atqb::parse(&hex!("50 11 22 33 44 aa bb cc dd 00 40 e0 70")).unwrap();
+        assert_eq!(atqb.max_frame_size(), 48);
+        assert!(!atqb.supports_iso_dep());
+        assert_eq!(atqb.sfgi(), Some(7));
+    }
+
+    #[test]
+    fn test_atqb_parse_invalid() {
+        assert_eq!(Atqb::parse(&ATQB[..11]), None);
+        assert_eq!(Atqb::parse(&hex!("51 11 22 33 44 aa bb cc dd 01 81 e1")), None);
+    }
+
+    #[test]
+    fn test_max_common_bitrate() {
+        let atqb = Atqb::parse(&hex!("50 11 22 33 44 aa bb cc dd 33 81 e1")).unwrap();
+        assert_eq!(atqb.max_common_bitrate(BitrateCapability(0x77)), Bitrate::Kbps424);
+        assert_eq!(atqb.max_common_bitrate(BitrateCapability(0x11)), Bitrate::Kbps212);
+        assert_eq!(atqb.max_common_bitrate(BitrateCapability(0x00)), Bitrate::Kbps106);
+    }
+
+    #[test]
+    fn test_attrib() {
+        let atqb = Atqb::parse(&ATQB).unwrap();
+        assert_eq!(
+            atqb.attrib(&Timings::default(), 256, Bitrate::Kbps106, 1),
+            hex!("1d 11 22 33 44 00 08 01 01")
+        );
+        assert_eq!(
+            atqb.attrib(&Timings::default(), 100, Bitrate::Kbps212, 0),
+            hex!("1d 11 22 33 44 00 56 01 00")
+        );
+    }
+}
@@ -0,0 +1,850 @@
+use rnfc_traits::iso14443a_ll::{Error as _, ErrorKind};
+use rnfc_traits::iso15693::Reader;
+
+use crate::fmt::Bytes;
+
+/// UID length in bytes. UIDs are stored in transmission order (LSB first).
+pub const UID_LEN: usize = 8;
+
+/// Max response length we can receive. Larger multi-block reads must be split by the caller.
+pub const RESPONSE_MAX_LEN: usize = 256;
+
+/// Timeout for a response.
+///
+/// The tag answers after t1 = 4352/fc, this gives plenty of margin for the reader's own latency.
+const TIMEOUT_1FC: u32 = 65536;
+
+/// Timeout for the response to a write.
+///
+/// Without the Option flag, the tag answers once the EEPROM write is done. The write time is
+/// IC specific, up to about 20 ms for common ICs. This is about 30 ms.
+const WRITE_TIMEOUT_1FC: u32 = 409600;
+
+/// Max block size, per the spec.
+pub const BLOCK_MAX_LEN: usize = 32;
+
+/// Max parameters length of a [custom command](Tag::custom_command): a password, or a
+/// block and its address.
+pub const CUSTOM_PARAMS_MAX_LEN: usize = 2 + BLOCK_MAX_LEN;
+
+// Request flags
+const FLAG_DATA_RATE_HIGH: u8 = 0x02;
+const FLAG_SELECT: u8 = 0x10;
+const FLAG_ADDRESS: u8 = 0x20;
+const FLAG_OPTION: u8 = 0x40;
+
+// Response flags
+const FLAG_ERROR: u8 = 0x01;
+
+// Commands
+const CMD_READ_SINGLE_BLOCK: u8 = 0x20;
+const CMD_WRITE_SINGLE_BLOCK: u8 = 0x21;
+const CMD_SELECT: u8 = 0x25;
+const CMD_READ_MULTIPLE_BLOCKS: u8 = 0x23;
+const CMD_GET_SYSTEM_INFO: u8 = 0x2B;
+// Extended commands, with 2-byte block addresses.
+const CMD_EXT_READ_SINGLE_BLOCK: u8 = 0x30;
+const CMD_EXT_WRITE_SINGLE_BLOCK: u8 = 0x31;
+const CMD_EXT_READ_MULTIPLE_BLOCKS: u8 = 0x33;
+const CMD_EXT_GET_SYSTEM_INFO: u8 = 0x3B;
+
+/// First custom command code. Custom commands go up to 0xDF, proprietary ones from 0xE0.
+const CMD_CUSTOM_FIRST: u8 = 0xA0;
+
+// Get System Information info flags
+const INFO_DSFID: u8 = 0x01;
+const INFO_AFI: u8 = 0x02;
+const INFO_MEMORY: u8 = 0x04;
+const INFO_IC_REFERENCE: u8 = 0x08;
+/// Fields requested with Get Extended System Information, they use the same flags.
+const EXT_INFO_REQUEST: u8 = INFO_DSFID | INFO_AFI | INFO_MEMORY | INFO_IC_REFERENCE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    Lower(E),
+    /// The tag responded with the error flag set. Contains the error code.
+    Tag(u8),
+    Protocol,
+    /// Output buffer too small for the response.
+    BufferTooSmall,
+    /// Reading back a written block returned different data.
+    VerifyFailed,
+}
+
+/// NXP ICODE SLIX custom commands, for [`Tag::custom_command`] with [`MFG_CODE`](icode::MFG_CODE).
+///
+/// For example, `tag.custom_command(icode::MFG_CODE, icode::CMD_GET_RANDOM_NUMBER, &[], &mut out)`
+/// returns 2 random bytes, which [`CMD_SET_PASSWORD`](icode::CMD_SET_PASSWORD) takes XORed
+/// with the password, after the password identifier. Which commands are available depends on
+/// the IC (SLIX, SLIX-S, SLIX-L, SLIX2), check its datasheet, as for their parameters.
+pub mod icode {
+    /// IC manufacturer code of NXP.
+    pub const MFG_CODE: u8 = 0x04;
+
+    pub const CMD_INVENTORY_READ: u8 = 0xA0;
+    pub const CMD_SET_EAS: u8 = 0xA2;
+    pub const CMD_RESET_EAS: u8 = 0xA3;
+    pub const CMD_LOCK_EAS: u8 = 0xA4;
+    pub const CMD_EAS_ALARM: u8 = 0xA5;
+    pub const CMD_PASSWORD_PROTECT_EAS_AFI: u8 = 0xA6;
+    pub const CMD_WRITE_EAS_ID: u8 = 0xA7;
+    pub const CMD_GET_NXP_SYSTEM_INFO: u8 = 0xAB;
+    pub const CMD_GET_RANDOM_NUMBER: u8 = 0xB2;
+    pub const CMD_SET_PASSWORD: u8 = 0xB3;
+    pub const CMD_WRITE_PASSWORD: u8 = 0xB4;
+    pub const CMD_LOCK_PASSWORD: u8 = 0xB5;
+    pub const CMD_PROTECT_PAGE: u8 = 0xB6;
+    pub const CMD_LOCK_PAGE_PROTECTION_CONDITION: u8 = 0xB7;
+    pub const CMD_GET_MULTIPLE_BLOCK_PROTECTION_STATUS: u8 = 0xB8;
+    pub const CMD_DESTROY: u8 = 0xB9;
+    pub const CMD_ENABLE_PRIVACY: u8 = 0xBA;
+    pub const CMD_64BIT_PASSWORD_PROTECTION: u8 = 0xBB;
+    pub const CMD_READ_SIGNATURE: u8 = 0xBD;
+
+    // Password identifiers
+    pub const PASSWORD_READ: u8 = 0x01;
+    pub const PASSWORD_WRITE: u8 = 0x02;
+    pub const PASSWORD_PRIVACY: u8 = 0x04;
+    pub const PASSWORD_DESTROY: u8 = 0x08;
+    pub const PASSWORD_EAS_AFI: u8 = 0x10;
+}
+
+/// Which tags a command is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressMode {
+    /// Only the tag with this UID.
+    Addressed([u8; UID_LEN]),
+    /// Any tag, which only makes sense when there's a single tag in the field.
+    NonAddressed,
+    /// Only the tag in the Selected state, see [`Tag::select`].
+    Selected,
+}
+
+/// Options for [`Tag::write_single_block_with_options`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WriteOptions {
+    /// Set the Option flag: the tag holds its response until the reader sends an EOF.
+    ///
+    /// Some ICs require it for writes, check the datasheet.
+    pub option_flag: bool,
+    /// Read the block back after writing, and fail with [`Error::VerifyFailed`] on mismatch.
+    pub verify: bool,
+}
+
+/// Memory size, as reported by Get System Information.
+///
+/// Tags with more than 256 blocks report it with Get Extended System Information, and need
+/// the extended commands for blocks above 255. Block operations use them automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MemorySize {
+    pub block_count: u16,
+    /// Block size in bytes.
+    pub block_size: u8,
+}
+
+/// Response to Get System Information. Fields the tag doesn't report are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemInfo {
+    pub uid: [u8; UID_LEN],
+    pub dsfid: Option<u8>,
+    pub afi: Option<u8>,
+    pub memory: Option<MemorySize>,
+    pub ic_reference: Option<u8>,
+}
+
+/// An ISO15693 tag.
+pub struct Tag<T: Reader> {
+    reader: T,
+    /// UID for addressed mode. `None` for non-addressed mode, which only
+    /// makes sense when there's a single tag in the field.
+    uid: Option<[u8; UID_LEN]>,
+}
+
+impl<T: Reader> Tag<T>
+where
+    T::Error: crate::fmt::Format,
+{
+    pub fn new(reader: T, uid: Option<[u8; UID_LEN]>) -> Self {
+        Self { reader, uid }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.reader
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.reader
+    }
+
+    pub fn uid(&self) -> Option<&[u8; UID_LEN]> {
+        self.uid.as_ref()
+    }
+
+    /// Read the tag's system information, including block size and count.
+    pub async fn get_system_info(&mut self) -> Result<SystemInfo, Error<T::Error>> {
+        let mut rx = [0; 32];
+        let res = self.command(CMD_GET_SYSTEM_INFO, false, &[], &mut rx).await?;
+        Self::parse_system_info(res, false)
+    }
+
+    /// Read the tag's system information with Get Extended System Information, for tags with
+    /// more than 256 blocks.
+    pub async fn get_extended_system_info(&mut self) -> Result<SystemInfo, Error<T::Error>> {
+        let mode = match self.uid {
+            Some(uid) => AddressMode::Addressed(uid),
+            None => AddressMode::NonAddressed,
+        };
+        // The info request goes before the UID, like the manufacturer code of custom commands.
+        let mut tx = [0; 3 + UID_LEN];
+        let n = write_header(&mut tx, CMD_EXT_GET_SYSTEM_INFO, Some(EXT_INFO_REQUEST), mode, false);
+
+        let mut rx = [0; 32];
+        let len = self.exchange(CMD_EXT_GET_SYSTEM_INFO, &tx[..n], &mut rx, TIMEOUT_1FC).await?;
+        let res = Self::parse_response(CMD_EXT_GET_SYSTEM_INFO, &rx[..len])?;
+        Self::parse_system_info(res, true)
+    }
+
+    /// Discover the tag's memory layout: Get System Information, falling back to Get Extended
+    /// System Information if the tag rejects it or doesn't report its memory size in it.
+    pub async fn discover_system_info(&mut self) -> Result<SystemInfo, Error<T::Error>> {
+        match self.get_system_info().await {
+            Ok(info) if info.memory.is_some() => Ok(info),
+            Ok(_) | Err(Error::Tag(_)) => {
+                debug!("no memory size in system info, trying extended");
+                self.get_extended_system_info().await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn parse_system_info(res: &[u8], extended: bool) -> Result<SystemInfo, Error<T::Error>> {
+        if res.len() < 1 + UID_LEN {
+            debug!("system info too short");
+            return Err(Error::Protocol);
+        }
+        let info_flags = res[0];
+        let mut uid = [0; UID_LEN];
+        uid.copy_from_slice(&res[1..][..UID_LEN]);
+
+        let mut rest = &res[1 + UID_LEN..];
+        let mut take = |n: usize| -> Result<&[u8], Error<T::Error>> {
+            if rest.len() < n {
+                debug!("system info too short");
+                return Err(Error::Protocol);
+            }
+            let (a, b) = rest.split_at(n);
+            rest = b;
+            Ok(a)
+        };
+
+        let dsfid = match info_flags & INFO_DSFID {
+            0 => None,
+            _ => Some(take(1)?[0]),
+        };
+        let afi = match info_flags & INFO_AFI {
+            0 => None,
+            _ => Some(take(1)?[0]),
+        };
+        let memory = match info_flags & INFO_MEMORY {
+            0 => None,
+            // The extended response has a 2-byte block count.
+            _ if extended => {
+                let m = take(3)?;
+                Some(MemorySize {
+                    block_count: u16::from_le_bytes([m[0], m[1]]).wrapping_add(1),
+                    block_size: (m[2] & 0x1F) + 1,
+                })
+            }
+            _ => {
+                let m = take(2)?;
+                Some(MemorySize {
+                    block_count: m[0] as u16 + 1,
+                    block_size: (m[1] & 0x1F) + 1,
+                })
+            }
+        };
+        let ic_reference = match info_flags & INFO_IC_REFERENCE {
+            0 => None,
+            _ => Some(take(1)?[0]),
+        };
+
+        Ok(SystemInfo {
+            uid,
+            dsfid,
+            afi,
+            memory,
+            ic_reference,
+        })
+    }
+
+    /// Read `count` blocks starting at `first` with a single Read Multiple Blocks command.
+    ///
+    /// Block data is written contiguously to `out`. Returns the number of bytes written.
+    pub async fn read_multiple_blocks(&mut self, first: u16, count: u8, out: &mut [u8]) -> Result<usize, Error<T::Error>> {
+        self.read_multiple_blocks_inner(first, count, out, None).await
+    }
+
+    /// Same as [`read_multiple_blocks`](Self::read_multiple_blocks), but also request the
+    /// block security status. One status byte per block is written to `status`.
+    pub async fn read_multiple_blocks_with_status(
+        &mut self,
+        first: u16,
+        count: u8,
+        out: &mut [u8],
+        status: &mut [u8],
+    ) -> Result<usize, Error<T::Error>> {
+        self.read_multiple_blocks_inner(first, count, out, Some(status)).await
+    }
+
+    async fn read_multiple_blocks_inner(
+        &mut self,
+        first: u16,
+        count: u8,
+        out: &mut [u8],
+        status: Option<&mut [u8]>,
+    ) -> Result<usize, Error<T::Error>> {
+        if count == 0 {
+            return Ok(0);
+        }
+        let count = count as usize;
+        if let Some(status) = &status {
+            if status.len() < count {
+                return Err(Error::BufferTooSmall);
+            }
+        }
+
+        let with_status = status.is_some();
+        // The tag expects the number of blocks minus one.
+        let last = first as usize + count - 1;
+        let mut params = [0; 4];
+        let (cmd, params) = match u8::try_from(last) {
+            Ok(_) => {
+                params[..2].copy_from_slice(&[first as u8, (count - 1) as u8]);
+                (CMD_READ_MULTIPLE_BLOCKS, &params[..2])
+            }
+            Err(_) => {
+                params[..2].copy_from_slice(&first.to_le_bytes());
+                params[2..].copy_from_slice(&((count - 1) as u16).to_le_bytes());
+                (CMD_EXT_READ_MULTIPLE_BLOCKS, &params[..])
+            }
+        };
+        let mut rx = [0; RESPONSE_MAX_LEN];
+        let res = self.command(cmd, with_status, params, &mut rx).await?;
+
+        // The block size isn't in the response, derive it from the length.
+        let stride = res.len() / count;
+        if stride * count != res.len() || stride <= with_status as usize {
+            debug!("read multiple blocks: bad response len {} for {} blocks", res.len(), count);
+            return Err(Error::Protocol);
+        }
+        let block_size = stride - with_status as usize;
+        if out.len() < block_size * count {
+            return Err(Error::BufferTooSmall);
+        }
+
+        match status {
+            Some(status) => {
+                for (i, chunk) in res.chunks_exact(stride).enumerate() {
+                    status[i] = chunk[0];
+                    out[i * block_size..][..block_size].copy_from_slice(&chunk[1..]);
+                }
+            }
+            None => out[..res.len()].copy_from_slice(res),
+        }
+
+        Ok(block_size * count)
+    }
+
+    /// Put the tag with `uid` in the Selected state, for [`AddressMode::Selected`].
+    ///
+    /// Any other tag in the Selected state goes back to the Ready state.
+    pub async fn select(&mut self, uid: &[u8; UID_LEN]) -> Result<(), Error<T::Error>> {
+        let mut rx = [0; 8];
+        self.command_with(CMD_SELECT, AddressMode::Addressed(*uid), false, &[], &mut rx, TIMEOUT_1FC)
+            .await?;
+        Ok(())
+    }
+
+    /// Read `block` with Read Single Block into `out`. Returns the block size.
+    pub async fn read_single_block(&mut self, block: u16, out: &mut [u8]) -> Result<usize, Error<T::Error>> {
+        let mode = match self.uid {
+            Some(uid) => AddressMode::Addressed(uid),
+            None => AddressMode::NonAddressed,
+        };
+        let mut rx = [0; 1 + BLOCK_MAX_LEN];
+        let res = self.read_single_block_with(block, mode, &mut rx).await?;
+        if out.len() < res.len() {
+            return Err(Error::BufferTooSmall);
+        }
+        out[..res.len()].copy_from_slice(res);
+        Ok(res.len())
+    }
+
+    async fn read_single_block_with<'a>(
+        &mut self,
+        block: u16,
+        mode: AddressMode,
+        rx: &'a mut [u8],
+    ) -> Result<&'a [u8], Error<T::Error>> {
+        let mut params = [0; 2];
+        let (cmd, n) = block_address(CMD_READ_SINGLE_BLOCK, CMD_EXT_READ_SINGLE_BLOCK, block, &mut params);
+        self.command_with(cmd, mode, false, &params[..n], rx, TIMEOUT_1FC).await
+    }
+
+    /// Write `data` to `block` with Write Single Block. `data` must be exactly one block.
+    ///
+    /// Panics if `data` is empty or longer than [`BLOCK_MAX_LEN`].
+    pub async fn write_single_block(&mut self, block: u16, data: &[u8], mode: AddressMode) -> Result<(), Error<T::Error>> {
+        self.write_single_block_with_options(block, data, mode, WriteOptions::default())
+            .await
+    }
+
+    /// Same as [`write_single_block`](Self::write_single_block), with [`WriteOptions`].
+    pub async fn write_single_block_with_options(
+        &mut self,
+        block: u16,
+        data: &[u8],
+        mode: AddressMode,
+        options: WriteOptions,
+    ) -> Result<(), Error<T::Error>> {
+        assert!(!data.is_empty() && data.len() <= BLOCK_MAX_LEN);
+        let mut params = [0; 2 + BLOCK_MAX_LEN];
+        let (cmd, n) = block_address(CMD_WRITE_SINGLE_BLOCK, CMD_EXT_WRITE_SINGLE_BLOCK, block, &mut params);
+        params[n..][..data.len()].copy_from_slice(data);
+        let params = &params[..n + data.len()];
+
+        let mut rx = [0; 8];
+        if options.option_flag {
+            // The tag answers right away only on error. Otherwise the timeout leaves it time
+            // to write, then the EOF asks for the response.
+            match self.request(cmd, mode, true, params, &mut rx, WRITE_TIMEOUT_1FC).await {
+                Ok(len) => {
+                    Self::parse_response(cmd, &rx[..len])?;
+                    debug!("write single block: response before EOF");
+                    return Err(Error::Protocol);
+                }
+                Err(Error::Lower(e)) if e.kind() == ErrorKind::Timeout => {}
+                Err(e) => return Err(e),
+            }
+            let len = match self.reader.send_eof(&mut rx, TIMEOUT_1FC).await {
+                Ok(len) => len,
+                Err(e) => {
+                    debug!("write single block: EOF failed: {:?}", e);
+                    return Err(Error::Lower(e));
+                }
+            };
+            Self::parse_response(cmd, &rx[..len])?;
+        } else {
+            self.command_with(cmd, mode, false, params, &mut rx, WRITE_TIMEOUT_1FC)
+                .await?;
+        }
+
+        if options.verify {
+            let mut rx = [0; 1 + BLOCK_MAX_LEN];
+            let res = self.read_single_block_with(block, mode, &mut rx).await?;
+            if res != data {
+                debug!("write single block: verify failed, read {}", Bytes(res));
+                return Err(Error::VerifyFailed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a custom command of the IC with manufacturer `mfg_code` to the tag given at creation.
+    /// Returns the response length, without the response flags.
+    ///
+    /// Custom commands are IC specific, such as the privacy, EAS and password commands of
+    /// [ICODE SLIX](icode). `params` follow the manufacturer code and UID in the request.
+    /// Writes and other slow commands may need a longer timeout than this waits, use the
+    /// reader directly for them.
+    ///
+    /// Panics if `cmd` isn't a custom command code (0xA0 to 0xDF), or if `params` is longer
+    /// than [`CUSTOM_PARAMS_MAX_LEN`].
+    pub async fn custom_command(
+        &mut self,
+        mfg_code: u8,
+        cmd: u8,
+        params: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, Error<T::Error>> {
+        assert!((CMD_CUSTOM_FIRST..0xE0).contains(&cmd), "not a custom command code");
+        assert!(params.len() <= CUSTOM_PARAMS_MAX_LEN);
+        let mode = match self.uid {
+            Some(uid) => AddressMode::Addressed(uid),
+            None => AddressMode::NonAddressed,
+        };
+        let mut tx = [0; 3 + UID_LEN + CUSTOM_PARAMS_MAX_LEN];
+        let mut n = write_header(&mut tx, cmd, Some(mfg_code), mode, false);
+        tx[n..][..params.len()].copy_from_slice(params);
+        n += params.len();
+
+        let mut rx = [0; RESPONSE_MAX_LEN];
+        let len = self.exchange(cmd, &tx[..n], &mut rx, TIMEOUT_1FC).await?;
+        let res = Self::parse_response(cmd, &rx[..len])?;
+        if out.len() < res.len() {
+            return Err(Error::BufferTooSmall);
+        }
+        out[..res.len()].copy_from_slice(res);
+        Ok(res.len())
+    }
+
+    /// Send a command to the tag given at creation, return the response without the response flags.
+    async fn command<'a>(
+        &mut self,
+        cmd: u8,
+        option: bool,
+        params: &[u8],
+        rx: &'a mut [u8],
+    ) -> Result<&'a [u8], Error<T::Error>> {
+        let mode = match self.uid {
+            Some(uid) => AddressMode::Addressed(uid),
+            None => AddressMode::NonAddressed,
+        };
+        self.command_with(cmd, mode, option, params, rx, TIMEOUT_1FC).await
+    }
+
+    /// Send a command, return the response without the response flags.
+    async fn command_with<'a>(
+        &mut self,
+        cmd: u8,
+        mode: AddressMode,
+        option: bool,
+        params: &[u8],
+        rx: &'a mut [u8],
+        timeout_1fc: u32,
+    ) -> Result<&'a [u8], Error<T::Error>> {
+        let len = self.request(cmd, mode, option, params, rx, timeout_1fc).await?;
+        Self::parse_response(cmd, &rx[..len])
+    }
+
+    /// Send a command, return the raw response length.
+    async fn request(
+        &mut self,
+        cmd: u8,
+        mode: AddressMode,
+        option: bool,
+        params: &[u8],
+        rx: &mut [u8],
+        timeout_1fc: u32,
+    ) -> Result<usize, Error<T::Error>> {
+        let mut tx = [0; 2 + UID_LEN + 2 + BLOCK_MAX_LEN];
+        let mut n = write_header(&mut tx, cmd, None, mode, option);
+        tx[n..][..params.len()].copy_from_slice(params);
+        n += params.len();
+        self.exchange(cmd, &tx[..n], rx, timeout_1fc).await
+    }
+
+    /// Send a request frame, return the raw response length.
+    async fn exchange(&mut self, cmd: u8, tx: &[u8], rx: &mut [u8], timeout_1fc: u32) -> Result<usize, Error<T::Error>> {
+        match self.reader.transceive(tx, rx, timeout_1fc).await {
+            Ok(len) => Ok(len),
+            Err(e) => {
+                debug!("Trx cmd {:02x} failed: {:?}", cmd, e);
+                Err(Error::Lower(e))
+            }
+        }
+    }
+
+    fn parse_response(cmd: u8, res: &[u8]) -> Result<&[u8], Error<T::Error>> {
+        match res.first() {
+            None => Err(Error::Protocol),
+            Some(&f) if f & FLAG_ERROR != 0 => {
+                let code = res.get(1).copied().unwrap_or(0);
+                debug!("cmd {:02x}: tag error {:02x}", cmd, code);
+                Err(Error::Tag(code))
+            }
+            Some(_) => Ok(&res[1..]),
+        }
+    }
+}
+
+/// Write the request flags, command code, `pre_uid` byte, and UID if addressed to `tx`.
+/// Returns the header length.
+///
+/// `pre_uid` is the parameter ISO15693-3 places before the UID: the manufacturer code of
+/// custom commands, or the info request of Get Extended System Information.
+fn write_header(tx: &mut [u8], cmd: u8, pre_uid: Option<u8>, mode: AddressMode, option: bool) -> usize {
+    let mut flags = FLAG_DATA_RATE_HIGH;
+    if option {
+        flags |= FLAG_OPTION;
+    }
+    tx[1] = cmd;
+    let mut n = 2;
+    if let Some(b) = pre_uid {
+        tx[n] = b;
+        n += 1;
+    }
+    match mode {
+        AddressMode::Addressed(uid) => {
+            flags |= FLAG_ADDRESS;
+            tx[n..][..UID_LEN].copy_from_slice(&uid);
+            n += UID_LEN;
+        }
+        AddressMode::NonAddressed => {}
+        AddressMode::Selected => flags |= FLAG_SELECT,
+    }
+    tx[0] = flags;
+    n
+}
+
+/// Write the address of `block` to `buf`, return the command to use and the address length.
+///
+/// Blocks above 255 need the extended command `ext_cmd`, with a 2-byte address.
+fn block_address(cmd: u8, ext_cmd: u8, block: u16, buf: &mut [u8]) -> (u8, usize) {
+    match u8::try_from(block) {
+        Ok(block) => {
+            buf[0] = block;
+            (cmd, 1)
+        }
+        Err(_) => {
+            buf[..2].copy_from_slice(&block.to_le_bytes());
+            (ext_cmd, 2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::test_util::mock;
+
+    const UID: [u8; 8] = hex!("01 02 03 04 05 06 07 e0");
+
+    #[test_log::test(tokio::test)]
+    async fn test_get_system_info() {
+        let mock = mock!(
+            ("22 2b 01 02 03 04 05 06 07 e0" => "00 0f 01 02 03 04 05 06 07 e0 00 00 1b 03 01"),
+        );
+        let mut tag = Tag::new(mock, Some(UID));
+        let info = tag.get_system_info().await.unwrap();
+        assert_eq!(
+            info,
+            SystemInfo {
+                uid: UID,
+                dsfid: Some(0),
+                afi: Some(0),
+                memory: Some(MemorySize {
+                    block_count: 28,
+                    block_size: 4,
+                }),
+                ic_reference: Some(1),
+            }
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_get_system_info_minimal() {
+        let mock = mock!(
+            ("02 2b" => "00 04 01 02 03 04 05 06 07 e0 07 07"),
+        );
+        let mut tag = Tag::new(mock, None);
+        let info = tag.get_system_info().await.unwrap();
+        assert_eq!(info.dsfid, None);
+        assert_eq!(info.afi, None);
+        assert_eq!(
+            info.memory,
+            Some(MemorySize {
+                block_count: 8,
+                block_size: 8,
+            })
+        );
+        assert_eq!(info.ic_reference, None);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_get_system_info_truncated() {
+        let mock = mock!(
+            ("02 2b" => "00 04 01 02 03 04 05 06 07 e0 07"),
+        );
+        let mut tag = Tag::new(mock, None);
+        assert_eq!(tag.get_system_info().await, Err(Error::Protocol));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_discover_extended() {
+        let mock = mock!(
+            ("02 2b" => "00 08 01 02 03 04 05 06 07 e0 02"),
+            ("02 3b 0f" => "00 0c 01 02 03 04 05 06 07 e0 ff 07 03 02"),
+        );
+        let mut tag = Tag::new(mock, None);
+        let info = tag.discover_system_info().await.unwrap();
+        assert_eq!(
+            info.memory,
+            Some(MemorySize {
+                block_count: 2048,
+                block_size: 4,
+            })
+        );
+        assert_eq!(info.ic_reference, Some(2));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_extended_system_info_addressed() {
+        let mock = mock!(("22 3b 0f 01 02 03 04 05 06 07 e0" => "00 0c 01 02 03 04 05 06 07 e0 ff 07 03 02"));
+        let mut tag = Tag::new(mock, Some(UID));
+        let info = tag.get_extended_system_info().await.unwrap();
+        assert_eq!(info.memory.unwrap().block_count, 2048);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_extended_blocks() {
+        let mock = mock!(
+            ("02 20 ff" => "00 11 12 13 14"),
+            ("02 30 00 01" => "00 21 22 23 24"),
+            ("02 31 34 12 11 22 33 44" => "00"),
+            ("02 33 ff 00 01 00" => "00 11 12 13 14 21 22 23 24"),
+        );
+        let mut tag = Tag::new(mock, None);
+        let mut out = [0; 8];
+        assert_eq!(tag.read_single_block(255, &mut out).await, Ok(4));
+        assert_eq!(out[..4], hex!("11 12 13 14"));
+        assert_eq!(tag.read_single_block(256, &mut out).await, Ok(4));
+        assert_eq!(out[..4], hex!("21 22 23 24"));
+        let data = hex!("11 22 33 44");
+        assert_eq!(tag.write_single_block(0x1234, &data, AddressMode::NonAddressed).await, Ok(()));
+        // Crossing block 255 needs the extended command too.
+        assert_eq!(tag.read_multiple_blocks(255, 2, &mut out).await, Ok(8));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_multiple_blocks() {
+        let mock = mock!(
+            ("22 23 01 02 03 04 05 06 07 e0 02 01" => "00 11 12 13 14 21 22 23 24"),
+        );
+        let mut tag = Tag::new(mock, Some(UID));
+        let mut out = [0; 8];
+        assert_eq!(tag.read_multiple_blocks(2, 2, &mut out).await, Ok(8));
+        assert_eq!(out, hex!("11 12 13 14 21 22 23 24"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_multiple_blocks_with_status() {
+        let mock = mock!(
+            ("62 23 01 02 03 04 05 06 07 e0 00 02" => "00 00 11 12 13 14 01 21 22 23 24 00 31 32 33 34"),
+        );
+        let mut tag = Tag::new(mock, Some(UID));
+        let mut out = [0; 12];
+        let mut status = [0; 3];
+        assert_eq!(
+            tag.read_multiple_blocks_with_status(0, 3, &mut out, &mut status).await,
+            Ok(12)
+        );
+        assert_eq!(out, hex!("11 12 13 14 21 22 23 24 31 32 33 34"));
+        assert_eq!(status, [0, 1, 0]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_multiple_blocks_tag_error() {
+        let mock = mock!(
+            ("02 23 40 01" => "01 10"),
+        );
+        let mut tag = Tag::new(mock, None);
+        let mut out = [0; 8];
+        assert_eq!(tag.read_multiple_blocks(0x40, 2, &mut out).await, Err(Error::Tag(0x10)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_multiple_blocks_buffer_too_small() {
+        let mock = mock!(
+            ("02 23 00 01" => "00 11 12 13 14 21 22 23 24"),
+        );
+        let mut tag = Tag::new(mock, None);
+        let mut out = [0; 4];
+        assert_eq!(tag.read_multiple_blocks(0, 2, &mut out).await, Err(Error::BufferTooSmall));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_write_single_block() {
+        let mock = mock!(
+            ("22 21 01 02 03 04 05 06 07 e0 05 11 22 33 44" => "00"),
+            ("02 21 06 11 22 33 44" => "01 12"),
+        );
+        let mut tag = Tag::new(mock, None);
+        let data = hex!("11 22 33 44");
+        assert_eq!(tag.write_single_block(5, &data, AddressMode::Addressed(UID)).await, Ok(()));
+        assert_eq!(
+            tag.write_single_block(6, &data, AddressMode::NonAddressed).await,
+            Err(Error::Tag(0x12))
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_custom_command() {
+        let mock = mock!(
+            ("22 b2 04 01 02 03 04 05 06 07 e0" => "00 12 34"),
+            ("22 b3 04 01 02 03 04 05 06 07 e0 04 11 22 33 44" => "01 0f"),
+        );
+        let mut tag = Tag::new(mock, Some(UID));
+        let mut out = [0; 2];
+        let n = tag
+            .custom_command(icode::MFG_CODE, icode::CMD_GET_RANDOM_NUMBER, &[], &mut out)
+            .await
+            .unwrap();
+        assert_eq!(out[..n], hex!("12 34"));
+        let res = tag
+            .custom_command(icode::MFG_CODE, icode::CMD_SET_PASSWORD, &hex!("04 11 22 33 44"), &mut out)
+            .await;
+        assert_eq!(res, Err(Error::Tag(0x0f)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_write_single_block_option_flag() {
+        let mock = mock!(
+            ("42 21 05 11 22 33 44" => timeout),
+            ("" => "00"),
+            // Errors come right away, without waiting for EOF.
+            ("42 21 06 11 22 33 44" => "01 12"),
+        );
+        let mut tag = Tag::new(mock, None);
+        let data = hex!("11 22 33 44");
+        let options = WriteOptions {
+            option_flag: true,
+            verify: false,
+        };
+        assert_eq!(
+            tag.write_single_block_with_options(5, &data, AddressMode::NonAddressed, options)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            tag.write_single_block_with_options(6, &data, AddressMode::NonAddressed, options)
+                .await,
+            Err(Error::Tag(0x12))
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_write_single_block_selected_verify() {
+        let mock = mock!(
+            ("22 25 01 02 03 04 05 06 07 e0" => "00"),
+            ("12 21 05 11 22 33 44" => "00"),
+            ("12 20 05" => "00 11 22 33 44"),
+            ("12 21 06 11 22 33 44" => "00"),
+            ("12 20 06" => "00 11 22 33 00"),
+        );
+        let mut tag = Tag::new(mock, None);
+        tag.select(&UID).await.unwrap();
+        let data = hex!("11 22 33 44");
+        let options = WriteOptions {
+            option_flag: false,
+            verify: true,
+        };
+        assert_eq!(
+            tag.write_single_block_with_options(5, &data, AddressMode::Selected, options)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            tag.write_single_block_with_options(6, &data, AddressMode::Selected, options)
+                .await,
+            Err(Error::VerifyFailed)
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! ISO7816-4 APDUs, as exchanged with ISO-DEP cards such as Type 4 Tags and smartcards.
+//!
+//! Only short APDUs are supported: up to 255 bytes of command data, and up to 256 bytes expected
+//! in the response.
+
+use rnfc_traits::iso_dep::Reader;
+
+use crate::fmt::Bytes;
+
+/// Max command data length in a short APDU.
+pub const LC_MAX: usize = 255;
+/// Max expected response length in a short APDU, encoded as 0.
+pub const LE_MAX: u16 = 256;
+/// Max encoded length of a short APDU: header, Lc, data and Le.
+pub const COMMAND_MAX_LEN: usize = 4 + 1 + LC_MAX + 1;
+
+pub const SW_SUCCESS: u16 = 0x9000;
+pub const SW_WRONG_LENGTH: u16 = 0x6700;
+/// SW1 for "wrong Le", SW2 has the right one.
+const SW1_WRONG_LE: u8 = 0x6C;
+/// SW1 for "more data available", SW2 has its length. Normal processing.
+const SW1_MORE_DATA: u8 = 0x61;
+
+const INS_SELECT: u8 = 0xA4;
+const INS_READ_BINARY: u8 = 0xB0;
+const P1_SELECT_BY_AID: u8 = 0x04;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    Lower(E),
+    /// The response is shorter than the status word.
+    Protocol,
+}
+
+/// APDU case, from which fields are present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Case {
+    /// No command data, no response data.
+    Case1,
+    /// No command data, response data expected.
+    Case2,
+    /// Command data, no response data.
+    Case3,
+    /// Command data, response data expected.
+    Case4,
+}
+
+/// A command APDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Command<'a> {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    /// Command data, up to [`LC_MAX`] bytes. Empty if none.
+    pub data: &'a [u8],
+    /// Expected response length, 1 to [`LE_MAX`]. `None` if no response data is expected.
+    pub le: Option<u16>,
+}
+
+impl<'a> Command<'a> {
+    /// A case 1 command, add data and Le with the other builders.
+    pub const fn new(cla: u8, ins: u8, p1: u8, p2: u8) -> Self {
+        Self {
+            cla,
+            ins,
+            p1,
+            p2,
+            data: &[],
+            le: None,
+        }
+    }
+
+    pub const fn with_data(self, data: &'a [u8]) -> Self {
+        Self { data, ..self }
+    }
+
+    pub const fn with_le(self, le: u16) -> Self {
+        Self { le: Some(le), ..self }
+    }
+
+    pub fn case(&self) -> Case {
+        match (self.data.is_empty(), self.le.is_some()) {
+            (true, false) => Case::Case1,
+            (true, true) => Case::Case2,
+            (false, false) => Case::Case3,
+            (false, true) => Case::Case4,
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        let lc = match self.data.len() {
+            0 => 0,
+            n => 1 + n,
+        };
+        4 + lc + self.le.is_some() as usize
+    }
+
+    /// Encode the command into `buf`, return the encoded length.
+    ///
+    /// Panics if `buf` is shorter than [`encoded_len`](Self::encoded_len), or if the data or Le
+    /// don't fit in a short APDU.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        assert!(self.data.len() <= LC_MAX, "APDU data too long for a short APDU");
+        buf[..4].copy_from_slice(&[self.cla, self.ins, self.p1, self.p2]);
+        let mut n = 4;
+        if !self.data.is_empty() {
+            buf[n] = self.data.len() as u8;
+            buf[n + 1..][..self.data.len()].copy_from_slice(self.data);
+            n += 1 + self.data.len();
+        }
+        if let Some(le) = self.le {
+            assert!((1..=LE_MAX).contains(&le), "APDU Le out of range for a short APDU");
+            // 256 is encoded as 0.
+            buf[n] = le as u8;
+            n += 1;
+        }
+        n
+    }
+}
+
+/// SELECT by DF name, for selecting an application by its AID. Asks for the FCI in the response.
+pub fn select_by_aid(aid: &[u8]) -> Command<'_> {
+    Command::new(0x00, INS_SELECT, P1_SELECT_BY_AID, 0x00)
+        .with_data(aid)
+        .with_le(LE_MAX)
+}
+
+/// READ BINARY `len` bytes at `offset` in the currently selected file. `len` 0 means 256.
+///
+/// Panics if `offset` is above 0x7FFF, which doesn't fit in P1-P2.
+pub fn read_binary(offset: u16, len: u8) -> Command<'static> {
+    assert!(offset <= 0x7FFF, "READ BINARY offset too large");
+    let le = match len {
+        0 => LE_MAX,
+        n => n as u16,
+    };
+    let [p1, p2] = offset.to_be_bytes();
+    Command::new(0x00, INS_READ_BINARY, p1, p2).with_le(le)
+}
+
+/// A response APDU: response data and status word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Response<'a> {
+    pub data: &'a [u8],
+    pub sw: u16,
+}
+
+impl<'a> Response<'a> {
+    /// Split the status word off a response.
+    pub fn parse(res: &'a [u8]) -> Option<Self> {
+        if res.len() < 2 {
+            return None;
+        }
+        let (data, sw) = res.split_at(res.len() - 2);
+        Some(Self {
+            data,
+            sw: u16::from_be_bytes([sw[0], sw[1]]),
+        })
+    }
+
+    pub fn sw1(&self) -> u8 {
+        (self.sw >> 8) as u8
+    }
+
+    pub fn sw2(&self) -> u8 {
+        self.sw as u8
+    }
+
+    /// Normal processing: 9000, or 61xx if more data is available with GET RESPONSE.
+    pub fn is_success(&self) -> bool {
+        self.sw == SW_SUCCESS || self.sw1() == SW1_MORE_DATA
+    }
+
+    /// The card rejected the Le, and reported the right one (6Cxx). Resend with this Le.
+    pub fn needs_le(&self) -> Option<u16> {
+        match self.sw1() {
+            SW1_WRONG_LE => Some(match self.sw2() {
+                0 => LE_MAX,
+                n => n as u16,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The card rejected Lc or Le, without saying which length it expected (6700).
+    pub fn wrong_length(&self) -> bool {
+        self.sw == SW_WRONG_LENGTH
+    }
+}
+
+/// Send `cmd` and receive the response into `rx`.
+///
+/// If the card asks for another Le (6Cxx), the command is resent once with it, as ISO7816-4
+/// requires. Other status words are returned as is, check them with [`Response::is_success`].
+///
+/// The command is encoded into a [`COMMAND_MAX_LEN`] byte local, which the returned future
+/// holds. It works with any ISO-DEP reader, so there's no session to keep the buffer in.
+pub async fn transmit<'a, T: Reader>(
+    reader: &mut T,
+    cmd: &Command<'_>,
+    rx: &'a mut [u8],
+) -> Result<Response<'a>, Error<T::Error>>
+where
+    T::Error: crate::fmt::Format,
+{
+    let mut tx = [0; COMMAND_MAX_LEN];
+    let mut n = cmd.encode(&mut tx);
+    debug!("apdu TX: {}", Bytes(&tx[..n]));
+    let mut len = reader.transceive(&tx[..n], rx).await.map_err(Error::Lower)?;
+
+    if let Some(le) = Response::parse(&rx[..len]).and_then(|res| res.needs_le()) {
+        if cmd.le.is_some() {
+            debug!("apdu: resending with Le {}", le);
+            n = cmd.with_le(le).encode(&mut tx);
+            len = reader.transceive(&tx[..n], rx).await.map_err(Error::Lower)?;
+        }
+    }
+
+    debug!("apdu RX: {}", Bytes(&rx[..len]));
+    Response::parse(&rx[..len]).ok_or(Error::Protocol)
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::test_util::mock;
+
+    fn encode(cmd: &Command<'_>) -> Vec<u8> {
+        let mut buf = [0; COMMAND_MAX_LEN];
+        let n = cmd.encode(&mut buf);
+        assert_eq!(n, cmd.encoded_len());
+        buf[..n].to_vec()
+    }
+
+    #[test]
+    fn test_cases() {
+        let cmd = Command::new(0x00, 0x70, 0x00, 0x00);
+        assert_eq!(cmd.case(), Case::Case1);
+        assert_eq!(encode(&cmd), hex!("00 70 00 00"));
+
+        let cmd = Command::new(0x00, 0xC0, 0x00, 0x00).with_le(0x10);
+        assert_eq!(cmd.case(), Case::Case2);
+        assert_eq!(encode(&cmd), hex!("00 c0 00 00 10"));
+
+        let cmd = Command::new(0x00, 0xD6, 0x00, 0x02).with_data(&hex!("11 22 33"));
+        assert_eq!(cmd.case(), Case::Case3);
+        assert_eq!(encode(&cmd), hex!("00 d6 00 02 03 11 22 33"));
+
+        let cmd = Command::new(0x00, 0x88, 0x00, 0x00).with_data(&hex!("11 22")).with_le(256);
+        assert_eq!(cmd.case(), Case::Case4);
+        assert_eq!(encode(&cmd), hex!("00 88 00 00 02 11 22 00"));
+    }
+
+    #[test]
+    fn test_builders() {
+        let cmd = select_by_aid(&hex!("d2 76 00 00 85 01 01"));
+        assert_eq!(encode(&cmd), hex!("00 a4 04 00 07 d2 76 00 00 85 01 01 00"));
+
+        assert_eq!(encode(&read_binary(0x0102, 0x0f)), hex!("00 b0 01 02 0f"));
+        assert_eq!(encode(&read_binary(0, 0)), hex!("00 b0 00 00 00"));
+    }
+
+    #[test]
+    fn test_response() {
+        let res = Response::parse(&hex!("11 22 90 00")).unwrap();
+        assert_eq!(res.data, hex!("11 22"));
+        assert!(res.is_success());
+        assert_eq!(res.needs_le(), None);
+
+        assert!(Response::parse(&hex!("61 10")).unwrap().is_success());
+
+        let res = Response::parse(&hex!("6c 08")).unwrap();
+        assert!(!res.is_success());
+        assert_eq!(res.needs_le(), Some(8));
+        assert_eq!(Response::parse(&hex!("6c 00")).unwrap().needs_le(), Some(256));
+
+        assert!(Response::parse(&hex!("67 00")).unwrap().wrong_length());
+        assert_eq!(Response::parse(&hex!("90")), None);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_transmit_wrong_le() {
+        let mut reader = mock!(
+            ("00 b0 00 00 10" => "6c 04"),
+            ("00 b0 00 00 04" => "11 22 33 44 90 00"),
+        );
+        let mut rx = [0; 32];
+        let res = transmit(&mut reader, &read_binary(0, 0x10), &mut rx).await.unwrap();
+        assert!(res.is_success());
+        assert_eq!(res.data, hex!("11 22 33 44"));
+        assert_eq!(reader.pos, 2);
+    }
+}
@@ -2,11 +2,19 @@ use rnfc_traits::iso14443a::{Error as _, Reader as Iso14443aReader};
 use rnfc_traits::iso14443a_ll::ErrorKind;
 use rnfc_traits::iso_dep::Reader as IsoDepReader;
 
+use crate::fmt::Bytes;
+
 pub const ATS_MAX_LEN: usize = 32; // TODO??
 
 const FSC_MAX: usize = 256;
 const FSC_MAX_WITHOUT_CRC: usize = FSC_MAX - 2;
 
+/// An ISO-DEP session with an ISO14443A card.
+///
+/// The session owns the buffer TX blocks are framed in (254 bytes: the max frame size less the
+/// CRC), so it's part of the session's memory instead of each transceive future, which async
+/// tasks capture whole. The session takes about 280 bytes plus `T`. The RATS response and the
+/// R- and S-blocks received once `rx` is full are small enough to stay local.
 pub struct IsoDepA<T: Iso14443aReader> {
     card: T,
 
@@ -23,6 +31,19 @@ pub struct IsoDepA<T: Iso14443aReader> {
 
     /// Block count spin bit: 0 or 1
     block_num: u8,
+
+    /// See `set_max_retransmissions`.
+    max_retransmissions: u8,
+    /// See `set_fwt_multiplier`.
+    fwt_multiplier: u8,
+    /// See `set_max_fwt`.
+    max_fwt_1fc: Option<u32>,
+    /// See `last_pcb`.
+    last_pcb: Option<u8>,
+    /// See `wtx_count`.
+    wtx_count: u32,
+    /// TX blocks are framed here, reused across transceives.
+    tx_buf: [u8; FSC_MAX_WITHOUT_CRC],
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -33,6 +54,16 @@ pub enum Error<E> {
     Communication,
     TxFrameTooBig,
     RxFrameTooBig,
+    /// The card didn't answer within the FWT ceiling set with [`IsoDepA::set_max_fwt`].
+    Timeout,
+    /// The card stopped answering, and the reader reports it's no longer in the field.
+    ///
+    /// Only returned with readers that can tell, see [`Iso14443aReader::card_in_field`].
+    /// Others report [`Error::Communication`] once the retransmissions are exhausted.
+    TagLost,
+    /// The card kept answering with block numbers from an earlier exchange, even after
+    /// resynchronizing per the ISO14443-4 rules. The card should be deselected or reactivated.
+    BlockNumberDesync,
 }
 
 // Divide by 2 so it fits in u8, saving some space
@@ -50,6 +81,16 @@ const FS_DIV_2_TABLE: [u8; 9] = [
 
 const RATS_TIMEOUT_1FC: u32 = 65536;
 
+/// PCB of S(PARAMETERS) blocks, without CID.
+const PCB_S_PARAMETERS: u8 = 0xF0;
+
+/// Largest block other than an I-block: S(WTX), PCB and WTXM.
+const RX_SCRATCH_LEN: usize = 2;
+
+/// Retransmissions after a transmission error. ISO14443-4 suggests 2, we default to more
+/// for robustness with cards that take a few attempts.
+const DEFAULT_MAX_RETRANSMISSIONS: u8 = 9;
+
 impl<T: Iso14443aReader> IsoDepA<T>
 where
     T::Error: crate::fmt::Format,
@@ -103,6 +144,12 @@ where
             sfgt_1fc,
             fwt_1fc,
             block_num: 0,
+            max_retransmissions: DEFAULT_MAX_RETRANSMISSIONS,
+            fwt_multiplier: 1,
+            max_fwt_1fc: None,
+            last_pcb: None,
+            wtx_count: 0,
+            tx_buf: [0; FSC_MAX_WITHOUT_CRC],
         })
     }
 
@@ -110,6 +157,60 @@ where
         &self.card
     }
 
+    /// Set how many times a block is retransmitted after a timeout or a corrupted response,
+    /// before failing with [`Error::Communication`].
+    ///
+    /// ISO14443-4 suggests 2. Flaky cards (some secure elements) may need more, while
+    /// latency-sensitive applications may prefer fewer to fail fast. Default: 9.
+    pub fn set_max_retransmissions(&mut self, max_retransmissions: u8) {
+        self.max_retransmissions = max_retransmissions;
+    }
+
+    /// Multiply the frame waiting time (FWT) announced by the card.
+    ///
+    /// For cards that take longer to respond than their ATS says, which otherwise
+    /// time out and need retransmissions. Applies on top of WTX requests. Default: 1.
+    pub fn set_fwt_multiplier(&mut self, fwt_multiplier: u8) {
+        self.fwt_multiplier = fwt_multiplier.max(1);
+    }
+
+    /// Limit the frame waiting time (FWT) to `max_fwt_1fc`, in units of 1/Fc, or remove the limit with `None`.
+    ///
+    /// The FWT announced by the card can be up to about 5 s (FWI 14), which a hung card would
+    /// block the reader for. With a limit, waits are bounded even for such cards: if the
+    /// card doesn't answer within a limited FWT, this fails with [`Error::Timeout`] right away,
+    /// without retransmissions. The limit applies after [`set_fwt_multiplier`](Self::set_fwt_multiplier)
+    /// and to WTX extensions too. Default: none.
+    pub fn set_max_fwt(&mut self, max_fwt_1fc: Option<u32>) {
+        self.max_fwt_1fc = max_fwt_1fc;
+    }
+
+    /// Current block number, 0 or 1: the one of our next I-block.
+    pub fn block_number(&self) -> u8 {
+        self.block_num
+    }
+
+    /// PCB of the last block sent, or `None` if nothing was sent since RATS.
+    pub fn last_pcb(&self) -> Option<u8> {
+        self.last_pcb
+    }
+
+    /// Number of waiting time extensions (S(WTX)) the card requested since activation.
+    pub fn wtx_count(&self) -> u32 {
+        self.wtx_count
+    }
+
+    /// Frame waiting time, in units of 1/Fc, after [`set_fwt_multiplier`](Self::set_fwt_multiplier)
+    /// and [`set_max_fwt`](Self::set_max_fwt). WTX extensions only apply to the block they answer.
+    pub fn fwt_1fc(&self) -> u32 {
+        self.clamp_fwt(self.fwt_1fc.saturating_mul(self.fwt_multiplier as u32))
+    }
+
+    /// Apply the `set_max_fwt` limit.
+    fn clamp_fwt(&self, fwt_1fc: u32) -> u32 {
+        fwt_1fc.min(self.max_fwt_1fc.unwrap_or(u32::MAX))
+    }
+
     pub fn inner_mut(&mut self) -> &mut T {
         &mut self.card
     }
@@ -117,10 +218,11 @@ where
     pub async fn deselect(&mut self) -> Result<(), Error<T::Error>> {
         let tx_buf = [0xC2];
         let mut rx_buf = [0; 1];
+        self.last_pcb = Some(tx_buf[0]);
 
         let rx_len = self
             .card
-            .transceive(&tx_buf, &mut rx_buf, self.fwt_1fc)
+            .transceive(&tx_buf, &mut rx_buf, self.fwt_1fc())
             .await
             .map_err(Error::Iso14443a)?;
         if rx_len != 1 || rx_buf != [0xC2] {
@@ -129,6 +231,64 @@ where
 
         Ok(())
     }
+
+    /// Send an S(PARAMETERS) block, and receive the card's S(PARAMETERS) response.
+    ///
+    /// S(PARAMETERS), from ISO14443-4:2016, negotiates enhanced features such as bitrates above
+    /// 848 kbps and larger frames. `inf` is the block's INF field, the TLV-encoded parameters
+    /// starting with the `A0` tag, encoded by the caller. The response's INF is returned in
+    /// `rx`, which needs one more byte, for the PCB.
+    ///
+    /// Returns `Ok(None)` if the card doesn't answer, as cards that don't support S(PARAMETERS)
+    /// do. The session can go on as usual then.
+    pub async fn send_parameters(&mut self, inf: &[u8], rx: &mut [u8]) -> Result<Option<usize>, Error<T::Error>> {
+        if 1 + inf.len() > self.fsc - 2 {
+            return Err(Error::TxFrameTooBig);
+        }
+        self.tx_buf[0] = PCB_S_PARAMETERS;
+        self.tx_buf[1..][..inf.len()].copy_from_slice(inf);
+        self.last_pcb = Some(PCB_S_PARAMETERS);
+
+        let fwt_1fc = self.fwt_1fc();
+        let rx_len = match self.card.transceive(&self.tx_buf[..1 + inf.len()], rx, fwt_1fc).await {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Timeout => {
+                debug!("isodep: no S(PARAMETERS) response, unsupported");
+                return Ok(None);
+            }
+            Err(e) => return Err(Error::Iso14443a(e)),
+        };
+        if rx_len == 0 || rx[0] != PCB_S_PARAMETERS {
+            warn!("isodep: unexpected S(PARAMETERS) response {}", Bytes(&rx[..rx_len]));
+            return Err(Error::Protocol);
+        }
+        rx.copy_within(1..rx_len, 0);
+        Ok(Some(rx_len - 1))
+    }
+
+    /// Check whether the card is still in the field, by sending an R(NAK).
+    ///
+    /// The card answers with an R(ACK) without changing its state, so this doesn't
+    /// interfere with the ongoing session and can be done between any two exchanges.
+    /// Returns `Ok(false)` if the card doesn't answer.
+    pub async fn presence_check(&mut self) -> Result<bool, Error<T::Error>> {
+        let tx_buf = [0xb2 | self.block_num];
+        let mut rx_buf = [0; 1];
+        self.last_pcb = Some(tx_buf[0]);
+
+        match self.card.transceive(&tx_buf, &mut rx_buf, self.fwt_1fc()).await {
+            // The block number is the card's current one, don't spin ours.
+            Ok(1) if rx_buf[0] & 0xfe == 0xa2 => Ok(true),
+            Ok(_) => {
+                warn!("isodep: unexpected presence check response {:02x}", rx_buf[0]);
+                Err(Error::Protocol)
+            }
+            Err(e) => match e.kind() {
+                ErrorKind::Timeout | ErrorKind::Corruption => Ok(false),
+                _ => Err(Error::Iso14443a(e)),
+            },
+        }
+    }
 }
 
 impl<T: Iso14443aReader> IsoDepReader for IsoDepA<T>
@@ -137,9 +297,17 @@ where
 {
     type Error = Error<T::Error>;
 
-    async fn transceive(&mut self, mut tx: &[u8], mut rx: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut tx_buf = [0; FSC_MAX_WITHOUT_CRC];
-        let mut rx_buf = [0; FSC_MAX_WITHOUT_CRC];
+    /// Exchange an APDU, chaining in both directions as needed.
+    ///
+    /// The response is bounded by `rx` only, not by the frame size: chained responses of several
+    /// KB work with no other buffer than `rx`. Each block is received directly into `rx`, with
+    /// its PCB over the last byte of the previous block, which is saved and restored. A block
+    /// that doesn't fit in what's left of `rx` fails in the lower layer, or with
+    /// [`Error::RxFrameTooBig`] once `rx` is full. TX blocks are slices
+    /// of `tx`, copied behind their PCB into the session's frame buffer.
+    async fn transceive(&mut self, mut tx: &[u8], rx: &mut [u8]) -> Result<usize, Self::Error> {
+        // For R- and S-blocks once `rx` is full.
+        let mut scratch = [0; RX_SCRATCH_LEN];
 
         enum Send {
             Data,
@@ -150,52 +318,87 @@ where
         let mut send = Send::Data;
 
         let max_n = self.fsc - 3;
-        let mut rx_total = 0;
+        let mut rx_total: usize = 0;
         let mut rx_chaining = false;
         let mut retries = 0;
+        // Recovery attempts after the card answered with a stale block.
+        let mut resynced = false;
+        let mut stale_acks = 0;
+        let mut invalid_blocks = 0;
 
         loop {
-            let mut fwt = self.fwt_1fc;
+            let mut fwt = self.fwt_1fc.saturating_mul(self.fwt_multiplier as u32);
             let tx_len = match send {
                 Send::Data => {
                     let n = tx.len().min(max_n);
                     let more_blocks = n != tx.len();
-                    tx_buf[0] = 0x02 | self.block_num | (more_blocks as u8) << 4;
-                    tx_buf[1..][..n].copy_from_slice(&tx[..n]);
+                    self.tx_buf[0] = 0x02 | self.block_num | (more_blocks as u8) << 4;
+                    self.tx_buf[1..][..n].copy_from_slice(&tx[..n]);
                     1 + n
                 }
                 Send::Wtx(mul) => {
-                    fwt *= mul as u32;
-                    tx_buf[0] = 0xF2;
-                    tx_buf[1] = mul;
+                    fwt = fwt.saturating_mul(mul as u32);
+                    self.tx_buf[0] = 0xF2;
+                    self.tx_buf[1] = mul;
                     2
                 }
                 Send::Ack => {
-                    tx_buf[0] = 0xa2 | self.block_num;
+                    self.tx_buf[0] = 0xa2 | self.block_num;
                     1
                 }
                 Send::Nak => {
-                    tx_buf[0] = 0xb2 | self.block_num;
+                    self.tx_buf[0] = 0xb2 | self.block_num;
                     1
                 }
             };
 
-            let res = self.card.transceive(&tx_buf[..tx_len], &mut rx_buf, fwt).await;
+            self.last_pcb = Some(self.tx_buf[0]);
+            let clamped = self.clamp_fwt(fwt) < fwt;
+            // The block goes at the end of the data received so far, PCB included.
+            let start = rx_total.saturating_sub(1);
+            let saved = rx.get(start).copied().unwrap_or(0);
+            let in_place = rx.len() - start >= RX_SCRATCH_LEN;
+            let rx_buf = match in_place {
+                true => &mut rx[start..],
+                false => &mut scratch[..],
+            };
+            let timeout_1fc = self.clamp_fwt(fwt);
+            let res = self.card.transceive(&self.tx_buf[..tx_len], rx_buf, timeout_1fc).await;
+            let (rx_pcb, rx_inf0) = (rx_buf[0], rx_buf.get(1).copied().unwrap_or(0));
+            if in_place && rx_total != 0 {
+                rx[start] = saved;
+            }
 
             send = match res {
                 Err(e) => {
                     warn!("isodep: got error {:?}", e);
                     match e.kind() {
                         ErrorKind::Timeout | ErrorKind::Corruption => {
-                            retries += 1;
-                            if retries >= 10 {
+                            // A removed card doesn't answer retransmissions either, don't wait for them.
+                            if e.kind() == ErrorKind::Timeout && self.card.card_in_field().await == Some(false) {
+                                debug!("isodep: card left the field");
+                                return Err(Error::TagLost);
+                            }
+                            // Retransmitting would wait for the limit again.
+                            if e.kind() == ErrorKind::Timeout && clamped {
+                                debug!("isodep: no response within the FWT limit");
+                                return Err(Error::Timeout);
+                            }
+                            if retries >= self.max_retransmissions {
                                 return Err(Error::Communication);
                             }
+                            retries += 1;
+                            self.card.reset_rx().await.map_err(Error::Iso14443a)?;
                             match rx_chaining {
                                 true => Send::Ack,
                                 false => Send::Nak,
                             }
                         }
+                        // Only R- and S-blocks fit in the scratch buffer, an I-block overflows it.
+                        ErrorKind::Other if !in_place => {
+                            warn!("isodep: block doesn't fit in rx");
+                            return Err(Error::RxFrameTooBig);
+                        }
                         _ => return Err(Error::Iso14443a(e)),
                     }
                 }
@@ -207,17 +410,34 @@ where
 
                     retries = 0;
 
-                    let rx_pcb = rx_buf[0]; // protocol control byte (aka header)
+                    // rx_pcb is the protocol control byte (aka header)
                     match rx_pcb {
+                        // I-block with the card's previous block number. The card toggles it on
+                        // each I-block it receives, so it repeated its last block instead of answering ours.
+                        0x02 | 0x03 | 0x12 | 0x13 if rx_pcb & 1 != self.block_num => {
+                            if resynced {
+                                warn!("isodep: block number desync");
+                                return Err(Error::BlockNumberDesync);
+                            }
+                            debug!("isodep: card repeated its last block, resyncing");
+                            resynced = true;
+                            // The card answers an R(NAK) with a block number different from its own
+                            // with R(ACK), then we retransmit our block.
+                            Send::Nak
+                        }
                         // I-block
                         0x02 | 0x03 | 0x12 | 0x13 => {
                             let rx_inf_len = rx_len - 1;
-                            if rx_inf_len > rx.len() {
+                            if rx_inf_len > rx.len() - rx_total {
                                 return Err(Error::RxFrameTooBig);
                             }
 
-                            rx[..rx_inf_len].copy_from_slice(&rx_buf[1..][..rx_inf_len]);
-                            rx = &mut rx[rx_inf_len..];
+                            if !in_place {
+                                rx[rx_total..][..rx_inf_len].copy_from_slice(&scratch[1..rx_len]);
+                            } else if rx_total == 0 {
+                                // Only the first block isn't right after the data received so far.
+                                rx.copy_within(1..rx_len, 0);
+                            }
                             rx_total += rx_inf_len;
 
                             // spin the spinny bit
@@ -242,6 +462,13 @@ where
 
                                 // spin the spinny bit
                                 self.block_num ^= 1;
+                            } else {
+                                // Retransmit our last block, unless the card never acknowledges it.
+                                if stale_acks >= self.max_retransmissions {
+                                    warn!("isodep: block number desync");
+                                    return Err(Error::BlockNumberDesync);
+                                }
+                                stale_acks += 1;
                             }
                             Send::Data
                         }
@@ -251,7 +478,21 @@ where
                                 warn!("isodep: invalid S(WTX) len {}", rx_len);
                                 return Err(Error::Protocol);
                             }
-                            Send::Wtx(rx_buf[1] & 0x3F)
+                            self.wtx_count = self.wtx_count.saturating_add(1);
+                            Send::Wtx(rx_inf0 & 0x3F)
+                        }
+                        // Only the reader sends S(PARAMETERS) requests, ignore one from the card
+                        // like any invalid block: ask for our block's response again.
+                        PCB_S_PARAMETERS => {
+                            warn!("isodep: ignoring S(PARAMETERS) from the card");
+                            if invalid_blocks >= self.max_retransmissions {
+                                return Err(Error::Communication);
+                            }
+                            invalid_blocks += 1;
+                            match rx_chaining {
+                                true => Send::Ack,
+                                false => Send::Nak,
+                            }
                         }
                         _ => {
                             warn!("unknown rx pcb {:02x}", rx_pcb);
@@ -278,6 +519,9 @@ mod test {
     struct MockReader {
         expected: Vec<(&'static [u8], Result<&'static [u8], ErrorKind>)>,
         pos: usize,
+        in_field: Option<bool>,
+        /// Number of `reset_rx` calls.
+        rx_resets: usize,
     }
 
     macro_rules! mock {
@@ -293,6 +537,8 @@ mod test {
                     $((&hex_literal::hex!($tx), mock!(@res $rx)),)*
                 ],
                 pos: 0,
+                in_field: None,
+                rx_resets: 0,
             }
         };
     }
@@ -315,6 +561,8 @@ mod test {
 
             self.pos += 1;
             match expected_rx {
+                // Readers fail responses that don't fit in `rx`.
+                Ok(expected_rx) if expected_rx.len() > rx.len() => Err(ErrorKind::Other),
                 Ok(expected_rx) => {
                     rx[..expected_rx.len()].copy_from_slice(expected_rx);
                     Ok(expected_rx.len())
@@ -334,6 +582,15 @@ mod test {
         fn uid(&self) -> &[u8] {
             todo!()
         }
+
+        async fn card_in_field(&mut self) -> Option<bool> {
+            self.in_field
+        }
+
+        async fn reset_rx(&mut self) -> Result<(), Self::Error> {
+            self.rx_resets += 1;
+            Ok(())
+        }
     }
 
     macro_rules! trx {
@@ -419,7 +676,40 @@ mod test {
         );
         let x = &mut IsoDepA::new(mock).await.unwrap();
         trx!(x, "12 34" => "56 78");
+        assert_eq!(x.wtx_count(), 2);
+        assert_eq!(x.block_number(), 1);
         trx!(x, "aa bb" => "cc dd");
+        assert_eq!(x.last_pcb(), Some(0x03));
+        assert_eq!(x.block_number(), 0);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_send_parameters() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "f0 a0 02 a1 00" => "f0 a0 04 a2 02 80 01",
+            "f0 a0 02 a1 00" => timeout,
+            "02 12 34" => "02 56 78",
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        let mut buf = [0u8; 16];
+        let n = x.send_parameters(&hex!("a0 02 a1 00"), &mut buf).await.unwrap();
+        assert_eq!(&buf[..n.unwrap()], hex!("a0 04 a2 02 80 01"));
+
+        // Unsupported: no answer, the session goes on.
+        assert_eq!(x.send_parameters(&hex!("a0 02 a1 00"), &mut buf).await, Ok(None));
+        trx!(x, "12 34" => "56 78");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_card_sends_parameters() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "02 12 34" => "f0 a0 00",
+            "b2" => "02 56 78",
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        trx!(x, "12 34" => "56 78");
     }
 
     // B.2.4 Chaining - Scenario 4 PCD uses chaining
@@ -455,6 +745,37 @@ mod test {
         trx!(x, "aa bb" => "cc dd");
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_picc_chaining_exact_buffer() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "02 12 34" => "12 00 11 22",
+            "a3" => "13 33 44",
+            // rx is full, the rest doesn't go in it.
+            "a2" => "f2 01",
+            "f2 01" => "02",
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        let mut buf = [0u8; 5];
+        let n = x.transceive(&hex!("12 34"), &mut buf).await.unwrap();
+        assert_eq!(&buf[..n], hex!("00 11 22 33 44"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_picc_chaining_rx_too_small() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "02 12 34" => "12 00 11 22",
+            "a3" => "13 33 44",
+            // rx is full, the next I-block only has the scratch buffer.
+            "a2" => "02 55 66",
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        let mut buf = [0u8; 5];
+        let res = x.transceive(&hex!("12 34"), &mut buf).await;
+        assert_eq!(res, Err(Error::RxFrameTooBig));
+    }
+
     // B.3 Error handling B.3.1 Exchange of I-blocks - Scenario 6 Start of protocol
     #[test_log::test(tokio::test)]
     async fn test_error_iblock_start() {
@@ -487,6 +808,7 @@ mod test {
         trx!(x, "11 22" => "33 44");
         trx!(x, "55 66" => "77 88");
         trx!(x, "99 aa" => "bb cc");
+        assert_eq!(x.card.rx_resets, 1);
     }
 
     // B.3 Error handling B.3.1 Exchange of I-blocks - Scenario 8
@@ -666,6 +988,24 @@ mod test {
         trx!(x, "aa bb" => "cc dd");
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_presence_check() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "b2" => "a3",
+            "02 12 34" => "02 56 78",
+            "b3" => "a2",
+            "03 aa bb" => "03 cc dd",
+            "b2" => timeout,
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        assert_eq!(x.presence_check().await, Ok(true));
+        trx!(x, "12 34" => "56 78");
+        assert_eq!(x.presence_check().await, Ok(true));
+        trx!(x, "aa bb" => "cc dd");
+        assert_eq!(x.presence_check().await, Ok(false));
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_error_retries() {
         let mock = mock!(
@@ -694,4 +1034,74 @@ mod test {
         x.fsc = 10;
         trx!(x, "12 34" => Error::Communication);
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_max_retransmissions() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "02 12 34" => timeout,
+            "b2" => timeout,
+            "b2" => timeout,
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        x.set_max_retransmissions(2);
+        trx!(x, "12 34" => Error::Communication);
+        assert_eq!(x.inner().pos, 4);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_tag_lost() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "02 12 34" => timeout,
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        x.inner_mut().in_field = Some(false);
+        trx!(x, "12 34" => Error::TagLost);
+        assert_eq!(x.inner().pos, 2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_max_fwt() {
+        let mock = mock!(
+            "e0 80" => "06 77 77 e1 02 80",
+            "02 12 34" => timeout,
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        assert_eq!(x.fwt_1fc(), 4096 << 14);
+        x.set_max_fwt(Some(13_560_000));
+        assert_eq!(x.fwt_1fc(), 13_560_000);
+        trx!(x, "12 34" => Error::Timeout);
+        assert_eq!(x.inner().pos, 2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_block_number_desync() {
+        // The card repeats its previous response, then the R(NAK) brings it back in sync.
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "02 12 34" => "02 56 78",
+            "03 aa bb" => "02 56 78",
+            "b3" => "a2",
+            "03 aa bb" => "03 cc dd",
+            "02 12 34" => "03 cc dd",
+            "b2" => "03 cc dd",
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        trx!(x, "12 34" => "56 78");
+        trx!(x, "aa bb" => "cc dd");
+        // Still repeating after the R(NAK).
+        trx!(x, "12 34" => Error::BlockNumberDesync);
+
+        let mock = mock!(
+            "e0 80" => "06 77 77 81 02 80",
+            "02 12 34" => "a3",
+            "02 12 34" => "a3",
+            "02 12 34" => "a3",
+        );
+        let x = &mut IsoDepA::new(mock).await.unwrap();
+        x.set_max_retransmissions(2);
+        trx!(x, "12 34" => Error::BlockNumberDesync);
+        assert_eq!(x.inner().pos, 4);
+    }
 }
@@ -6,5 +6,24 @@ mod fmt;
 
 pub use rnfc_traits as traits;
 
+#[cfg(feature = "emulation")]
+pub mod emulation;
+#[cfg(feature = "iso14443a")]
 pub mod iso14443a;
+#[cfg(feature = "iso14443b")]
+pub mod iso14443b;
+#[cfg(feature = "iso15693")]
+pub mod iso15693;
+#[cfg(feature = "iso-dep")]
+pub mod iso7816;
+#[cfg(feature = "iso-dep")]
 pub mod iso_dep;
+#[cfg(feature = "iso14443a")]
+pub mod poll;
+#[cfg(feature = "iso14443a")]
+pub mod type1;
+#[cfg(feature = "felica")]
+pub mod type3;
+
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,176 @@
+//! Poll for a single NFC-A tag and activate it in one call, see [`Poller::poll_single`].
+
+use core::ops::BitOr;
+
+use rnfc_traits::iso14443a_ll::Reader as LLReader;
+
+use crate::iso14443a::{self, Card, CardType, PollGuard, Poller};
+#[cfg(feature = "iso-dep")]
+use crate::iso_dep::{self, IsoDepA};
+
+/// Tag types accepted by [`Poller::poll_single`]. Combine them with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Technologies(pub u8);
+
+impl Technologies {
+    /// ISO-DEP cards, such as Type 4 tags. Only with the `iso-dep` feature.
+    pub const ISO_DEP: Self = Self(0x01);
+    /// Type 2 tags, such as MIFARE Ultralight or NTAG.
+    pub const TYPE2: Self = Self(0x02);
+    pub const MIFARE_CLASSIC: Self = Self(0x04);
+    pub const ALL: Self = Self(0x07);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Technologies {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A selected tag, activated as far as its type needs to be used.
+///
+/// The ISO-DEP variant holds the session's frame buffer, see `IsoDepA`.
+#[allow(clippy::large_enum_variant)]
+pub enum Tag<'d, T: LLReader> {
+    /// ISO-DEP card, after RATS.
+    #[cfg(feature = "iso-dep")]
+    IsoDep(IsoDepA<Card<'d, T>>),
+    Type2(Card<'d, T>),
+    MifareClassic(Card<'d, T>),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    Iso14443a(iso14443a::Error<E>),
+    /// ISO-DEP activation failed.
+    #[cfg(feature = "iso-dep")]
+    IsoDep(iso_dep::Error<E>),
+}
+
+impl<T: LLReader> Poller<T>
+where
+    T::Error: crate::fmt::Format,
+{
+    /// Poll until a tag of one of `techs` is selected, and return it ready to use.
+    ///
+    /// This waits for as long as it takes, wrap it in a timeout to give up. Soft errors (no
+    /// card, protocol errors) are retried, other errors are returned.
+    ///
+    /// Tags of other types are halted so they stay quiet while polling continues. Polling uses
+    /// REQA, so halted tags (including ones halted before, by [`Poller::enumerate`]) aren't
+    /// found until the field is cycled.
+    ///
+    /// This is cancellation-safe, like [`Poller::poll_until`]: if this future is dropped while
+    /// polling, the reader turns the field off. Once an ISO-DEP card is selected, dropping it
+    /// during RATS leaves the field on, the card is then dropped along with the future.
+    pub async fn poll_single(&mut self, techs: Technologies) -> Result<Tag<'_, T>, Error<T::Error>> {
+        let mut guard = PollGuard::new(self);
+        loop {
+            let this = guard.poller();
+            let (uid, atqa, sak) = match this.activate(false).await {
+                Ok(x) => x,
+                Err(e) if e.is_soft() => continue,
+                Err(e) => {
+                    guard.disarm();
+                    return Err(Error::Iso14443a(e));
+                }
+            };
+
+            let card_type = this.classify(atqa, sak);
+            match card_type {
+                #[cfg(feature = "iso-dep")]
+                CardType::IsoDep | CardType::IsoDepAndNfcDep if techs.contains(Technologies::ISO_DEP) => {
+                    let card = guard.disarm().card(uid, atqa, sak);
+                    return IsoDepA::new(card).await.map(Tag::IsoDep).map_err(Error::IsoDep);
+                }
+                CardType::Type2 if techs.contains(Technologies::TYPE2) => {
+                    return Ok(Tag::Type2(guard.disarm().card(uid, atqa, sak)));
+                }
+                CardType::MifareClassic if techs.contains(Technologies::MIFARE_CLASSIC) => {
+                    return Ok(Tag::MifareClassic(guard.disarm().card(uid, atqa, sak)));
+                }
+                _ => {
+                    debug!("poll: skipping {:?} card", card_type);
+                    let _ = this.transceive_hlta().await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::poll_once;
+    use hex_literal::hex;
+    use rnfc_traits::iso14443a::Reader as _;
+    use rnfc_traits::iso14443a_ll::{Crc, Frame};
+
+    use super::*;
+    use crate::test_util::{mock, StuckReader};
+
+    const STANDARD: Frame = Frame::Standard {
+        timeout_1fc: 65536,
+        crc: Crc::A,
+    };
+
+    #[test]
+    fn test_technologies() {
+        let techs = Technologies::TYPE2 | Technologies::MIFARE_CLASSIC;
+        assert!(techs.contains(Technologies::TYPE2));
+        assert!(!techs.contains(Technologies::ISO_DEP));
+        assert!(Technologies::ALL.contains(techs));
+    }
+
+    #[test]
+    fn test_poll_single_dropped() {
+        let mut reader = StuckReader::default();
+        let mut poller = iso14443a::Poller::new(&mut reader);
+        assert!(poll_once(poller.poll_single(Technologies::ALL)).is_pending());
+        assert!(reader.field_off);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_poll_single_skips_other_types() {
+        let mock = mock!(
+            (Frame::ReqA, "" => timeout),
+            (Frame::ReqA, "" => timeout),
+            (Frame::ReqA, "" => timeout),
+            (Frame::ReqA, "" => timeout),
+            // MIFARE Classic, halted
+            (Frame::ReqA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (STANDARD, "93 70 01 02 03 04 04" => "08" / 8),
+            (STANDARD, "50 00" => timeout),
+            // Type 2
+            (Frame::ReqA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 11 12 13 14 04" / 56),
+            (STANDARD, "93 70 11 12 13 14 04" => "00" / 8),
+        );
+        let mut poller = iso14443a::Poller::new(mock);
+        match poller.poll_single(Technologies::TYPE2).await.unwrap() {
+            Tag::Type2(card) => assert_eq!(card.uid(), hex!("11 12 13 14")),
+            _ => panic!("expected a Type 2 tag"),
+        }
+    }
+
+    #[cfg(feature = "iso-dep")]
+    #[test_log::test(tokio::test)]
+    async fn test_poll_single_iso_dep() {
+        let mock = mock!(
+            (Frame::ReqA, "" => "04 00" / 16),
+            (Frame::Anticoll { bits: 16 }, "93 20 00 00 00 00" => "93 20 01 02 03 04 04" / 56),
+            (STANDARD, "93 70 01 02 03 04 04" => "20" / 8),
+            (STANDARD, "e0 80" => "06 77 77 81 02 80" / 48),
+        );
+        let mut poller = iso14443a::Poller::new(mock);
+        assert!(matches!(poller.poll_single(Technologies::ALL).await, Ok(Tag::IsoDep(_))));
+    }
+}
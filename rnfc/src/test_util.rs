@@ -0,0 +1,173 @@
+//! Mock reader shared by the tests.
+//!
+//! Build one with [`mock!`], listing the expected exchanges in order. Dropping the mock
+//! checks all of them happened.
+
+use std::vec::Vec;
+
+use rnfc_traits::iso14443a_ll::ErrorKind;
+
+/// Expected frame and tx data, and the response data and length.
+///
+/// The frame is an [`iso14443a_ll::Frame`](rnfc_traits::iso14443a_ll::Frame) for the low
+/// level reader, with the length in bits. It's `()` for the byte-oriented readers, with the
+/// length in bytes.
+pub type Exchange<F> = (F, &'static [u8], Result<(&'static [u8], usize), ErrorKind>);
+
+pub struct MockReader<F = ()> {
+    pub expected: Vec<Exchange<F>>,
+    pub pos: usize,
+    /// Total time waited with `delay_1fc`.
+    pub delayed_1fc: u32,
+    /// Whether `field_off` was called.
+    pub field_off: bool,
+}
+
+impl<F> MockReader<F> {
+    pub fn new(expected: Vec<Exchange<F>>) -> Self {
+        Self {
+            expected,
+            pos: 0,
+            delayed_1fc: 0,
+            field_off: false,
+        }
+    }
+
+    /// All expected exchanges happened.
+    pub fn done(&self) -> bool {
+        self.pos == self.expected.len()
+    }
+}
+
+impl<F: core::fmt::Debug + PartialEq + Copy> MockReader<F> {
+    fn exchange(&mut self, opts: F, tx: &[u8], rx: &mut [u8]) -> Result<usize, ErrorKind> {
+        if self.pos >= self.expected.len() {
+            panic!("unexpected transceive!\n         got: {:?} {:02x?}", opts, tx);
+        }
+
+        let (expected_opts, expected_tx, expected_rx) = self.expected[self.pos];
+        if opts != expected_opts || tx != expected_tx {
+            panic!(
+                "unexpected tx!\n    expected: {:?} {:02x?}\n         got: {:?} {:02x?}",
+                expected_opts, expected_tx, opts, tx
+            );
+        }
+
+        self.pos += 1;
+        let (expected_rx, len) = expected_rx?;
+        rx[..expected_rx.len()].copy_from_slice(expected_rx);
+        Ok(len)
+    }
+}
+
+impl<F> Drop for MockReader<F> {
+    fn drop(&mut self) {
+        // Don't hide the original failure behind a double panic.
+        if !std::thread::panicking() {
+            assert!(
+                self.done(),
+                "only {} of {} expected exchanges happened",
+                self.pos,
+                self.expected.len()
+            );
+        }
+    }
+}
+
+/// Build a [`MockReader`] from `(frame, "tx" => "rx" / bits)` entries for the low level reader,
+/// or `("tx" => "rx")` entries for the byte-oriented ones. `=> timeout` fails the exchange.
+macro_rules! mock {
+    (@entry $tx:literal => timeout) => {
+        ((), &hex_literal::hex!($tx)[..], Err(rnfc_traits::iso14443a_ll::ErrorKind::Timeout))
+    };
+    (@entry $tx:literal => $rx:literal) => {
+        ((), &hex_literal::hex!($tx)[..], Ok((&hex_literal::hex!($rx)[..], hex_literal::hex!($rx).len())))
+    };
+    (@entry $frame:expr, $tx:literal => timeout) => {
+        ($frame, &hex_literal::hex!($tx)[..], Err(rnfc_traits::iso14443a_ll::ErrorKind::Timeout))
+    };
+    (@entry $frame:expr, $tx:literal => $rx:literal / $bits:literal) => {
+        ($frame, &hex_literal::hex!($tx)[..], Ok((&hex_literal::hex!($rx)[..], $bits)))
+    };
+    ($(($($entry:tt)*)),* $(,)?) => {
+        $crate::test_util::MockReader::new(vec![$(mock!(@entry $($entry)*),)*])
+    };
+}
+pub(crate) use mock;
+
+impl rnfc_traits::iso14443a_ll::Reader for MockReader<rnfc_traits::iso14443a_ll::Frame> {
+    type Error = ErrorKind;
+
+    async fn transceive(
+        &mut self,
+        tx: &[u8],
+        rx: &mut [u8],
+        opts: rnfc_traits::iso14443a_ll::Frame,
+    ) -> Result<usize, Self::Error> {
+        self.exchange(opts, tx, rx)
+    }
+
+    async fn delay_1fc(&mut self, time_1fc: u32) {
+        self.delayed_1fc += time_1fc;
+    }
+
+    fn field_off(&mut self) {
+        self.field_off = true;
+    }
+}
+
+/// Low level reader whose transceives never complete, to drop a poll mid-transceive.
+#[derive(Default)]
+pub struct StuckReader {
+    /// Whether `field_off` was called.
+    pub field_off: bool,
+}
+
+impl rnfc_traits::iso14443a_ll::Reader for StuckReader {
+    type Error = ErrorKind;
+
+    async fn transceive(
+        &mut self,
+        _tx: &[u8],
+        _rx: &mut [u8],
+        _opts: rnfc_traits::iso14443a_ll::Frame,
+    ) -> Result<usize, Self::Error> {
+        core::future::pending().await
+    }
+
+    fn field_off(&mut self) {
+        self.field_off = true;
+    }
+}
+
+#[cfg(feature = "iso15693")]
+impl rnfc_traits::iso15693::Reader for MockReader {
+    type Error = ErrorKind;
+
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], _timeout_1fc: u32) -> Result<usize, Self::Error> {
+        self.exchange((), tx, rx)
+    }
+
+    /// Expected as an exchange with an empty tx.
+    async fn send_eof(&mut self, rx: &mut [u8], _timeout_1fc: u32) -> Result<usize, Self::Error> {
+        self.exchange((), &[], rx)
+    }
+}
+
+#[cfg(feature = "felica")]
+impl rnfc_traits::felica::Reader for MockReader {
+    type Error = ErrorKind;
+
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8], _timeout_1fc: u32) -> Result<usize, Self::Error> {
+        self.exchange((), tx, rx)
+    }
+}
+
+#[cfg(feature = "iso-dep")]
+impl rnfc_traits::iso_dep::Reader for MockReader {
+    type Error = ErrorKind;
+
+    async fn transceive(&mut self, tx: &[u8], rx: &mut [u8]) -> Result<usize, Self::Error> {
+        self.exchange((), tx, rx)
+    }
+}
@@ -0,0 +1,260 @@
+//! NFC Forum Type 1 Tag (Topaz/Jewel).
+//!
+//! Type 1 tags are woken up with WUPA like other Type A cards, but don't support anticollision.
+//! Instead they're addressed by their 4-byte UID, obtained with the RID command. Frames use
+//! CRC_B, which is computed here, and are sent as [`Frame::Type1`] frames for the reader's T1T framing.
+//!
+//! Only static memory tags (up to 120 bytes, such as Topaz 512/Jewel) are supported.
+
+use rnfc_traits::iso14443a_ll::{Crc, Frame, Reader as LLReader};
+
+use crate::fmt::Bytes;
+use crate::iso14443a::Atqa;
+
+/// Memory size returned by RALL, not including the header ROM bytes.
+pub const MEMORY_LEN: usize = 120;
+
+const CMD_RALL: u8 = 0x00;
+const CMD_READ: u8 = 0x01;
+const CMD_WRITE_E: u8 = 0x53;
+const CMD_RID: u8 = 0x78;
+
+const TIMEOUT_1FC: u32 = 65536;
+/// Erase + write takes ~5ms.
+const TIMEOUT_WRITE_1FC: u32 = 13560 * 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    Lower(E),
+    Protocol,
+}
+
+/// Address of a byte in static memory.
+pub fn addr(block: u8, byte: u8) -> u8 {
+    (block << 3) | (byte & 0x07)
+}
+
+pub struct Type1Tag<T: LLReader> {
+    reader: T,
+    hr: [u8; 2],
+    uid: [u8; 4],
+}
+
+impl<T: LLReader> Type1Tag<T>
+where
+    T::Error: crate::fmt::Format,
+{
+    /// Wake up a Type 1 tag and read its header ROM and UID.
+    pub async fn new(mut reader: T) -> Result<Self, Error<T::Error>> {
+        let mut rx = [0; 2];
+        let bits = reader.transceive(&[], &mut rx, Frame::WupA).await.map_err(Error::Lower)?;
+        if bits != 16 {
+            debug!("WUPA response wrong length: {} bits", bits);
+            return Err(Error::Protocol);
+        }
+        // Type 1 tags don't do bit frame anticollision, and have the platform
+        // configuration (proprietary bits) set to 0b1100.
+        let atqa = Atqa(rx);
+        if atqa.bit_frame_anticollision() != 0 || atqa.proprietary() != 0x0C {
+            debug!("ATQA {} is not a Type 1 tag", Bytes(&rx));
+            return Err(Error::Protocol);
+        }
+
+        let mut this = Self {
+            reader,
+            hr: [0; 2],
+            uid: [0; 4],
+        };
+        this.rid().await?;
+        Ok(this)
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.reader
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.reader
+    }
+
+    /// Header ROM bytes HR0, HR1.
+    pub fn hr(&self) -> [u8; 2] {
+        self.hr
+    }
+
+    pub fn uid(&self) -> [u8; 4] {
+        self.uid
+    }
+
+    /// Read the header ROM and UID (RID).
+    pub async fn rid(&mut self) -> Result<([u8; 2], [u8; 4]), Error<T::Error>> {
+        let mut rx = [0; 6];
+        self.command(CMD_RID, 0, 0, false, &mut rx).await?;
+        self.hr.copy_from_slice(&rx[..2]);
+        self.uid.copy_from_slice(&rx[2..]);
+        debug!("Type 1 tag: hr={} uid={}", Bytes(&self.hr), Bytes(&self.uid));
+        Ok((self.hr, self.uid))
+    }
+
+    /// Read the whole static memory (RALL).
+    pub async fn read_all(&mut self, out: &mut [u8; MEMORY_LEN]) -> Result<(), Error<T::Error>> {
+        let mut rx = [0; 2 + MEMORY_LEN];
+        self.command(CMD_RALL, 0, 0, true, &mut rx).await?;
+        if rx[..2] != self.hr {
+            debug!("RALL: header ROM mismatch");
+            return Err(Error::Protocol);
+        }
+        out.copy_from_slice(&rx[2..]);
+        Ok(())
+    }
+
+    /// Read a single byte (READ).
+    pub async fn read(&mut self, addr: u8) -> Result<u8, Error<T::Error>> {
+        let mut rx = [0; 2];
+        self.command(CMD_READ, addr, 0, true, &mut rx).await?;
+        if rx[0] != addr {
+            debug!("READ: address mismatch");
+            return Err(Error::Protocol);
+        }
+        Ok(rx[1])
+    }
+
+    /// Erase and write a single byte (WRITE-E).
+    pub async fn write(&mut self, addr: u8, data: u8) -> Result<(), Error<T::Error>> {
+        let mut rx = [0; 2];
+        self.command(CMD_WRITE_E, addr, data, true, &mut rx).await?;
+        if rx != [addr, data] {
+            debug!("WRITE-E: got {}, expected {:02x} {:02x}", Bytes(&rx), addr, data);
+            return Err(Error::Protocol);
+        }
+        Ok(())
+    }
+
+    /// Send a command and receive a fixed-length response.
+    ///
+    /// `with_uid` is false only for RID, which is sent before we know the UID.
+    async fn command(&mut self, cmd: u8, addr: u8, data: u8, with_uid: bool, rx: &mut [u8]) -> Result<(), Error<T::Error>> {
+        let mut tx = [0; 9];
+        tx[0] = cmd;
+        tx[1] = addr;
+        tx[2] = data;
+        if with_uid {
+            tx[3..7].copy_from_slice(&self.uid);
+        }
+        let crc = Crc::B.compute(&tx[..7]).unwrap();
+        tx[7..].copy_from_slice(&crc);
+
+        let timeout_1fc = match cmd {
+            CMD_WRITE_E => TIMEOUT_WRITE_1FC,
+            _ => TIMEOUT_1FC,
+        };
+
+        let mut buf = [0; 2 + MEMORY_LEN + 2];
+        let bits = self
+            .reader
+            .transceive(&tx, &mut buf, Frame::Type1 { timeout_1fc })
+            .await
+            .map_err(Error::Lower)?;
+
+        let n = rx.len();
+        if bits != (n + 2) * 8 {
+            debug!("cmd {:02x}: response wrong length: {} bits", cmd, bits);
+            return Err(Error::Protocol);
+        }
+        if Crc::B.compute(&buf[..n]).unwrap() != buf[n..][..2] {
+            debug!("cmd {:02x}: bad CRC", cmd);
+            return Err(Error::Protocol);
+        }
+        rx.copy_from_slice(&buf[..n]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::test_util::mock;
+
+    const RAW: Frame = Frame::Type1 {
+        timeout_1fc: TIMEOUT_1FC,
+    };
+    const RAW_WRITE: Frame = Frame::Type1 {
+        timeout_1fc: TIMEOUT_WRITE_1FC,
+    };
+
+    macro_rules! wakeup {
+        () => {
+            [
+                mock!(@entry Frame::WupA, "" => "00 0c" / 16),
+                mock!(@entry RAW, "78 00 00 00 00 00 00 d0 43" => "11 48 01 02 03 04 59 ef" / 64),
+            ]
+        };
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_rid() {
+        let mut mock = mock!();
+        mock.expected.extend(wakeup!());
+        let tag = Type1Tag::new(mock).await.unwrap();
+        assert_eq!(tag.hr(), hex!("11 48"));
+        assert_eq!(tag.uid(), hex!("01 02 03 04"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_not_type1() {
+        let mock = mock!(
+            (Frame::WupA, "" => "04 00" / 16),
+        );
+        assert_eq!(Type1Tag::new(mock).await.err(), Some(Error::Protocol));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_all() {
+        let mut mock = mock!();
+        mock.expected.extend(wakeup!());
+        mock.expected.push(mock!(@entry RAW, "00 00 00 01 02 03 04 3f 49" => "
+            11 48 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10 11 12 13 14 15 16 17 18 19 1a 1b 1c 1d
+            1e 1f 20 21 22 23 24 25 26 27 28 29 2a 2b 2c 2d 2e 2f 30 31 32 33 34 35 36 37 38 39 3a 3b 3c 3d
+            3e 3f 40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f 50 51 52 53 54 55 56 57 58 59 5a 5b 5c 5d
+            5e 5f 60 61 62 63 64 65 66 67 68 69 6a 6b 6c 6d 6e 6f 70 71 72 73 74 75 76 77 c4 a4" / 992));
+        let mut tag = Type1Tag::new(mock).await.unwrap();
+        let mut out = [0; MEMORY_LEN];
+        tag.read_all(&mut out).await.unwrap();
+        for (i, b) in out.iter().enumerate() {
+            assert_eq!(*b as usize, i);
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_read() {
+        let mut mock = mock!();
+        mock.expected.extend(wakeup!());
+        mock.expected
+            .push(mock!(@entry RAW, "01 08 00 01 02 03 04 b2 f7" => "08 aa d7 cb" / 32));
+        let mut tag = Type1Tag::new(mock).await.unwrap();
+        assert_eq!(tag.read(addr(1, 0)).await, Ok(0xaa));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_write() {
+        let mut mock = mock!();
+        mock.expected.extend(wakeup!());
+        mock.expected
+            .push(mock!(@entry RAW_WRITE, "53 09 5a 01 02 03 04 27 8f" => "09 5a 80 25" / 32));
+        let mut tag = Type1Tag::new(mock).await.unwrap();
+        assert_eq!(tag.write(addr(1, 1), 0x5a).await, Ok(()));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_write_bad_crc() {
+        let mut mock = mock!();
+        mock.expected.extend(wakeup!());
+        mock.expected
+            .push(mock!(@entry RAW_WRITE, "53 09 5a 01 02 03 04 27 8f" => "09 5a 80 26" / 32));
+        let mut tag = Type1Tag::new(mock).await.unwrap();
+        assert_eq!(tag.write(addr(1, 1), 0x5a).await, Err(Error::Protocol));
+    }
+}
@@ -0,0 +1,377 @@
+//! NFC Forum Type 3 Tag (FeliCa-based NDEF).
+//!
+//! The NDEF message is stored in the NDEF service, starting at block 1. Block 0 is the
+//! Attribute Information Block (AIB), which holds the NDEF length and the tag's limits.
+
+use rnfc_traits::felica::Reader;
+
+use crate::fmt::Bytes;
+
+pub const IDM_LEN: usize = 8;
+pub const BLOCK_SIZE: usize = 16;
+
+/// Max blocks we read or write in a single Check/Update command, so frames fit in 255 bytes.
+const MAX_BLOCKS_PER_CMD: usize = 12;
+const FRAME_MAX_LEN: usize = 255;
+
+const SYSTEM_CODE_NDEF: u16 = 0x12FC;
+const SERVICE_NDEF_READ: u16 = 0x000B;
+const SERVICE_NDEF_WRITE: u16 = 0x0009;
+
+const CMD_POLLING: u8 = 0x00;
+const CMD_CHECK: u8 = 0x06;
+const CMD_UPDATE: u8 = 0x08;
+
+const WRITE_FLAG_OFF: u8 = 0x00;
+const WRITE_FLAG_ON: u8 = 0x0F;
+
+/// Timeout for a response.
+///
+/// The real max response time depends on the command and is given in PMm, this is
+/// enough for any sane tag.
+const TIMEOUT_1FC: u32 = 13560 * 50;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    Lower(E),
+    /// The tag responded with a non-zero status. Contains status flags 1 and 2.
+    Status(u8, u8),
+    Protocol,
+    /// The NDEF message doesn't fit in the tag, or in the output buffer.
+    BufferTooSmall,
+}
+
+/// Attribute Information Block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AttributeInfo {
+    pub version: u8,
+    /// Max number of blocks that can be read with a single Check command.
+    pub nbr: u8,
+    /// Max number of blocks that can be written with a single Update command.
+    pub nbw: u8,
+    /// Number of blocks available for the NDEF message.
+    pub nmaxb: u16,
+    /// `true` if a write is in progress (or was interrupted), in which case the NDEF message may be corrupted.
+    pub writing: bool,
+    pub writable: bool,
+    /// Length of the NDEF message, in bytes.
+    pub ln: u32,
+}
+
+impl AttributeInfo {
+    fn parse(block: &[u8]) -> Option<Self> {
+        let checksum = block[..14].iter().map(|&b| b as u16).sum::<u16>();
+        if checksum != u16::from_be_bytes([block[14], block[15]]) {
+            debug!("AIB: bad checksum");
+            return None;
+        }
+        Some(Self {
+            version: block[0],
+            nbr: block[1],
+            nbw: block[2],
+            nmaxb: u16::from_be_bytes([block[3], block[4]]),
+            writing: block[9] != WRITE_FLAG_OFF,
+            writable: block[10] != 0,
+            ln: u32::from_be_bytes([0, block[11], block[12], block[13]]),
+        })
+    }
+
+    fn to_block(self) -> [u8; BLOCK_SIZE] {
+        let mut block = [0; BLOCK_SIZE];
+        block[0] = self.version;
+        block[1] = self.nbr;
+        block[2] = self.nbw;
+        block[3..5].copy_from_slice(&self.nmaxb.to_be_bytes());
+        block[9] = if self.writing { WRITE_FLAG_ON } else { WRITE_FLAG_OFF };
+        block[10] = self.writable as u8;
+        block[11..14].copy_from_slice(&self.ln.to_be_bytes()[1..]);
+        let checksum = block[..14].iter().map(|&b| b as u16).sum::<u16>();
+        block[14..].copy_from_slice(&checksum.to_be_bytes());
+        block
+    }
+}
+
+/// Block numbers for `n` NDEF data blocks starting at data block `first`.
+/// Data blocks start after the AIB, so data block 0 is block 1.
+fn ndef_blocks(first: usize, n: usize) -> [u16; MAX_BLOCKS_PER_CMD] {
+    let mut blocks = [0; MAX_BLOCKS_PER_CMD];
+    for (i, b) in blocks[..n].iter_mut().enumerate() {
+        *b = (1 + first + i) as u16;
+    }
+    blocks
+}
+
+pub struct Type3Tag<T: Reader> {
+    reader: T,
+    idm: [u8; IDM_LEN],
+}
+
+impl<T: Reader> Type3Tag<T>
+where
+    T::Error: crate::fmt::Format,
+{
+    /// Poll for a tag exposing the NDEF system code.
+    pub async fn new(mut reader: T) -> Result<Self, Error<T::Error>> {
+        let sc = SYSTEM_CODE_NDEF.to_be_bytes();
+        // Request code 0 (no extra data), time slot 0 (single slot).
+        let tx = [6, CMD_POLLING, sc[0], sc[1], 0x00, 0x00];
+        let mut rx = [0; 20];
+        let n = reader.transceive(&tx, &mut rx, TIMEOUT_1FC).await.map_err(Error::Lower)?;
+        let res = &rx[..n];
+        if n < 2 + IDM_LEN * 2 || res[0] as usize != n || res[1] != CMD_POLLING + 1 {
+            debug!("polling: bad response {}", Bytes(res));
+            return Err(Error::Protocol);
+        }
+
+        let mut idm = [0; IDM_LEN];
+        idm.copy_from_slice(&res[2..][..IDM_LEN]);
+        debug!("Type 3 tag: idm={}", Bytes(&idm));
+        Ok(Self { reader, idm })
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.reader
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.reader
+    }
+
+    pub fn idm(&self) -> [u8; IDM_LEN] {
+        self.idm
+    }
+
+    /// Read blocks from the NDEF service (Check). `out` must be `BLOCK_SIZE * blocks.len()` bytes.
+    pub async fn check(&mut self, blocks: &[u16], out: &mut [u8]) -> Result<(), Error<T::Error>> {
+        if blocks.len() > MAX_BLOCKS_PER_CMD || out.len() != blocks.len() * BLOCK_SIZE {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut rx = [0; FRAME_MAX_LEN];
+        let res = self.command(CMD_CHECK, SERVICE_NDEF_READ, blocks, &[], &mut rx).await?;
+        if res.len() != 1 + out.len() || res[0] as usize != blocks.len() {
+            debug!("check: bad response {}", Bytes(res));
+            return Err(Error::Protocol);
+        }
+        out.copy_from_slice(&res[1..]);
+        Ok(())
+    }
+
+    /// Write blocks to the NDEF service (Update). `data` must be `BLOCK_SIZE * blocks.len()` bytes.
+    pub async fn update(&mut self, blocks: &[u16], data: &[u8]) -> Result<(), Error<T::Error>> {
+        if blocks.len() > MAX_BLOCKS_PER_CMD || data.len() != blocks.len() * BLOCK_SIZE {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut rx = [0; FRAME_MAX_LEN];
+        let res = self.command(CMD_UPDATE, SERVICE_NDEF_WRITE, blocks, data, &mut rx).await?;
+        if !res.is_empty() {
+            debug!("update: bad response {}", Bytes(res));
+            return Err(Error::Protocol);
+        }
+        Ok(())
+    }
+
+    /// Read the Attribute Information Block.
+    pub async fn read_attribute_info(&mut self) -> Result<AttributeInfo, Error<T::Error>> {
+        let mut block = [0; BLOCK_SIZE];
+        self.check(&[0], &mut block).await?;
+        AttributeInfo::parse(&block).ok_or(Error::Protocol)
+    }
+
+    /// Read the NDEF message into `out`. Returns its length.
+    pub async fn read_ndef(&mut self, out: &mut [u8]) -> Result<usize, Error<T::Error>> {
+        let aib = self.read_attribute_info().await?;
+        if aib.writing {
+            warn!("NDEF write flag is set, message may be corrupted");
+        }
+
+        let len = aib.ln as usize;
+        let block_count = len.div_ceil(BLOCK_SIZE);
+        if block_count > aib.nmaxb as usize {
+            debug!("NDEF length {} exceeds nmaxb {}", len, aib.nmaxb);
+            return Err(Error::Protocol);
+        }
+        if out.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let per_cmd = (aib.nbr as usize).clamp(1, MAX_BLOCKS_PER_CMD);
+        let mut buf = [0; MAX_BLOCKS_PER_CMD * BLOCK_SIZE];
+        let mut block = 0;
+        while block < block_count {
+            let n = per_cmd.min(block_count - block);
+            let blocks = ndef_blocks(block, n);
+            self.check(&blocks[..n], &mut buf[..n * BLOCK_SIZE]).await?;
+
+            let pos = block * BLOCK_SIZE;
+            let chunk_len = (n * BLOCK_SIZE).min(len - pos);
+            out[pos..][..chunk_len].copy_from_slice(&buf[..chunk_len]);
+            block += n;
+        }
+        Ok(len)
+    }
+
+    /// Write `msg` as the NDEF message.
+    ///
+    /// The AIB write flag is set during the write, so an interrupted write can be detected by readers.
+    pub async fn write_ndef(&mut self, msg: &[u8]) -> Result<(), Error<T::Error>> {
+        let mut aib = self.read_attribute_info().await?;
+        if !aib.writable {
+            debug!("tag is read-only");
+            return Err(Error::Protocol);
+        }
+        let block_count = msg.len().div_ceil(BLOCK_SIZE);
+        if block_count > aib.nmaxb as usize {
+            return Err(Error::BufferTooSmall);
+        }
+
+        aib.writing = true;
+        self.update(&[0], &aib.to_block()).await?;
+
+        let per_cmd = (aib.nbw as usize).clamp(1, MAX_BLOCKS_PER_CMD);
+        let mut buf = [0; MAX_BLOCKS_PER_CMD * BLOCK_SIZE];
+        let mut block = 0;
+        while block < block_count {
+            let n = per_cmd.min(block_count - block);
+            let blocks = ndef_blocks(block, n);
+
+            let pos = block * BLOCK_SIZE;
+            let data = &msg[pos..][..(n * BLOCK_SIZE).min(msg.len() - pos)];
+            buf[..n * BLOCK_SIZE].fill(0);
+            buf[..data.len()].copy_from_slice(data);
+            self.update(&blocks[..n], &buf[..n * BLOCK_SIZE]).await?;
+            block += n;
+        }
+
+        aib.writing = false;
+        aib.ln = msg.len() as u32;
+        self.update(&[0], &aib.to_block()).await
+    }
+
+    /// Send a Check or Update command on a single service. Returns the response after the status flags.
+    async fn command<'a>(
+        &mut self,
+        cmd: u8,
+        service: u16,
+        blocks: &[u16],
+        data: &[u8],
+        rx: &'a mut [u8],
+    ) -> Result<&'a [u8], Error<T::Error>> {
+        let mut tx = [0; FRAME_MAX_LEN];
+        tx[1] = cmd;
+        tx[2..][..IDM_LEN].copy_from_slice(&self.idm);
+        let mut n = 2 + IDM_LEN;
+        tx[n] = 1; // number of services
+        tx[n + 1..][..2].copy_from_slice(&service.to_le_bytes());
+        tx[n + 3] = blocks.len() as u8;
+        n += 4;
+        for &b in blocks {
+            // Block list element for service index 0, using the 2-byte form when possible.
+            if b < 256 {
+                tx[n] = 0x80;
+                tx[n + 1] = b as u8;
+                n += 2;
+            } else {
+                tx[n] = 0x00;
+                tx[n + 1..][..2].copy_from_slice(&b.to_le_bytes());
+                n += 3;
+            }
+        }
+        tx[n..][..data.len()].copy_from_slice(data);
+        n += data.len();
+        tx[0] = n as u8;
+
+        let len = match self.reader.transceive(&tx[..n], rx, TIMEOUT_1FC).await {
+            Ok(len) => len,
+            Err(e) => {
+                debug!("Trx cmd {:02x} failed: {:?}", cmd, e);
+                return Err(Error::Lower(e));
+            }
+        };
+        let res = &rx[..len];
+
+        let header = 2 + IDM_LEN + 2;
+        if len < header || res[0] as usize != len || res[1] != cmd + 1 || res[2..][..IDM_LEN] != self.idm {
+            debug!("cmd {:02x}: bad response {}", cmd, Bytes(res));
+            return Err(Error::Protocol);
+        }
+        let (status1, status2) = (res[2 + IDM_LEN], res[3 + IDM_LEN]);
+        if status1 != 0 {
+            debug!("cmd {:02x}: status {:02x} {:02x}", cmd, status1, status2);
+            return Err(Error::Status(status1, status2));
+        }
+        Ok(&res[header..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::test_util::mock;
+
+    #[test_log::test(tokio::test)]
+    async fn test_read_ndef() {
+        let mock = mock!(
+            ("06 00 12 fc 00 00" => "12 01 01 02 03 04 05 06 07 08 00 f0 00 00 02 06 03 00"),
+            ("10 06 01 02 03 04 05 06 07 08 01 0b 00 01 80 00"
+                => "1d 07 01 02 03 04 05 06 07 08 00 00 01 10 04 01 00 0d 00 00 00 00 00 01 00 00 14 00 37"),
+            ("12 06 01 02 03 04 05 06 07 08 01 0b 00 02 80 01 80 02"
+                => "2d 07 01 02 03 04 05 06 07 08 00 00 02
+                    d1 01 10 55 03 65 78 61 6d 70 6c 65 2e 63 6f 6d
+                    2f 61 62 63 00 00 00 00 00 00 00 00 00 00 00 00"),
+        );
+        let mut tag = Type3Tag::new(mock).await.unwrap();
+        assert_eq!(tag.idm(), hex!("01 02 03 04 05 06 07 08"));
+
+        let mut out = [0; 64];
+        let n = tag.read_ndef(&mut out).await.unwrap();
+        assert_eq!(&out[..n], hex!("d1 01 10 55 03 65 78 61 6d 70 6c 65 2e 63 6f 6d 2f 61 62 63"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_write_ndef() {
+        let mock = mock!(
+            ("06 00 12 fc 00 00" => "12 01 01 02 03 04 05 06 07 08 00 f0 00 00 02 06 03 00"),
+            ("10 06 01 02 03 04 05 06 07 08 01 0b 00 01 80 00"
+                => "1d 07 01 02 03 04 05 06 07 08 00 00 01 10 04 01 00 0d 00 00 00 00 00 01 00 00 00 00 23"),
+            ("20 08 01 02 03 04 05 06 07 08 01 09 00 01 80 00 10 04 01 00 0d 00 00 00 00 0f 01 00 00 00 00 32"
+                => "0c 09 01 02 03 04 05 06 07 08 00 00"),
+            ("20 08 01 02 03 04 05 06 07 08 01 09 00 01 80 01 d0 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00"
+                => "0c 09 01 02 03 04 05 06 07 08 00 00"),
+            ("20 08 01 02 03 04 05 06 07 08 01 09 00 01 80 00 10 04 01 00 0d 00 00 00 00 00 01 00 00 03 00 26"
+                => "0c 09 01 02 03 04 05 06 07 08 00 00"),
+        );
+        let mut tag = Type3Tag::new(mock).await.unwrap();
+        tag.write_ndef(&hex!("d0 00 00")).await.unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_status_error() {
+        let mock = mock!(
+            ("06 00 12 fc 00 00" => "12 01 01 02 03 04 05 06 07 08 00 f0 00 00 02 06 03 00"),
+            ("10 06 01 02 03 04 05 06 07 08 01 0b 00 01 80 00" => "0c 07 01 02 03 04 05 06 07 08 01 a6"),
+        );
+        let mut tag = Type3Tag::new(mock).await.unwrap();
+        assert_eq!(tag.read_attribute_info().await, Err(Error::Status(0x01, 0xa6)));
+    }
+
+    #[test]
+    fn test_aib_roundtrip() {
+        let block = hex!("10 04 01 00 0d 00 00 00 00 0f 01 00 00 14 00 46");
+        let aib = AttributeInfo::parse(&block).unwrap();
+        assert_eq!(aib.nbr, 4);
+        assert_eq!(aib.nmaxb, 13);
+        assert!(aib.writing);
+        assert_eq!(aib.ln, 20);
+        assert_eq!(aib.to_block(), block);
+
+        let mut bad = block;
+        bad[15] ^= 1;
+        assert_eq!(AttributeInfo::parse(&bad), None);
+    }
+}